@@ -1,4 +1,6 @@
-#![feature(test)]
+// The benchmarks need the unstable test crate, so without the nightly feature they’re skipped.
+#![cfg(feature = "nightly")]
+#![cfg_attr(feature = "nightly", feature(test))]
 
 extern crate anymap;
 
@@ -41,11 +43,33 @@ fn get_present(b: &mut Bencher) {
     })
 }
 
+#[bench]
+fn entry_or_insert(b: &mut Bencher) {
+    b.iter(|| {
+        let mut data = AnyMap::new();
+        for _ in 0..100 {
+            *data.entry::<i32>().or_insert(0) += 1;
+        }
+    })
+}
+
+#[cfg(feature = "hashbrown")]
+#[bench]
+fn entry_or_insert_hashbrown(b: &mut Bencher) {
+    b.iter(|| {
+        let mut data = anymap::hashbrown::AnyMap::new();
+        for _ in 0..100 {
+            *data.entry::<i32>().or_insert(0) += 1;
+        }
+    })
+}
+
 macro_rules! big_benchmarks {
     ($name:ident, $($T:ident)*) => (
         #[bench]
         fn $name(b: &mut Bencher) {
             $(
+                #[allow(dead_code)]
                 struct $T(&'static str);
             )*
 
@@ -0,0 +1,250 @@
+//! `AnyMapActor`, a map owned by its own thread and reached through a cheap handle.
+
+use core::any::Any;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+
+use crate::Map;
+
+/// The kind of map an [`AnyMapActor`] owns: its values must be `Send`, to get to its thread.
+pub type SendMap = Map<dyn Any + Send>;
+
+type Job = Box<dyn FnOnce(&mut SendMap) + Send>;
+
+/// A handle to a map owned by a thread of its own, which runs the operations sent to it one at a
+/// time, so that no locking is needed.
+///
+/// Each operation returns a [`Reply`], which is a `Future`, so async code can await it without
+/// blocking, whatever the runtime; or it can be waited for with [`Reply::wait`]. Handles are
+/// cheap to clone, and the thread stops once they have all been dropped.
+///
+/// ```rust
+/// let actor = anymap::AnyMapActor::spawn();
+/// actor.insert(1u32).wait();
+/// let other = actor.clone();
+/// other.with_mut(|count: Option<&mut u32>| *count.unwrap() += 1).wait();
+/// assert_eq!(actor.get_cloned::<u32>().wait(), Some(2));
+/// ```
+#[derive(Clone, Debug)]
+pub struct AnyMapActor {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl AnyMapActor {
+    /// Spawn a thread owning an empty map, and return a handle to it.
+    #[inline]
+    pub fn spawn() -> AnyMapActor {
+        AnyMapActor::with_map(Map::new())
+    }
+
+    /// Spawn a thread owning `map`, and return a handle to it.
+    pub fn with_map(mut map: SendMap) -> AnyMapActor {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let _ = std::thread::Builder::new()
+            .name("anymap actor".into())
+            .spawn(move || {
+                for job in receiver {
+                    job(&mut map);
+                }
+            })
+            .expect("failed to spawn the map’s thread");
+        AnyMapActor { jobs }
+    }
+
+    /// Run `f` on the map, on its thread, replying with what it returns.
+    pub fn run<R, F>(&self, f: F) -> Reply<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut SendMap) -> R + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(State { result: None, waker: None, done: false }));
+        let completer = Completer(state.clone());
+        // If the thread has stopped, the job is dropped, which completes the reply.
+        let _ = self.jobs.send(Box::new(move |map| completer.complete(f(map))));
+        Reply { state }
+    }
+
+    /// Run `f` on the value of type `T`, if there is one, replying with what it returns.
+    #[inline]
+    pub fn with_mut<T, R, F>(&self, f: F) -> Reply<R>
+    where
+        T: Any + Send,
+        R: Send + 'static,
+        F: FnOnce(Option<&mut T>) -> R + Send + 'static,
+    {
+        self.run(move |map| f(map.get_mut::<T>()))
+    }
+
+    /// Reply with a clone of the value of type `T`, if there is one.
+    #[inline]
+    pub fn get_cloned<T: Any + Send + Clone>(&self) -> Reply<Option<T>> {
+        self.run(|map| map.get::<T>().cloned())
+    }
+
+    /// Set the value of type `T`, replying with the previous value, if there was one.
+    #[inline]
+    pub fn insert<T: Any + Send>(&self, value: T) -> Reply<Option<T>> {
+        self.run(move |map| map.insert(value))
+    }
+
+    /// Remove the value of type `T`, replying with it, if there was one.
+    #[inline]
+    pub fn remove<T: Any + Send>(&self) -> Reply<Option<T>> {
+        self.run(|map| map.remove::<T>())
+    }
+}
+
+#[derive(Debug)]
+struct State<R> {
+    result: Option<R>,
+    waker: Option<Waker>,
+    done: bool,
+}
+
+fn lock<R>(state: &Mutex<State<R>>) -> MutexGuard<'_, State<R>> {
+    // Nothing panics while holding the lock.
+    state.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+/// The sending side of a [`Reply`]; if dropped without completing (because the job panicked or
+/// the thread had stopped), the reply is completed with no result.
+struct Completer<R>(Arc<Mutex<State<R>>>);
+
+impl<R> Completer<R> {
+    fn complete(self, result: R) {
+        lock(&self.0).result = Some(result);
+    }
+}
+
+impl<R> Drop for Completer<R> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut state = lock(&self.0);
+            state.done = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// The reply to an operation sent to an [`AnyMapActor`]: a `Future` of its result.
+///
+/// If the operation panicked, so that there is no result, the map’s thread has stopped, and
+/// awaiting or waiting for the reply panics too.
+#[derive(Debug)]
+pub struct Reply<R> {
+    state: Arc<Mutex<State<R>>>,
+}
+
+impl<R> Reply<R> {
+    /// Block the current thread until the operation has run, and return its result.
+    #[cfg_attr(feature = "track-caller", track_caller)]
+    pub fn wait(self) -> R {
+        let thread = std::thread::current();
+        loop {
+            {
+                let mut state = lock(&self.state);
+                if state.done {
+                    return take_result(&mut state);
+                }
+                state.waker = Some(waker_for(thread.clone()));
+            }
+            std::thread::park();
+        }
+    }
+}
+
+impl<R> Future for Reply<R> {
+    type Output = R;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<R> {
+        let mut state = lock(&self.state);
+        if state.done {
+            Poll::Ready(take_result(&mut state))
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg_attr(feature = "track-caller", track_caller)]
+fn take_result<R>(state: &mut State<R>) -> R {
+    state.result.take().expect("the map’s actor thread panicked, or the reply was already taken")
+}
+
+/// A waker which unparks the given thread.
+fn waker_for(thread: std::thread::Thread) -> Waker {
+    use core::task::{RawWaker, RawWakerVTable};
+
+    unsafe fn clone(data: *const ()) -> RawWaker {
+        let thread = &*(data as *const std::thread::Thread);
+        RawWaker::new(Box::into_raw(Box::new(thread.clone())) as *const (), &VTABLE)
+    }
+    unsafe fn wake(data: *const ()) {
+        Box::from_raw(data as *mut std::thread::Thread).unpark();
+    }
+    unsafe fn wake_by_ref(data: *const ()) {
+        (*(data as *const std::thread::Thread)).unpark();
+    }
+    unsafe fn drop(data: *const ()) {
+        core::mem::drop(Box::from_raw(data as *mut std::thread::Thread));
+    }
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+
+    let data = Box::into_raw(Box::new(thread)) as *const ();
+    // SAFETY: the vtable’s functions treat the data as the Box<Thread> it is.
+    unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)] struct A(i32);
+
+    #[test]
+    fn test_actor() {
+        let actor = AnyMapActor::spawn();
+        assert_eq!(actor.get_cloned::<A>().wait(), None);
+        assert_eq!(actor.insert(A(1)).wait(), None);
+        let threads: Vec<_> = (0..4).map(|_| {
+            let actor = actor.clone();
+            std::thread::spawn(move || actor.with_mut(|a: Option<&mut A>| a.unwrap().0 += 1).wait())
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(actor.remove::<A>().wait(), Some(A(5)));
+        assert_eq!(actor.run(|map| map.len()).wait(), 0);
+    }
+
+    #[test]
+    fn test_actor_future() {
+        // Poll by hand, with a waker for this thread, as an executor would.
+        let actor = AnyMapActor::spawn();
+        let mut reply = actor.insert(A(1));
+        let waker = waker_for(std::thread::current());
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match Pin::new(&mut reply).poll(&mut cx) {
+                Poll::Ready(old) => break assert_eq!(old, None),
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_actor_panic() {
+        let actor = AnyMapActor::spawn();
+        let reply = actor.run(|_| -> () { panic!("oops") });
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| reply.wait())).is_err());
+        // The thread has stopped, so later replies have no result either.
+        let reply = actor.get_cloned::<A>();
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| reply.wait())).is_err());
+    }
+}
@@ -2,11 +2,16 @@ use core::fmt;
 use core::any::{Any, TypeId};
 #[cfg(not(feature = "std"))]
 use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use std::panic::{RefUnwindSafe, UnwindSafe};
 
 #[doc(hidden)]
 pub trait CloneToAny {
     /// Clone `self` into a new `Box<dyn CloneAny>` object.
     fn clone_to_any(&self) -> Box<dyn CloneAny>;
+
+    /// Convert a `Box<dyn CloneAny>` into a `Box<dyn Any>`, for want of trait upcasting.
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
 }
 
 impl<T: Any + Clone> CloneToAny for T {
@@ -14,6 +19,11 @@ impl<T: Any + Clone> CloneToAny for T {
     fn clone_to_any(&self) -> Box<dyn CloneAny> {
         Box::new(self.clone())
     }
+
+    #[inline]
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
 }
 
 macro_rules! impl_clone {
@@ -32,9 +42,13 @@ macro_rules! impl_clone {
                 //
                 // ¹ https://github.com/rust-lang/rust/issues/51443#issuecomment-421988013
                 // ² https://github.com/rust-lang/rust/blob/e7825f2b690c9a0d21b6f6d84c404bb53b151b38/library/alloc/src/boxed.rs#L1613-L1616
+                //
+                // Since then, rustc has started rejecting that pointer cast too (E0804, “cannot add
+                // auto trait to dyn bound via pointer cast”), and suggests transmute instead. Auto
+                // traits contribute nothing to the vtable, so the two fat pointers are identical.
                 let clone: Box<dyn CloneAny> = (**self).clone_to_any();
                 let raw: *mut dyn CloneAny = Box::into_raw(clone);
-                unsafe { Box::from_raw(raw as *mut $t) }
+                unsafe { Box::from_raw(core::mem::transmute::<*mut dyn CloneAny, *mut $t>(raw)) }
             }
         }
 
@@ -51,7 +65,14 @@ macro_rules! impl_clone {
 ///
 /// This should only be implemented on trait objects for subtraits of `Any`, though you can
 /// implement it for other types and it’ll work fine, so long as your implementation is correct.
-pub trait Downcast {
+/// Implement it with [`impl_downcast!`](crate::impl_downcast) rather than directly.
+///
+/// # Safety
+///
+/// The map trusts `type_id` in choosing which type to downcast a value to: it must return the
+/// `TypeId` of the concrete type of `self`, and the unchecked downcasts must, given that type,
+/// return the very same value. If either lies, safe map operations cause *undefined behaviour*.
+pub unsafe trait Downcast {
     /// Gets the `TypeId` of `self`.
     fn type_id(&self) -> TypeId;
 
@@ -89,16 +110,78 @@ pub trait Downcast {
 }
 
 /// A trait for the conversion of an object into a boxed trait object.
+///
+/// This is implemented for every `T` whose box [`CoerceFrom`] makes into an `A`.
 pub trait IntoBox<A: ?Sized + Downcast>: Any {
     /// Convert self into the appropriate boxed form.
     fn into_box(self) -> Box<A>;
+
+    /// Convert a box of self into the appropriate boxed form, reusing the allocation.
+    #[doc(hidden)]
+    fn box_into(boxed: Box<Self>) -> Box<A> where Self: Sized;
 }
 
-macro_rules! implement {
+impl<A: ?Sized + Downcast + CoerceFrom<T>, T: Any> IntoBox<A> for T {
+    #[inline]
+    fn into_box(self) -> Box<A> {
+        A::coerce(Box::new(self))
+    }
+
+    #[inline]
+    fn box_into(boxed: Box<T>) -> Box<A> {
+        A::coerce(boxed)
+    }
+}
+
+/// The unsizing of a `Box<T>` into a box of this trait object.
+///
+/// This is what connects a value type to a trait object type, for [`IntoBox`]; without it,
+/// another crate couldn’t implement `IntoBox<dyn ItsTrait>` for all `T: ItsTrait`. Implement it
+/// with [`impl_downcast!`](crate::impl_downcast) rather than directly.
+///
+/// # Safety
+///
+/// `coerce` must return the very same value, just unsized; if it returns some other value,
+/// downcasting it will cause *undefined behaviour*.
+pub unsafe trait CoerceFrom<T> {
+    /// Unsize the box.
+    fn coerce(boxed: Box<T>) -> Box<Self>;
+}
+
+/// Implement [`Downcast`] and [`CoerceFrom`] for a trait object type, so that it can be used as
+/// the `A` in `Map<A>`.
+///
+/// The trait must have `Any` as a supertrait; any auto traits follow it, as in `dyn Trait + Send`.
+/// This lets a map store values behind a domain trait, and call its methods on each value without
+/// downcasting:
+///
+/// ```rust
+/// use std::any::Any;
+///
+/// trait Named: Any {
+///     fn name(&self) -> String;
+/// }
+///
+/// anymap::impl_downcast!(Named);
+///
+/// struct Cat;
+/// impl Named for Cat {
+///     fn name(&self) -> String { "cat".into() }
+/// }
+///
+/// let mut data = anymap::OrderedMap::<dyn Named>::new();
+/// data.insert(Cat);
+/// assert!(data.get::<Cat>().is_some());
+/// let names: Vec<_> = data.as_raw().values().map(|named| named.name()).collect();
+/// assert_eq!(names, ["cat"]);
+/// ```
+#[macro_export]
+macro_rules! impl_downcast {
     ($any_trait:ident $(+ $auto_traits:ident)*) => {
-        impl Downcast for dyn $any_trait $(+ $auto_traits)* {
+        unsafe impl $crate::Downcast for dyn $any_trait $(+ $auto_traits)* {
             #[inline]
-            fn type_id(&self) -> TypeId {
+            fn type_id(&self) -> ::core::any::TypeId {
+                // The trait object’s own type_id (from Any, through the vtable), not Downcast’s.
                 self.type_id()
             }
 
@@ -113,23 +196,34 @@ macro_rules! implement {
             }
 
             #[inline]
-            unsafe fn downcast_unchecked<T: 'static>(self: Box<Self>) -> Box<T> {
-                Box::from_raw(Box::into_raw(self) as *mut T)
+            unsafe fn downcast_unchecked<T: 'static>(self: $crate::__private::Box<Self>)
+                -> $crate::__private::Box<T>
+            {
+                $crate::__private::Box::from_raw($crate::__private::Box::into_raw(self) as *mut T)
             }
         }
 
-        impl<T: $any_trait $(+ $auto_traits)*> IntoBox<dyn $any_trait $(+ $auto_traits)*> for T {
+        unsafe impl<T: $any_trait $(+ $auto_traits)*> $crate::CoerceFrom<T>
+            for dyn $any_trait $(+ $auto_traits)*
+        {
             #[inline]
-            fn into_box(self) -> Box<dyn $any_trait $(+ $auto_traits)*> {
-                Box::new(self)
+            fn coerce(boxed: $crate::__private::Box<T>) -> $crate::__private::Box<Self> {
+                boxed
             }
         }
     }
 }
 
+macro_rules! implement {
+    ($($tt:tt)*) => { impl_downcast!($($tt)*); }
+}
+
 implement!(Any);
 implement!(Any + Send);
 implement!(Any + Send + Sync);
+#[cfg(feature = "std")] implement!(Any + UnwindSafe + RefUnwindSafe);
+#[cfg(feature = "std")] implement!(Any + Send + UnwindSafe + RefUnwindSafe);
+#[cfg(feature = "std")] implement!(Any + Send + Sync + UnwindSafe + RefUnwindSafe);
 
 /// [`Any`], but with cloning.
 ///
@@ -140,6 +234,55 @@ impl<T: Any + Clone> CloneAny for T { }
 implement!(CloneAny);
 implement!(CloneAny + Send);
 implement!(CloneAny + Send + Sync);
+#[cfg(feature = "std")] implement!(CloneAny + UnwindSafe + RefUnwindSafe);
+#[cfg(feature = "std")] implement!(CloneAny + Send + UnwindSafe + RefUnwindSafe);
+#[cfg(feature = "std")] implement!(CloneAny + Send + Sync + UnwindSafe + RefUnwindSafe);
 impl_clone!(dyn CloneAny);
 impl_clone!(dyn CloneAny + Send);
 impl_clone!(dyn CloneAny + Send + Sync);
+#[cfg(feature = "std")] impl_clone!(dyn CloneAny + UnwindSafe + RefUnwindSafe);
+#[cfg(feature = "std")] impl_clone!(dyn CloneAny + Send + UnwindSafe + RefUnwindSafe);
+#[cfg(feature = "std")] impl_clone!(dyn CloneAny + Send + Sync + UnwindSafe + RefUnwindSafe);
+
+/// The conversion of a box of this trait object into a box of another with weaker bounds.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub(crate) trait Upcast<B: ?Sized> {
+    fn upcast(self: Box<Self>) -> Box<B>;
+}
+
+macro_rules! upcast {
+    // Dropping auto traits is an ordinary unsizing coercion.
+    ($from:ty => $($to:ty),+) => {
+        $(
+            #[cfg(any(feature = "std", feature = "hashbrown"))]
+            impl Upcast<$to> for $from {
+                #[inline]
+                fn upcast(self: Box<Self>) -> Box<$to> {
+                    self
+                }
+            }
+        )+
+    };
+    // Going from CloneAny to Any needs CloneToAny::into_any, which loses the auto traits; they
+    // are then reapplied as in impl_clone, which is sound because the value came from a $from.
+    (clone $from:ty => $($to:ty),+) => {
+        $(
+            #[cfg(any(feature = "std", feature = "hashbrown"))]
+            impl Upcast<$to> for $from {
+                #[inline]
+                fn upcast(self: Box<Self>) -> Box<$to> {
+                    let raw: *mut dyn Any = Box::into_raw(self.into_any());
+                    unsafe { Box::from_raw(core::mem::transmute::<*mut dyn Any, *mut $to>(raw)) }
+                }
+            }
+        )+
+    };
+}
+
+upcast!(dyn Any + Send => dyn Any);
+upcast!(dyn Any + Send + Sync => dyn Any + Send, dyn Any);
+upcast!(dyn CloneAny + Send => dyn CloneAny);
+upcast!(dyn CloneAny + Send + Sync => dyn CloneAny + Send, dyn CloneAny);
+upcast!(clone dyn CloneAny => dyn Any);
+upcast!(clone dyn CloneAny + Send => dyn Any + Send, dyn Any);
+upcast!(clone dyn CloneAny + Send + Sync => dyn Any + Send + Sync, dyn Any + Send, dyn Any);
@@ -0,0 +1,244 @@
+//! `BudgetMap`, a map which keeps the bytes held by its values within a budget.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+/// What a `BudgetMap` does when a value would take it over its byte budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OverBudget {
+    /// Don’t insert the value; `BudgetMap::insert` gives it back.
+    Reject,
+    /// Remove values, least recently inserted or changed first, until there’s room.
+    EvictOldest,
+}
+
+/// The bytes a value holds on the heap beyond its own size, for a `BudgetMap` to count once the
+/// type is registered with `BudgetMap::measure`.
+///
+/// This needn’t be exact, but should be cheap.
+pub trait HeapSize {
+    /// The number of bytes this value owns on the heap.
+    fn heap_size(&self) -> usize;
+}
+
+impl HeapSize for String {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+// Not counting what the elements themselves own, which would mean walking them.
+impl<T> HeapSize for Vec<T> {
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.capacity() * core::mem::size_of::<T>()
+    }
+}
+
+macro_rules! budget_map {
+    ($example_use:literal) => {
+        /// A [`Map`] which counts the bytes its values hold, and won’t go over a byte budget,
+        /// rejecting values or evicting others as its [`OverBudget`](crate::OverBudget) says.
+        ///
+        /// A value counts as its own size, plus its [`HeapSize`](crate::HeapSize) if its type has
+        /// been registered with [`measure`](BudgetMap::measure). The count is only updated on
+        /// mutation through the methods here, so reading happens through
+        /// `Deref<Target = Map<A>>`, and changing a value through
+        /// [`with_mut`](BudgetMap::with_mut).
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// use anymap::OverBudget;
+        ///
+        /// let mut data = BudgetMap::<dyn std::any::Any>::new(64, OverBudget::Reject);
+        /// data.measure::<String>();
+        /// assert_eq!(data.insert(String::with_capacity(16)), Ok(None));
+        /// assert_eq!(data.total_bytes(), std::mem::size_of::<String>() + 16);
+        /// assert!(data.insert(String::with_capacity(1024)).is_err());
+        /// assert_eq!(data.get::<String>().unwrap().capacity(), 16);
+        /// ```
+        pub struct BudgetMap<A: ?Sized + Downcast = dyn Any> {
+            map: Map<A>,
+            budget: usize,
+            over_budget: crate::OverBudget,
+            /// The functions measuring the heap size of values of the types registered with
+            /// `measure`.
+            measures: HashMap<TypeId, fn(&A) -> usize, BuildHasherDefault<TypeIdHasher>>,
+            /// The bytes counted for each value, least recently inserted or changed first.
+            sizes: Vec<(TypeId, usize)>,
+            total: usize,
+        }
+
+        // Not #[derive(Debug)]: on older compilers, higher-ranked fn pointers don’t implement Debug.
+        impl<A: ?Sized + Downcast> core::fmt::Debug for BudgetMap<A> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_struct("BudgetMap")
+                    .field("used", &self.total)
+                    .field("budget", &self.budget)
+                    .field("len", &self.map.len())
+                    .finish()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> core::ops::Deref for BudgetMap<A> {
+            type Target = Map<A>;
+
+            #[inline]
+            fn deref(&self) -> &Map<A> {
+                &self.map
+            }
+        }
+
+        impl<A: ?Sized + Downcast> BudgetMap<A> {
+            /// Create an empty collection with a budget of `budget` bytes.
+            #[inline]
+            pub fn new(budget: usize, over_budget: crate::OverBudget) -> BudgetMap<A> {
+                BudgetMap::with_map(Map::new(), budget, over_budget)
+            }
+
+            /// Wrap an existing map, counting its values by their size alone, and leaving them
+            /// all in even if they’re over budget.
+            pub fn with_map(map: Map<A>, budget: usize, over_budget: crate::OverBudget) -> BudgetMap<A> {
+                let sizes: Vec<_> = map.raw.iter()
+                    .map(|(type_id, value)| (*type_id, core::mem::size_of_val::<A>(value)))
+                    .collect();
+                let total = sizes.iter().map(|&(_, bytes)| bytes).sum();
+                BudgetMap {
+                    map,
+                    budget,
+                    over_budget,
+                    measures: HashMap::with_hasher(Default::default()),
+                    sizes,
+                    total,
+                }
+            }
+
+            /// The budget, in bytes.
+            #[inline]
+            pub fn budget(&self) -> usize {
+                self.budget
+            }
+
+            /// The bytes counted for all the values in the collection.
+            #[inline]
+            pub fn total_bytes(&self) -> usize {
+                self.total
+            }
+
+            /// Count the [`HeapSize`](crate::HeapSize) of values of the type `T`, as well as
+            /// their own size, recounting the value already there, if any.
+            pub fn measure<T: IntoBox<A> + crate::HeapSize>(&mut self) {
+                fn measure_as<T: IntoBox<A> + crate::HeapSize, A: ?Sized + Downcast>(value: &A) -> usize {
+                    // SAFETY: measures are only used on values under their own type’s key.
+                    unsafe { value.downcast_ref_unchecked::<T>() }.heap_size()
+                }
+                let _ = self.measures.insert(TypeId::of::<T>(), measure_as::<T, A>);
+                self.recount(TypeId::of::<T>());
+            }
+
+            /// The bytes to count for the value of the type `type_id`.
+            fn bytes_of(&self, type_id: TypeId, value: &A) -> usize {
+                core::mem::size_of_val(value) + self.measures.get(&type_id).map_or(0, |measure| measure(value))
+            }
+
+            /// Stop counting the value of the type `type_id`, returning the bytes it counted for.
+            fn forget(&mut self, type_id: TypeId) -> usize {
+                match self.sizes.iter().position(|&(t, _)| t == type_id) {
+                    Some(i) => {
+                        let (_, bytes) = self.sizes.remove(i);
+                        self.total -= bytes;
+                        bytes
+                    },
+                    None => 0,
+                }
+            }
+
+            /// Remove values, oldest first, until there’s room for `bytes` more, or nothing left
+            /// to remove. The value being counted must have been forgotten first.
+            fn make_room(&mut self, bytes: usize) {
+                while self.total + bytes > self.budget && !self.sizes.is_empty() {
+                    let (type_id, evicted) = self.sizes.remove(0);
+                    let _ = self.map.raw.remove(&type_id);
+                    self.total -= evicted;
+                }
+            }
+
+            /// Count the value of the type `type_id` afresh, as just changed, evicting others if
+            /// that’s what to do when over budget.
+            fn recount(&mut self, type_id: TypeId) {
+                let _ = self.forget(type_id);
+                let bytes = match self.map.raw.get(&type_id) {
+                    Some(value) => self.bytes_of(type_id, value),
+                    None => return,
+                };
+                if self.over_budget == crate::OverBudget::EvictOldest {
+                    // Keep the value from evicting itself.
+                    let value = self.map.raw.remove(&type_id);
+                    self.make_room(bytes);
+                    let _ = self.map.raw.insert(type_id, value.unwrap());
+                }
+                self.sizes.push((type_id, bytes));
+                self.total += bytes;
+            }
+
+            /// Sets the value stored in the collection for the type `T`, if it fits in the budget
+            /// (evicting others to make room, if that’s what to do when over budget).
+            ///
+            /// If the collection already had a value of type `T`, `Ok(Some)` of that value is
+            /// returned; if the value didn’t fit, `Err` of the value, and any value of type `T`
+            /// already there is kept.
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Result<Option<T>, T> {
+                let type_id = TypeId::of::<T>();
+                let boxed = value.into_box();
+                let bytes = self.bytes_of(type_id, &boxed);
+                let old_bytes = self.sizes.iter().find(|&&(t, _)| t == type_id).map_or(0, |&(_, bytes)| bytes);
+                let fits = self.total - old_bytes + bytes <= self.budget;
+                if bytes > self.budget || !fits && self.over_budget == crate::OverBudget::Reject {
+                    return Err(unsafe { *boxed.downcast_unchecked::<T>() });
+                }
+                let _ = self.forget(type_id);
+                let old = self.map.raw.remove(&type_id);
+                self.make_room(bytes);
+                self.map.record_name::<T>();
+                let _ = self.map.raw.insert(type_id, boxed);
+                self.sizes.push((type_id, bytes));
+                self.total += bytes;
+                Ok(old.map(|any| unsafe { *any.downcast_unchecked::<T>() }))
+            }
+
+            /// Calls `f` with a mutable reference to the value stored in the collection for the
+            /// type `T`, if it exists, then counts the value afresh.
+            ///
+            /// If that takes the collection over budget, others are evicted if that’s what to do;
+            /// otherwise it’s left over budget until values are removed.
+            pub fn with_mut<T: IntoBox<A>, R, F: FnOnce(&mut T) -> R>(&mut self, f: F) -> Option<R> {
+                let result = f(self.map.get_mut::<T>()?);
+                self.recount(TypeId::of::<T>());
+                Some(result)
+            }
+
+            /// Removes the `T` value from the collection,
+            /// returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+                let _ = self.forget(TypeId::of::<T>());
+                self.map.remove::<T>()
+            }
+
+            /// Removes all items from the collection. Keeps the allocated memory for reuse.
+            #[inline]
+            pub fn clear(&mut self) {
+                self.sizes.clear();
+                self.total = 0;
+                self.map.clear();
+            }
+
+            /// Unwrap the underlying map.
+            #[inline]
+            pub fn into_inner(self) -> Map<A> {
+                self.map
+            }
+        }
+    };
+}
@@ -0,0 +1,83 @@
+//! `EventBus`, which dispatches events to handlers by the events’ type.
+
+macro_rules! event_bus {
+    ($example_use:literal) => {
+        /// The handlers subscribed to events of type `E`, as stored in an [`EventBus`].
+        struct Handlers<E>(Vec<Box<dyn Fn(&E)>>);
+
+        /// A collection of event handlers, keyed by the type of event they handle.
+        ///
+        /// [`subscribe`](EventBus::subscribe) adds a handler for events of type `E`, and
+        /// [`emit`](EventBus::emit) calls every handler for the event’s type, in the order they
+        /// were subscribed. Any `'static` type can be an event; there is no trait to implement.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// use std::cell::Cell;
+        /// use std::rc::Rc;
+        ///
+        /// struct Clicked { x: i32 }
+        ///
+        /// let total = Rc::new(Cell::new(0));
+        /// let mut bus = EventBus::new();
+        /// let handle = total.clone();
+        /// bus.subscribe(move |event: &Clicked| handle.set(handle.get() + event.x));
+        /// assert_eq!(bus.emit(Clicked { x: 3 }), 1);
+        /// assert_eq!(bus.emit("nobody listens to strings"), 0);
+        /// assert_eq!(total.get(), 3);
+        /// ```
+        #[derive(Default)]
+        pub struct EventBus {
+            handlers: AnyMap,
+        }
+
+        impl core::fmt::Debug for EventBus {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_struct("EventBus")
+                    .field("event_types", &self.handlers.len())
+                    .finish()
+            }
+        }
+
+        impl EventBus {
+            /// Create an event bus with no handlers.
+            #[inline]
+            pub fn new() -> EventBus {
+                EventBus::default()
+            }
+
+            /// Add a handler for events of type `E`.
+            pub fn subscribe<E: 'static, F: Fn(&E) + 'static>(&mut self, handler: F) {
+                self.handlers.entry::<Handlers<E>>()
+                    .or_insert_with(|| Handlers(Vec::new()))
+                    .0.push(Box::new(handler));
+            }
+
+            /// Remove all the handlers for events of type `E`, returning how many there were.
+            #[inline]
+            pub fn unsubscribe_all<E: 'static>(&mut self) -> usize {
+                self.handlers.remove::<Handlers<E>>().map_or(0, |handlers| handlers.0.len())
+            }
+
+            /// Returns the number of handlers for events of type `E`.
+            #[inline]
+            pub fn subscribers<E: 'static>(&self) -> usize {
+                self.handlers.get::<Handlers<E>>().map_or(0, |handlers| handlers.0.len())
+            }
+
+            /// Call every handler for events of type `E` with `event`, returning how many there
+            /// were.
+            pub fn emit<E: 'static>(&self, event: E) -> usize {
+                match self.handlers.get::<Handlers<E>>() {
+                    Some(handlers) => {
+                        for handler in &handlers.0 {
+                            handler(&event);
+                        }
+                        handlers.0.len()
+                    }
+                    None => 0,
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,101 @@
+//! `CastRegistry`, for viewing values in a `Map` as some trait object without knowing their types.
+
+macro_rules! cast_registry {
+    ($example_use:literal) => {
+        /// A set of functions for viewing values of various types as `&U`, normally a trait object.
+        ///
+        /// Register a cast for each type that should be viewable with
+        /// [`register`](CastRegistry::register); then any `Map` can produce the views with
+        /// [`Map::get_as`] and [`Map::iter_as`]. Types without a registered cast are skipped.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// use std::fmt::Display;
+        /// let mut casts = CastRegistry::<dyn Display>::new();
+        /// casts.register::<i32>(|x| x);
+        /// casts.register::<String>(|x| x);
+        ///
+        /// let mut data = AnyMap::new();
+        /// data.insert(42i32);
+        /// data.insert(String::from("hello"));
+        /// data.insert(());
+        /// assert_eq!(data.get_as::<i32, _>(&casts).unwrap().to_string(), "42");
+        /// let mut shown: Vec<_> = data.iter_as(&casts).map(|x| x.to_string()).collect();
+        /// shown.sort();
+        /// assert_eq!(shown, ["42", "hello"]);
+        /// ```
+        pub struct CastRegistry<U: ?Sized + 'static, A: ?Sized + Downcast = dyn Any> {
+            casts: HashMap<TypeId, Box<dyn Fn(&A) -> &U>, BuildHasherDefault<TypeIdHasher>>,
+        }
+
+        impl<U: ?Sized + 'static, A: ?Sized + Downcast> core::fmt::Debug for CastRegistry<U, A> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_set().entries(self.casts.keys()).finish()
+            }
+        }
+
+        impl<U: ?Sized + 'static, A: ?Sized + Downcast> Default for CastRegistry<U, A> {
+            #[inline]
+            fn default() -> CastRegistry<U, A> {
+                CastRegistry::new()
+            }
+        }
+
+        impl<U: ?Sized + 'static, A: ?Sized + Downcast> CastRegistry<U, A> {
+            /// Create an empty registry.
+            #[inline]
+            pub fn new() -> CastRegistry<U, A> {
+                CastRegistry {
+                    casts: HashMap::with_hasher(Default::default()),
+                }
+            }
+
+            /// Register the function used to view a value of type `T` as `&U`, replacing any
+            /// previously registered for `T`.
+            ///
+            /// Normally this is just `|x| x`, letting the compiler do the unsizing coercion.
+            pub fn register<T: IntoBox<A>>(&mut self, cast: fn(&T) -> &U) {
+                let cast: Box<dyn Fn(&A) -> &U> = Box::new(move |any: &A| {
+                    // SAFETY: `cast_raw` is only called with values of the type they’re keyed by.
+                    cast(unsafe { any.downcast_ref_unchecked::<T>() })
+                });
+                let _ = self.casts.insert(TypeId::of::<T>(), cast);
+            }
+
+            /// Returns true if a cast is registered for the type `T`.
+            #[inline]
+            pub fn contains<T: IntoBox<A>>(&self) -> bool {
+                self.casts.contains_key(&TypeId::of::<T>())
+            }
+
+            /// View `value`, whose type is that of `type_id`, through its registered cast.
+            #[inline]
+            fn cast_raw<'a>(&self, type_id: &TypeId, value: &'a A) -> Option<&'a U> {
+                self.casts.get(type_id).map(|cast| cast(value))
+            }
+        }
+
+        impl<A: ?Sized + Downcast> Map<A> {
+            /// Returns the value stored in the collection for the type `T` as a `&U`, if it
+            /// exists and `casts` has a cast registered for `T`.
+            #[inline]
+            pub fn get_as<'a, T: IntoBox<A>, U: ?Sized + 'static>(
+                &'a self,
+                casts: &CastRegistry<U, A>,
+            ) -> Option<&'a U> {
+                let type_id = TypeId::of::<T>();
+                self.raw.get(&type_id).and_then(|value| casts.cast_raw(&type_id, value))
+            }
+
+            /// An iterator visiting, as `&U` and in arbitrary order, all the values in the
+            /// collection of types that `casts` has a cast registered for.
+            #[inline]
+            pub fn iter_as<'a, U: ?Sized + 'static>(
+                &'a self,
+                casts: &'a CastRegistry<U, A>,
+            ) -> impl Iterator<Item = &'a U> {
+                self.raw.iter().filter_map(move |(type_id, value)| casts.cast_raw(type_id, value))
+            }
+        }
+    };
+}
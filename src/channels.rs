@@ -0,0 +1,78 @@
+//! `TypedChannels`, which holds one channel per message type.
+
+macro_rules! typed_channels {
+    ($example_use:literal) => {
+        /// The channel for messages of type `M`, as stored in a [`TypedChannels`].
+        struct Channel<M> {
+            sender: std::sync::mpsc::Sender<M>,
+            receiver: Option<std::sync::mpsc::Receiver<M>>,
+        }
+
+        /// A collection of `std::sync::mpsc` channels, one per message type, each created when
+        /// first asked for.
+        ///
+        /// Subsystems which share this (or a map holding it) can talk to one another knowing
+        /// nothing but the message type: any number of senders can be got, but only one receiver
+        /// (channels being multi-producer, single-consumer).
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// struct Log(&'static str);
+        ///
+        /// let mut channels = TypedChannels::new();
+        /// let logs = channels.receiver::<Log>().unwrap();
+        /// assert!(channels.receiver::<Log>().is_none());
+        ///
+        /// let sender = channels.sender::<Log>();
+        /// std::thread::spawn(move || sender.send(Log("hello")).unwrap()).join().unwrap();
+        /// assert_eq!(logs.recv().unwrap().0, "hello");
+        /// ```
+        #[derive(Default)]
+        pub struct TypedChannels {
+            channels: Map<dyn Any + Send>,
+        }
+
+        impl core::fmt::Debug for TypedChannels {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_struct("TypedChannels")
+                    .field("message_types", &self.channels.len())
+                    .finish()
+            }
+        }
+
+        impl TypedChannels {
+            /// Create a collection with no channels.
+            #[inline]
+            pub fn new() -> TypedChannels {
+                TypedChannels::default()
+            }
+
+            /// The channel for messages of type `M`, created if need be.
+            fn channel<M: Send + 'static>(&mut self) -> &mut Channel<M> {
+                self.channels.entry::<Channel<M>>().or_insert_with(|| {
+                    let (sender, receiver) = std::sync::mpsc::channel();
+                    Channel { sender, receiver: Some(receiver) }
+                })
+            }
+
+            /// Returns a sender for messages of type `M`.
+            #[inline]
+            pub fn sender<M: Send + 'static>(&mut self) -> std::sync::mpsc::Sender<M> {
+                self.channel::<M>().sender.clone()
+            }
+
+            /// Returns the receiver for messages of type `M`, or `None` if it has been taken
+            /// already.
+            #[inline]
+            pub fn receiver<M: Send + 'static>(&mut self) -> Option<std::sync::mpsc::Receiver<M>> {
+                self.channel::<M>().receiver.take()
+            }
+
+            /// Returns true if the channel for messages of type `M` has been created.
+            #[inline]
+            pub fn contains<M: Send + 'static>(&self) -> bool {
+                self.channels.contains::<Channel<M>>()
+            }
+        }
+    };
+}
@@ -0,0 +1,222 @@
+//! `ColdMap`, a map which can demote idle values to encoded bytes and load them back on access.
+
+/// A demoted value: its payload, as saved and encoded, and the version it was saved at.
+#[derive(Debug)]
+pub(crate) struct ColdValue {
+    pub(crate) version: u32,
+    pub(crate) stored: Vec<u8>,
+}
+
+macro_rules! cold_map {
+    ($example_use:literal) => {
+        /// A [`Map`] which can demote values it holds to bytes, saved through
+        /// [`Save`](crate::persist::Save) and then encoded (perhaps compressed) by a
+        /// [`Codec`](crate::persist::Codec), and loads them back when they are next accessed.
+        ///
+        /// This trades time for memory in maps holding large values that are mostly left alone.
+        /// Only the types listed in its [`Registry`](crate::persist::Registry) are ever demoted.
+        /// Since any access may have to load a value, access is through the methods here, which
+        /// take `&mut self` and can fail.
+        ///
+        /// This requires the std feature.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// use std::io::{self, Read, Write};
+        /// use anymap::persist::{Load, Registry, Save};
+        ///
+        /// #[derive(Debug, PartialEq)]
+        /// struct History(Vec<u8>);
+        ///
+        /// impl Save for History {
+        ///     const KEY: &'static str = "history";
+        ///     const VERSION: u32 = 1;
+        ///     fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+        ///         out.write_all(&self.0)
+        ///     }
+        /// }
+        ///
+        /// impl Load for History {
+        ///     fn load(input: &mut dyn Read, _version: u32) -> io::Result<History> {
+        ///         let mut bytes = Vec::new();
+        ///         input.read_to_end(&mut bytes)?;
+        ///         Ok(History(bytes))
+        ///     }
+        /// }
+        ///
+        /// let mut data = ColdMap::<(), dyn std::any::Any>::new(Registry::new().register::<History>(), ());
+        /// data.insert(History(vec![1, 2, 3]))?;
+        /// data.insert(7u32)?;
+        /// for _ in 0..10 {
+        ///     *data.get_mut::<u32>()?.unwrap() += 1;
+        /// }
+        /// assert_eq!(data.demote_idle(5)?, 1);
+        /// assert!(data.is_cold::<History>());
+        /// assert_eq!(data.cold_bytes(), 3);
+        /// assert_eq!(data.get::<History>()?, Some(&History(vec![1, 2, 3])));
+        /// assert!(!data.is_cold::<History>());
+        /// # Ok::<(), io::Error>(())
+        /// ```
+        #[derive(Debug)]
+        pub struct ColdMap<C, A: ?Sized + Downcast = dyn Any> {
+            map: Map<A>,
+            registry: crate::persist::Registry<A>,
+            codec: C,
+            cold: HashMap<TypeId, crate::cold::ColdValue, BuildHasherDefault<TypeIdHasher>>,
+            /// When each value was last accessed, by `clock`.
+            last_used: HashMap<TypeId, u64, BuildHasherDefault<TypeIdHasher>>,
+            /// The number of accesses so far.
+            clock: u64,
+        }
+
+        impl<C: crate::persist::Codec, A: ?Sized + Downcast> ColdMap<C, A> {
+            /// Create an empty collection, which may demote values of the types in `registry`,
+            /// encoding them with `codec`.
+            #[inline]
+            pub fn new(registry: crate::persist::Registry<A>, codec: C) -> ColdMap<C, A> {
+                ColdMap {
+                    map: Map::new(),
+                    registry,
+                    codec,
+                    cold: HashMap::with_hasher(Default::default()),
+                    last_used: HashMap::with_hasher(Default::default()),
+                    clock: 0,
+                }
+            }
+
+            /// Returns the number of items in the collection, demoted or not.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.map.len() + self.cold.len()
+            }
+
+            /// Returns true if there are no items in the collection.
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            /// Returns true if the collection contains a value of type `T`, demoted or not.
+            #[inline]
+            pub fn contains<T: IntoBox<A>>(&self) -> bool {
+                self.map.contains::<T>() || self.is_cold::<T>()
+            }
+
+            /// Returns true if the value of type `T` is demoted.
+            #[inline]
+            pub fn is_cold<T: IntoBox<A>>(&self) -> bool {
+                self.cold.contains_key(&TypeId::of::<T>())
+            }
+
+            /// The number of bytes the demoted values are stored in.
+            #[inline]
+            pub fn cold_bytes(&self) -> usize {
+                self.cold.values().map(|cold| cold.stored.len()).sum()
+            }
+
+            /// Load the value of the type `type_id` back, if it was demoted. If this fails, it
+            /// stays demoted.
+            fn load(&mut self, type_id: TypeId) -> std::io::Result<()> {
+                if let Some(cold) = self.cold.get(&type_id) {
+                    let payload = self.codec.decode(&cold.stored)?;
+                    let value = self.registry.load_value(&type_id, cold.version, &mut &payload[..])?;
+                    let _ = self.cold.remove(&type_id);
+                    let _ = self.map.raw.insert(type_id, value);
+                }
+                Ok(())
+            }
+
+            /// Note an access of the value of the type `type_id`.
+            #[inline]
+            fn touch(&mut self, type_id: TypeId) {
+                self.clock += 1;
+                let _ = self.last_used.insert(type_id, self.clock);
+            }
+
+            /// Returns a reference to the value stored in the collection for the type `T`, if it
+            /// exists, loading it back first if it was demoted.
+            pub fn get<T: IntoBox<A>>(&mut self) -> std::io::Result<Option<&T>> {
+                self.load(TypeId::of::<T>())?;
+                self.touch(TypeId::of::<T>());
+                Ok(self.map.get::<T>())
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists, loading it back first if it was demoted.
+            pub fn get_mut<T: IntoBox<A>>(&mut self) -> std::io::Result<Option<&mut T>> {
+                self.load(TypeId::of::<T>())?;
+                self.touch(TypeId::of::<T>());
+                Ok(self.map.get_mut::<T>())
+            }
+
+            /// Sets the value stored in the collection for the type `T`.
+            /// If the collection already had a value of type `T`, that value is returned (loading
+            /// it back first if it was demoted, so that this can fail).
+            /// Otherwise, `None` is returned.
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> std::io::Result<Option<T>> {
+                self.load(TypeId::of::<T>())?;
+                self.touch(TypeId::of::<T>());
+                Ok(self.map.insert(value))
+            }
+
+            /// Removes the `T` value from the collection, loading it back first if it was demoted,
+            /// returning it if there was one or `None` if there was not.
+            pub fn remove<T: IntoBox<A>>(&mut self) -> std::io::Result<Option<T>> {
+                self.load(TypeId::of::<T>())?;
+                let _ = self.last_used.remove(&TypeId::of::<T>());
+                Ok(self.map.remove::<T>())
+            }
+
+            /// Demote the value of the type `type_id`, returning true if it was there to demote
+            /// and its type is registered.
+            fn demote_raw(&mut self, type_id: TypeId) -> std::io::Result<bool> {
+                let mut payload = Vec::new();
+                let version = match self.map.raw.get(&type_id) {
+                    Some(value) => match self.registry.save_value(&type_id, value, &mut payload) {
+                        Some(version) => version?,
+                        None => return Ok(false),
+                    },
+                    None => return Ok(false),
+                };
+                let stored = self.codec.encode(&payload);
+                let _ = self.map.raw.remove(&type_id);
+                let _ = self.cold.insert(type_id, crate::cold::ColdValue { version, stored });
+                Ok(true)
+            }
+
+            /// Demote the value of type `T`, returning true if there was one to demote and `T` is
+            /// registered.
+            #[inline]
+            pub fn demote<T: IntoBox<A>>(&mut self) -> std::io::Result<bool> {
+                self.demote_raw(TypeId::of::<T>())
+            }
+
+            /// Demote the values of the registered types which haven’t been accessed in the last
+            /// `idle` accesses of the collection, returning how many were demoted.
+            pub fn demote_idle(&mut self, idle: u64) -> std::io::Result<usize> {
+                let clock = self.clock;
+                let last_used = &self.last_used;
+                let idle_types: Vec<TypeId> = self.map.raw.keys()
+                    .filter(|type_id| clock - last_used.get(type_id).cloned().unwrap_or(0) > idle)
+                    .cloned()
+                    .collect();
+                let mut demoted = 0;
+                for type_id in idle_types {
+                    if self.demote_raw(type_id)? {
+                        demoted += 1;
+                    }
+                }
+                Ok(demoted)
+            }
+
+            /// Load back all the demoted values and unwrap the underlying map.
+            pub fn into_inner(mut self) -> std::io::Result<Map<A>> {
+                let cold: Vec<TypeId> = self.cold.keys().cloned().collect();
+                for type_id in cold {
+                    self.load(type_id)?;
+                }
+                Ok(self.map)
+            }
+        }
+    };
+}
@@ -0,0 +1,166 @@
+//! `CountedMap`, which counts the hits, misses and mutations of each type.
+
+/// How often a type has been accessed in a `CountedMap`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AccessCounts {
+    /// Lookups (`get` or `get_mut`) which found a value.
+    pub hits: u64,
+    /// Lookups which found nothing.
+    pub misses: u64,
+    /// Insertions, removals of a value, and `get_mut` hits.
+    pub mutations: u64,
+}
+
+macro_rules! counted_map {
+    ($example_use:literal) => {
+        /// A [`Map`] which counts, for each type, how often it is looked up (successfully or not)
+        /// and mutated, so that unused entries can be found with [`report`](CountedMap::report).
+        ///
+        /// Counting happens in this type’s `get`, `get_mut`, `insert` and `remove`; everything else
+        /// on `Map` is reached, uncounted, through `Deref`. Because `get` takes `&self` and still
+        /// counts, this type is not `Sync`.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// let mut data = CountedMap::<dyn std::any::Any>::new();
+        /// data.insert(1u8);
+        /// data.insert(2u16);
+        /// assert_eq!(data.get::<u8>(), Some(&1));
+        /// assert_eq!(data.get::<u32>(), None);
+        /// assert_eq!(data.counts::<u32>().misses, 1);
+        /// let report = data.report();
+        /// // Least used first: u32 was only missed, and u16 was inserted but never looked up.
+        /// assert_eq!(report[1].0.type_id, std::any::TypeId::of::<u16>());
+        /// assert_eq!(report[1].1.hits, 0);
+        /// ```
+        #[derive(Debug)]
+        pub struct CountedMap<A: ?Sized + Downcast = dyn Any> {
+            map: Map<A>,
+            counts: core::cell::RefCell<
+                HashMap<TypeId, (crate::TypeInfo, crate::AccessCounts), BuildHasherDefault<TypeIdHasher>>
+            >,
+        }
+
+        impl<A: ?Sized + Downcast> Default for CountedMap<A> {
+            #[inline]
+            fn default() -> CountedMap<A> {
+                CountedMap::new()
+            }
+        }
+
+        /// Count the accesses of an existing map, starting from zero.
+        impl<A: ?Sized + Downcast> From<Map<A>> for CountedMap<A> {
+            #[inline]
+            fn from(map: Map<A>) -> CountedMap<A> {
+                CountedMap {
+                    map,
+                    counts: core::cell::RefCell::new(HashMap::with_hasher(Default::default())),
+                }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> core::ops::Deref for CountedMap<A> {
+            type Target = Map<A>;
+
+            #[inline]
+            fn deref(&self) -> &Map<A> {
+                &self.map
+            }
+        }
+
+        impl<A: ?Sized + Downcast> CountedMap<A> {
+            /// Create an empty collection, with nothing counted.
+            #[inline]
+            pub fn new() -> CountedMap<A> {
+                Map::new().into()
+            }
+
+            /// Update the counts for the type `T`.
+            fn count<T: 'static, F: FnOnce(&mut crate::AccessCounts)>(&self, update: F) {
+                let mut counts = self.counts.borrow_mut();
+                update(&mut counts.entry(TypeId::of::<T>())
+                    .or_insert_with(|| (crate::TypeInfo::of::<T>(), Default::default())).1);
+            }
+
+            /// Returns a reference to the value stored in the collection for the type `T`,
+            /// if it exists, counting a hit or a miss.
+            #[inline]
+            pub fn get<T: IntoBox<A>>(&self) -> Option<&T> {
+                let value = self.map.get::<T>();
+                let hit = value.is_some();
+                self.count::<T, _>(|counts| {
+                    if hit { counts.hits += 1 } else { counts.misses += 1 }
+                });
+                value
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists, counting a hit and a mutation, or a miss.
+            #[inline]
+            pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+                let hit = self.map.contains::<T>();
+                self.count::<T, _>(|counts| if hit {
+                    counts.hits += 1;
+                    counts.mutations += 1;
+                } else {
+                    counts.misses += 1;
+                });
+                self.map.get_mut::<T>()
+            }
+
+            /// Sets the value stored in the collection for the type `T`, counting a mutation.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> {
+                self.count::<T, _>(|counts| counts.mutations += 1);
+                self.map.insert(value)
+            }
+
+            /// Removes the `T` value from the collection, counting a mutation if there was one,
+            /// returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+                let old = self.map.remove::<T>();
+                if old.is_some() {
+                    self.count::<T, _>(|counts| counts.mutations += 1);
+                }
+                old
+            }
+
+            /// The counts for the type `T`, which are all zero if it hasn’t been accessed.
+            #[inline]
+            pub fn counts<T: IntoBox<A>>(&self) -> crate::AccessCounts {
+                self.counts.borrow().get(&TypeId::of::<T>())
+                    .map_or_else(Default::default, |&(_, counts)| counts)
+            }
+
+            /// The counts for every type accessed, and every type in the collection (even if it
+            /// was put there uncounted), least used (by hits, then mutations) first.
+            pub fn report(&self) -> Vec<(crate::TypeInfo, crate::AccessCounts)> {
+                let counts = self.counts.borrow();
+                let mut report: Vec<_> = counts.values().cloned().collect();
+                report.extend(self.map.raw.keys()
+                    .filter(|type_id| !counts.contains_key(type_id))
+                    .map(|&type_id| {
+                        let info = crate::TypeInfo { type_id, name: self.map.name_of(type_id) };
+                        (info, Default::default())
+                    }));
+                report.sort_by_key(|&(_, counts)| (counts.hits, counts.mutations));
+                report
+            }
+
+            /// Set all the counts back to zero.
+            #[inline]
+            pub fn reset_counts(&mut self) {
+                self.counts.get_mut().clear();
+            }
+
+            /// Unwrap the underlying map, discarding the counts.
+            #[inline]
+            pub fn into_inner(self) -> Map<A> {
+                self.map
+            }
+        }
+    };
+}
@@ -0,0 +1,311 @@
+//! The error types of the fallible operations on maps.
+//!
+//! Each implements `Display`, and `std::error::Error` with the std feature. They are all
+//! re-exported at the crate root too.
+
+#[cfg(any(debug_assertions, feature = "debug-validate"))]
+use core::any::TypeId;
+use core::fmt;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+pub use crate::manifest::ManifestError;
+pub use crate::schema::SchemaError;
+use crate::TypeInfo;
+
+/// The error from `Map::try_get` and `Map::try_get_mut`: the map has no value of the type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MissingTypeError {
+    type_info: TypeInfo,
+}
+
+impl MissingTypeError {
+    /// The error for the type `T`.
+    #[inline]
+    pub(crate) fn of<T: 'static>() -> MissingTypeError {
+        MissingTypeError { type_info: TypeInfo::of::<T>() }
+    }
+
+    /// The missing type. Its name is known if the type-names Cargo feature is enabled.
+    #[inline]
+    pub fn type_info(&self) -> TypeInfo {
+        self.type_info
+    }
+}
+
+impl fmt::Display for MissingTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "map contains no value of type {}", self.type_info)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MissingTypeError {}
+
+/// The error from `Map::try_insert`: the map already has a value of the type. This holds the
+/// value which was not inserted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OccupiedError<T> {
+    value: T,
+    type_info: TypeInfo,
+}
+
+impl<T: 'static> OccupiedError<T> {
+    /// The error for the value `value`.
+    #[inline]
+    pub(crate) fn new(value: T) -> OccupiedError<T> {
+        OccupiedError { value, type_info: TypeInfo::of::<T>() }
+    }
+
+    /// The type of the value. Its name is known if the type-names Cargo feature is enabled.
+    #[inline]
+    pub fn type_info(&self) -> TypeInfo {
+        self.type_info
+    }
+
+    /// Take back the value which was not inserted.
+    #[inline]
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+impl<T> fmt::Display for OccupiedError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "map already contains a value of type {}", self.type_info)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: fmt::Debug> std::error::Error for OccupiedError<T> {}
+
+/// The error from `Map::try_clone`: some values’ types have no clone function registered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CloneError {
+    pub(crate) unregistered: Vec<TypeInfo>,
+}
+
+impl CloneError {
+    /// The types of the values which couldn’t be cloned.
+    ///
+    /// Their names are known only if they were put in the map by type, with the type-names Cargo
+    /// feature enabled.
+    #[inline]
+    pub fn unregistered(&self) -> &[TypeInfo] {
+        &self.unregistered
+    }
+}
+
+impl fmt::Display for CloneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("no clone function registered for ")?;
+        for (i, info) in self.unregistered.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", info)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CloneError {}
+
+/// The error from `Map::apply`: an operation in the batch wasn’t possible, so none were applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OpsError {
+    pub(crate) index: usize,
+    pub(crate) type_info: TypeInfo,
+    pub(crate) occupied: bool,
+}
+
+impl OpsError {
+    /// The position of the failing operation in the batch, counting from zero.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The type the failing operation was for. Its name is known if the type-names Cargo feature
+    /// is enabled.
+    #[inline]
+    pub fn type_info(&self) -> TypeInfo {
+        self.type_info
+    }
+
+    /// True if the operation failed because a value of the type was present (for an insert),
+    /// false if because none was (for a replace or remove).
+    #[inline]
+    pub fn occupied(&self) -> bool {
+        self.occupied
+    }
+}
+
+impl fmt::Display for OpsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "operation {} failed: map ", self.index)?;
+        if self.occupied {
+            write!(f, "already contains a value of type {}", self.type_info)
+        } else {
+            write!(f, "contains no value of type {}", self.type_info)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OpsError {}
+
+/// An entry of a raw map whose key doesn’t match its value’s type, as found by
+/// `Map::debug_validate`.
+#[cfg(any(debug_assertions, feature = "debug-validate"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyMismatch {
+    pub(crate) key: TypeId,
+    pub(crate) actual: TypeId,
+}
+
+#[cfg(any(debug_assertions, feature = "debug-validate"))]
+impl KeyMismatch {
+    /// The key the value was stored under.
+    #[inline]
+    pub fn key(&self) -> TypeId {
+        self.key
+    }
+
+    /// The `TypeId` of the value’s actual type.
+    #[inline]
+    pub fn actual(&self) -> TypeId {
+        self.actual
+    }
+}
+
+#[cfg(any(debug_assertions, feature = "debug-validate"))]
+impl fmt::Display for KeyMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "value of type {:?} is stored under the key {:?}", self.actual, self.key)
+    }
+}
+
+#[cfg(all(feature = "std", any(debug_assertions, feature = "debug-validate")))]
+impl std::error::Error for KeyMismatch {}
+
+/// The error from `Map::try_reserve`: the capacity overflowed or the allocator failed, as told
+/// by its [`kind`](TryReserveError::kind).
+///
+/// With the try-reserve Cargo feature, an error from std’s `HashMap` is kept as its
+/// [`source`](std::error::Error::source).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::incompatible_msrv)]  // The try-reserve feature requires 1.57.
+pub struct TryReserveError {
+    kind: TryReserveErrorKind,
+    #[cfg(feature = "try-reserve")]
+    source: Option<std::collections::TryReserveError>,
+}
+
+/// Why reserving capacity failed, for [`TryReserveError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TryReserveErrorKind {
+    /// The capacity needed exceeds the collection’s maximum (usually `isize::MAX` bytes).
+    CapacityOverflow,
+    /// The allocator returned an error.
+    AllocError,
+}
+
+impl TryReserveError {
+    /// The error of the kind `kind`.
+    #[cfg(any(feature = "hashbrown", feature = "try-reserve"))]
+    #[inline]
+    pub(crate) fn new(kind: TryReserveErrorKind) -> TryReserveError {
+        TryReserveError {
+            kind,
+            #[cfg(feature = "try-reserve")]
+            source: None,
+        }
+    }
+
+    /// The error passing on hashbrown’s own error, `error`.
+    #[cfg(feature = "hashbrown")]
+    #[inline]
+    pub(crate) fn from_hashbrown<E: fmt::Debug>(error: E) -> TryReserveError {
+        TryReserveError::new(kind_of(&error))
+    }
+
+    /// The error passing on std’s own error, `error`.
+    #[cfg(feature = "try-reserve")]
+    #[inline]
+    #[allow(clippy::incompatible_msrv)]  // The try-reserve feature requires 1.57.
+    pub(crate) fn from_std(error: std::collections::TryReserveError) -> TryReserveError {
+        TryReserveError { kind: kind_of(&error), source: Some(error) }
+    }
+
+    /// Whether the capacity overflowed or the allocator failed.
+    #[inline]
+    pub fn kind(&self) -> TryReserveErrorKind {
+        self.kind
+    }
+}
+
+/// The kind of a hash table’s error from `try_reserve`.
+///
+/// Its type differs between backends and versions (hashbrown’s was `CollectionAllocErr` before
+/// 0.9.0, and `TryReserveError` since; std’s kind is unstable), but every one of them calls the
+/// overflow `CapacityOverflow`, so that’s what is looked for in its `Debug` output.
+#[cfg(any(feature = "hashbrown", feature = "try-reserve"))]
+fn kind_of<E: fmt::Debug>(error: &E) -> TryReserveErrorKind {
+    use core::fmt::Write;
+
+    /// Notes whether `CapacityOverflow` is written, without allocating.
+    struct Finder {
+        matched: usize,
+        found: bool,
+    }
+
+    impl fmt::Write for Finder {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            const NEEDLE: &[u8] = b"CapacityOverflow";
+            for &byte in s.as_bytes() {
+                if self.found {
+                    break;
+                }
+                self.matched = if byte == NEEDLE[self.matched] {
+                    self.matched + 1
+                } else if byte == NEEDLE[0] {
+                    1
+                } else {
+                    0
+                };
+                self.found = self.matched == NEEDLE.len();
+            }
+            Ok(())
+        }
+    }
+
+    let mut finder = Finder { matched: 0, found: false };
+    let _ = write!(finder, "{:?}", error);
+    if finder.found {
+        TryReserveErrorKind::CapacityOverflow
+    } else {
+        TryReserveErrorKind::AllocError
+    }
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("memory allocation failed")?;
+        if self.kind == TryReserveErrorKind::CapacityOverflow {
+            f.write_str(" because the computed capacity exceeded the collection’s maximum")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TryReserveError {
+    #[cfg(feature = "try-reserve")]
+    #[allow(clippy::incompatible_msrv)]  // The try-reserve feature requires 1.57.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_ref().map(|error| error as &(dyn std::error::Error + 'static))
+    }
+}
@@ -0,0 +1,169 @@
+//! `ExpiringMap`, whose entries can be given a time to live.
+
+macro_rules! expiring_map {
+    ($example_use:literal) => {
+        /// A value in an [`ExpiringMap`], with its expiry time.
+        #[derive(Debug)]
+        struct ExpiringSlot<A: ?Sized> {
+            expires: Option<std::time::Instant>,
+            value: Box<A>,
+        }
+
+        impl<A: ?Sized> ExpiringSlot<A> {
+            #[inline]
+            fn is_live(&self, now: std::time::Instant) -> bool {
+                self.expires.map_or(true, |expires| now < expires)
+            }
+        }
+
+        /// A collection like [`Map`], but where values can be inserted with a time to live, after
+        /// which they are treated as absent.
+        ///
+        /// Expired values stay in memory (and are counted by [`len`](ExpiringMap::len)) until
+        /// they are overwritten or removed, or [`sweep_expired`](ExpiringMap::sweep_expired) is
+        /// called.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// use std::time::Duration;
+        /// let mut data = ExpiringMap::<dyn std::any::Any>::new();
+        /// data.insert_with_ttl("token", Duration::from_secs(0));
+        /// data.insert_with_ttl(42i32, Duration::from_secs(3600));
+        /// data.insert(1u8);
+        /// assert_eq!(data.get::<&str>(), None);
+        /// assert_eq!(data.get::<i32>(), Some(&42));
+        /// assert_eq!(data.sweep_expired(), 1);
+        /// assert_eq!(data.len(), 2);
+        /// ```
+        #[derive(Debug)]
+        pub struct ExpiringMap<A: ?Sized + Downcast = dyn Any> {
+            raw: HashMap<TypeId, ExpiringSlot<A>, BuildHasherDefault<TypeIdHasher>>,
+        }
+
+        impl<A: ?Sized + Downcast> Default for ExpiringMap<A> {
+            #[inline]
+            fn default() -> ExpiringMap<A> {
+                ExpiringMap::new()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> ExpiringMap<A> {
+            /// Create an empty collection.
+            #[inline]
+            pub fn new() -> ExpiringMap<A> {
+                ExpiringMap {
+                    raw: HashMap::with_hasher(Default::default()),
+                }
+            }
+
+            /// Returns the number of items in the collection, *including* expired items which have
+            /// not yet been swept.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.raw.len()
+            }
+
+            /// Returns true if there are no items in the collection, not even expired ones.
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.raw.is_empty()
+            }
+
+            /// Removes all items from the collection. Keeps the allocated memory for reuse.
+            #[inline]
+            pub fn clear(&mut self) {
+                self.raw.clear()
+            }
+
+            /// Returns a reference to the value stored in the collection for the type `T`,
+            /// if it exists and has not expired.
+            #[inline]
+            pub fn get<T: IntoBox<A>>(&self) -> Option<&T> {
+                let now = std::time::Instant::now();
+                self.raw.get(&TypeId::of::<T>())
+                    .filter(|slot| slot.is_live(now))
+                    .map(|slot| unsafe { slot.value.downcast_ref_unchecked::<T>() })
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists and has not expired.
+            #[inline]
+            pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+                let now = std::time::Instant::now();
+                self.raw.get_mut(&TypeId::of::<T>())
+                    .filter(|slot| slot.is_live(now))
+                    .map(|slot| unsafe { slot.value.downcast_mut_unchecked::<T>() })
+            }
+
+            fn insert_slot<T: IntoBox<A>>(&mut self, slot: ExpiringSlot<A>) -> Option<T> {
+                let now = std::time::Instant::now();
+                self.raw.insert(TypeId::of::<T>(), slot)
+                    .filter(|slot| slot.is_live(now))
+                    .map(|slot| unsafe { *slot.value.downcast_unchecked::<T>() })
+            }
+
+            /// Sets the value stored in the collection for the type `T`, never to expire.
+            /// If the collection already had an unexpired value of type `T`, that value is
+            /// returned. Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> {
+                self.insert_slot(ExpiringSlot {
+                    expires: None,
+                    value: value.into_box(),
+                })
+            }
+
+            /// Sets the value stored in the collection for the type `T`, to expire once `ttl`
+            /// has elapsed.
+            /// If the collection already had an unexpired value of type `T`, that value is
+            /// returned. Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert_with_ttl<T: IntoBox<A>>(
+                &mut self,
+                value: T,
+                ttl: std::time::Duration,
+            ) -> Option<T> {
+                self.insert_slot(ExpiringSlot {
+                    expires: Some(std::time::Instant::now() + ttl),
+                    value: value.into_box(),
+                })
+            }
+
+            /// Removes the `T` value from the collection,
+            /// returning it if there was an unexpired one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+                let now = std::time::Instant::now();
+                self.raw.remove(&TypeId::of::<T>())
+                    .filter(|slot| slot.is_live(now))
+                    .map(|slot| unsafe { *slot.value.downcast_unchecked::<T>() })
+            }
+
+            /// Returns true if the collection contains an unexpired value of type `T`.
+            #[inline]
+            pub fn contains<T: IntoBox<A>>(&self) -> bool {
+                let now = std::time::Instant::now();
+                self.raw.get(&TypeId::of::<T>()).map_or(false, |slot| slot.is_live(now))
+            }
+
+            /// Returns the time remaining before the value of type `T` expires, if it exists, has
+            /// not expired, and has a time to live.
+            #[inline]
+            pub fn ttl<T: IntoBox<A>>(&self) -> Option<std::time::Duration> {
+                let now = std::time::Instant::now();
+                self.raw.get(&TypeId::of::<T>())
+                    .and_then(|slot| slot.expires)
+                    .filter(|&expires| now < expires)
+                    .map(|expires| expires - now)
+            }
+
+            /// Drops all expired values, returning how many there were.
+            pub fn sweep_expired(&mut self) -> usize {
+                let now = std::time::Instant::now();
+                let len = self.raw.len();
+                self.raw.retain(|_, slot| slot.is_live(now));
+                len - self.raw.len()
+            }
+        }
+    };
+}
@@ -0,0 +1,125 @@
+//! `FallbackMap`, which asks a hook for values it doesn’t have.
+
+macro_rules! fallback_map {
+    ($example_use:literal) => {
+        /// A [`Map`] with a fallback hook, which [`get_or_fallback`](FallbackMap::get_or_fallback)
+        /// calls to supply a value when the map doesn’t have one, inserting what it returns.
+        ///
+        /// This lets a map be backed by some external registry or lazily-loaded configuration.
+        /// The map itself is reached through `Deref` and `DerefMut`; plain `get` (which can’t
+        /// insert, as it borrows the map immutably) doesn’t consult the hook.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// use std::any::{Any, TypeId};
+        /// struct Port(u16);
+        ///
+        /// let mut data = FallbackMap::<dyn Any>::new();
+        /// data.set_fallback(|type_id| if type_id == TypeId::of::<Port>() {
+        ///     Some(Box::new(Port(8080)))
+        /// } else {
+        ///     None
+        /// });
+        /// assert!(data.get::<Port>().is_none());
+        /// assert_eq!(data.get_or_fallback::<Port>().unwrap().0, 8080);
+        /// assert!(data.get::<Port>().is_some());
+        /// assert!(data.get_or_fallback::<String>().is_none());
+        /// ```
+        pub struct FallbackMap<A: ?Sized + Downcast = dyn Any> {
+            map: Map<A>,
+            fallback: Option<Box<dyn FnMut(TypeId) -> Option<Box<A>>>>,
+        }
+
+        impl<A: ?Sized + Downcast> core::fmt::Debug for FallbackMap<A>
+        where
+            Map<A>: core::fmt::Debug,
+        {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_struct("FallbackMap")
+                    .field("map", &self.map)
+                    .field("fallback", &self.fallback.is_some())
+                    .finish()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> Default for FallbackMap<A> {
+            #[inline]
+            fn default() -> FallbackMap<A> {
+                FallbackMap::new()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> From<Map<A>> for FallbackMap<A> {
+            #[inline]
+            fn from(map: Map<A>) -> FallbackMap<A> {
+                FallbackMap {
+                    map,
+                    fallback: None,
+                }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> core::ops::Deref for FallbackMap<A> {
+            type Target = Map<A>;
+
+            #[inline]
+            fn deref(&self) -> &Map<A> {
+                &self.map
+            }
+        }
+
+        impl<A: ?Sized + Downcast> core::ops::DerefMut for FallbackMap<A> {
+            #[inline]
+            fn deref_mut(&mut self) -> &mut Map<A> {
+                &mut self.map
+            }
+        }
+
+        impl<A: ?Sized + Downcast> FallbackMap<A> {
+            /// Create an empty collection with no fallback hook.
+            #[inline]
+            pub fn new() -> FallbackMap<A> {
+                Map::new().into()
+            }
+
+            /// Set the fallback hook, replacing any previous one.
+            ///
+            /// It is given the `TypeId` of the missing type, and may return a value of that type.
+            /// A value of any other type is discarded.
+            #[inline]
+            pub fn set_fallback<F>(&mut self, fallback: F)
+            where
+                F: FnMut(TypeId) -> Option<Box<A>> + 'static,
+            {
+                self.fallback = Some(Box::new(fallback));
+            }
+
+            /// Remove the fallback hook.
+            #[inline]
+            pub fn clear_fallback(&mut self) {
+                self.fallback = None;
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// first asking the fallback hook for one and inserting it if there isn’t one.
+            pub fn get_or_fallback<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+                let key = TypeId::of::<T>();
+                if !self.map.raw.contains_key(&key) {
+                    let value = self.fallback.as_mut().and_then(|fallback| fallback(key))?;
+                    if Downcast::type_id(&*value) != key {
+                        return None;
+                    }
+                    self.map.record_name::<T>();
+                    let _ = self.map.raw.insert(key, value);
+                }
+                self.map.get_mut::<T>()
+            }
+
+            /// Convert this into the plain map, dropping the fallback hook.
+            #[inline]
+            pub fn into_inner(self) -> Map<A> {
+                self.map
+            }
+        }
+    };
+}
@@ -0,0 +1,133 @@
+//! A C interface to a map, for sharing values between a host application and Rust plugins.
+//!
+//! C has no `TypeId`, so values are keyed by a 64-bit type tag chosen by the caller, and are
+//! opaque pointers, each with an optional function to drop it. Agreeing on tags (and on what type
+//! a pointer under each tag points to) is the callers’ responsibility.
+//!
+//! The corresponding C declarations are:
+//!
+//! ```c
+//! typedef struct AnyMapHandle AnyMapHandle;
+//!
+//! AnyMapHandle *anymap_new(void);
+//! bool anymap_insert(AnyMapHandle *handle, uint64_t type_tag, void *ptr, void (*drop_fn)(void *));
+//! void *anymap_get(const AnyMapHandle *handle, uint64_t type_tag);
+//! void anymap_free(AnyMapHandle *handle);
+//! ```
+//!
+//! This requires the ffi Cargo feature.
+
+use std::collections::HashMap;
+use std::os::raw::c_void;
+
+/// A function to drop a value stored in an [`AnyMapHandle`].
+pub type DropFn = unsafe extern "C" fn(*mut c_void);
+
+/// A map of opaque pointers keyed by type tag, for use through the `anymap_*` functions.
+#[derive(Debug, Default)]
+pub struct AnyMapHandle {
+    values: HashMap<u64, Value>,
+}
+
+#[derive(Debug)]
+struct Value {
+    ptr: *mut c_void,
+    drop_fn: Option<DropFn>,
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        if let Some(drop_fn) = self.drop_fn {
+            // SAFETY: anymap_insert’s caller promised that this is how to drop this pointer.
+            unsafe { drop_fn(self.ptr) }
+        }
+    }
+}
+
+/// Create an empty map, to be freed with [`anymap_free`].
+#[no_mangle]
+pub extern "C" fn anymap_new() -> *mut AnyMapHandle {
+    Box::into_raw(Box::new(AnyMapHandle::default()))
+}
+
+/// Store `ptr` in the map under `type_tag`, to be dropped with `drop_fn` (if not null) when it’s
+/// replaced or the map is freed.
+///
+/// If there was already a value under `type_tag`, it is dropped and true is returned.
+///
+/// # Safety
+///
+/// `handle` must have come from [`anymap_new`] and not yet been freed, and `drop_fn` must be safe
+/// to call on `ptr` exactly once, from whichever thread frees the map.
+#[no_mangle]
+pub unsafe extern "C" fn anymap_insert(
+    handle: *mut AnyMapHandle,
+    type_tag: u64,
+    ptr: *mut c_void,
+    drop_fn: Option<DropFn>,
+) -> bool {
+    (*handle).values.insert(type_tag, Value { ptr, drop_fn }).is_some()
+}
+
+/// Get the pointer stored in the map under `type_tag`, or null if there isn’t one.
+///
+/// The map keeps ownership of the value.
+///
+/// # Safety
+///
+/// `handle` must have come from [`anymap_new`] and not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn anymap_get(handle: *const AnyMapHandle, type_tag: u64) -> *mut c_void {
+    match (*handle).values.get(&type_tag) {
+        Some(value) => value.ptr,
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Free the map, dropping all of its values. A null `handle` is ignored.
+///
+/// # Safety
+///
+/// `handle` must be null, or have come from [`anymap_new`] and not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn anymap_free(handle: *mut AnyMapHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn drop_u32(ptr: *mut c_void) {
+        drop(Box::from_raw(ptr as *mut u32));
+        let _ = DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn boxed(value: u32) -> *mut c_void {
+        Box::into_raw(Box::new(value)) as *mut c_void
+    }
+
+    #[test]
+    fn test_ffi() {
+        unsafe {
+            let handle = anymap_new();
+            assert!(anymap_get(handle, 1).is_null());
+            assert!(!anymap_insert(handle, 1, boxed(10), Some(drop_u32)));
+            assert_eq!(*(anymap_get(handle, 1) as *const u32), 10);
+            assert!(anymap_insert(handle, 1, boxed(20), Some(drop_u32)));
+            assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+            assert_eq!(*(anymap_get(handle, 1) as *const u32), 20);
+
+            let mut not_owned = 30u32;
+            assert!(!anymap_insert(handle, 2, &mut not_owned as *mut u32 as *mut c_void, None));
+            anymap_free(handle);
+            assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+            anymap_free(std::ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! `FrozenMap`, a read-only map in a sorted slice.
+
+use core::any::{Any, TypeId};
+
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(all(not(feature = "std"), feature = "hashbrown"))]
+use alloc::vec::Vec;
+
+use crate::any::{Downcast, IntoBox};
+
+/// A read-only collection like [`Map`](crate::Map), from `Map::freeze`.
+///
+/// The values are kept in a single slice, sorted by `TypeId`, and found by binary search: for
+/// maps which are built once, at startup, and then only read, this is compact and cache-friendly,
+/// and reading never allocates.
+#[derive(Debug)]
+pub struct FrozenMap<A: ?Sized + Downcast = dyn Any> {
+    entries: Box<[(TypeId, Box<A>)]>,
+}
+
+/// The most common type of `FrozenMap`: just using `Any`;
+/// <code>[FrozenMap]&lt;dyn [Any]&gt;</code>.
+pub type FrozenAnyMap = FrozenMap<dyn Any>;
+
+// #[derive(Clone)] would want A to implement Clone, but in reality only Box<A> can.
+impl<A: ?Sized + Downcast> Clone for FrozenMap<A> where Box<A>: Clone {
+    #[inline]
+    fn clone(&self) -> FrozenMap<A> {
+        FrozenMap {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<A: ?Sized + Downcast> FrozenMap<A> {
+    /// Freeze the given entries, whose keys must match their values’ types and be distinct.
+    #[cfg(any(feature = "std", feature = "hashbrown"))]
+    pub(crate) fn new(mut entries: Vec<(TypeId, Box<A>)>) -> FrozenMap<A> {
+        entries.sort_unstable_by_key(|&(key, _)| key);
+        FrozenMap {
+            entries: entries.into_boxed_slice(),
+        }
+    }
+
+    /// Returns the number of items in the collection.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if there are no items in the collection.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns a reference to the value stored in the collection for the type `T`,
+    /// if it exists.
+    #[inline]
+    pub fn get<T: IntoBox<A>>(&self) -> Option<&T> {
+        self.entries.binary_search_by_key(&TypeId::of::<T>(), |&(key, _)| key)
+            .ok()
+            .map(|i| unsafe { self.entries[i].1.downcast_ref_unchecked::<T>() })
+    }
+
+    /// Returns true if the collection contains a value of type `T`.
+    #[inline]
+    pub fn contains<T: IntoBox<A>>(&self) -> bool {
+        self.entries.binary_search_by_key(&TypeId::of::<T>(), |&(key, _)| key).is_ok()
+    }
+
+    /// An iterator visiting the `TypeId`s of all the values in the collection, in order.
+    #[inline]
+    pub fn type_ids(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.entries.iter().map(|&(key, _)| key)
+    }
+}
+
+// FrozenMap::new, which these use, is only there for a backend’s Map::freeze.
+#[cfg(all(test, any(feature = "std", feature = "hashbrown")))]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "std"))]
+    use alloc::vec;
+
+    #[derive(Clone, Debug, PartialEq)] struct A(i32);
+    #[derive(Clone, Debug, PartialEq)] struct B(i32);
+    #[derive(Clone, Debug, PartialEq)] struct C(i32);
+
+    #[test]
+    fn test_frozen_map() {
+        let map = FrozenAnyMap::new(vec![
+            (TypeId::of::<B>(), Box::new(B(2)) as Box<dyn Any>),
+            (TypeId::of::<A>(), Box::new(A(1))),
+        ]);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get::<A>(), Some(&A(1)));
+        assert_eq!(map.get::<B>(), Some(&B(2)));
+        assert_eq!(map.get::<C>(), None);
+        assert!(map.contains::<A>() && !map.contains::<C>());
+        let mut keys = [TypeId::of::<A>(), TypeId::of::<B>()];
+        keys.sort();
+        assert!(map.type_ids().eq(keys.iter().cloned()));
+    }
+}
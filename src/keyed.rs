@@ -0,0 +1,116 @@
+//! `Key`, for storing values under a key type rather than their own type, in the manner of the
+//! typemap and type-map crates.
+
+use core::any::Any;
+use core::fmt;
+use core::marker::PhantomData;
+
+/// A key type, naming the type of the value stored under it, as in the typemap crate.
+///
+/// Code written against `typemap::Key` can move over by implementing this instead (it has the
+/// same shape), and using `Map::get_keyed` and friends in place of `TypeMap::get` and friends.
+pub trait Key: Any {
+    /// The type of the value stored under this key.
+    type Value: Any;
+}
+
+/// The value stored under the key `K`. This keeps the map’s keys matching its values’ types: a
+/// value is stored as a `Keyed<K>`, not as itself, so that `K` and `K::Value` don’t collide.
+#[doc(hidden)]
+pub struct Keyed<K: Key> {
+    pub(crate) value: K::Value,
+    _key: PhantomData<fn() -> K>,
+}
+
+impl<K: Key> Keyed<K> {
+    #[inline]
+    pub(crate) fn new(value: K::Value) -> Keyed<K> {
+        Keyed { value, _key: PhantomData }
+    }
+}
+
+// #[derive(Clone)] would want K: Clone, which it needn’t.
+impl<K: Key> Clone for Keyed<K> where K::Value: Clone {
+    #[inline]
+    fn clone(&self) -> Keyed<K> {
+        Keyed::new(self.value.clone())
+    }
+}
+
+impl<K: Key> fmt::Debug for Keyed<K> where K::Value: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+macro_rules! keyed_map {
+    ($example_init:literal) => {
+        impl<A: ?Sized + Downcast> Map<A> {
+            /// Returns a reference to the value stored in the collection under the key type `K`,
+            /// if it exists.
+            ///
+            /// Values stored by key don’t collide with values stored by type, even of the same
+            /// type.
+            ///
+            /// ```rust
+            /// struct Port;
+            /// impl anymap::Key for Port {
+            ///     type Value = u16;
+            /// }
+            ///
+            #[doc = $example_init]
+            /// data.insert_keyed::<Port>(8080);
+            /// data.insert(1u16);
+            /// assert_eq!(data.get_keyed::<Port>(), Some(&8080));
+            /// assert_eq!(data.get::<u16>(), Some(&1));
+            /// ```
+            #[inline]
+            pub fn get_keyed<K: crate::Key>(&self) -> Option<&K::Value>
+            where
+                crate::keyed::Keyed<K>: IntoBox<A>,
+            {
+                self.get::<crate::keyed::Keyed<K>>().map(|keyed| &keyed.value)
+            }
+
+            /// Returns a mutable reference to the value stored in the collection under the key
+            /// type `K`, if it exists.
+            #[inline]
+            pub fn get_keyed_mut<K: crate::Key>(&mut self) -> Option<&mut K::Value>
+            where
+                crate::keyed::Keyed<K>: IntoBox<A>,
+            {
+                self.get_mut::<crate::keyed::Keyed<K>>().map(|keyed| &mut keyed.value)
+            }
+
+            /// Sets the value stored in the collection under the key type `K`.
+            /// If the collection already had a value under `K`, that value is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert_keyed<K: crate::Key>(&mut self, value: K::Value) -> Option<K::Value>
+            where
+                crate::keyed::Keyed<K>: IntoBox<A>,
+            {
+                self.insert(crate::keyed::Keyed::<K>::new(value)).map(|keyed| keyed.value)
+            }
+
+            /// Removes the value stored under the key type `K` from the collection,
+            /// returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove_keyed<K: crate::Key>(&mut self) -> Option<K::Value>
+            where
+                crate::keyed::Keyed<K>: IntoBox<A>,
+            {
+                self.remove::<crate::keyed::Keyed<K>>().map(|keyed| keyed.value)
+            }
+
+            /// Returns true if the collection contains a value under the key type `K`.
+            #[inline]
+            pub fn contains_keyed<K: crate::Key>(&self) -> bool
+            where
+                crate::keyed::Keyed<K>: IntoBox<A>,
+            {
+                self.contains::<crate::keyed::Keyed<K>>()
+            }
+        }
+    };
+}
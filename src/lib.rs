@@ -5,7 +5,8 @@
 //! # Cargo features
 //!
 //! This crate has two independent features, each of which provides an implementation providing
-//! types `Map`, `AnyMap`, `OccupiedEntry`, `VacantEntry`, `Entry` and `RawMap`:
+//! types `Map`, `AnyMap`, `OccupiedEntry`, `VacantEntry`, `Entry` and `RawMap`, and the other
+//! collections built the same way, like `RcMap`:
 //!
 #![cfg_attr(feature = "std", doc = " - **std** (default, *enabled* in this build):")]
 #![cfg_attr(not(feature = "std"), doc = " - **std** (default, *disabled* in this build):")]
@@ -16,6 +17,42 @@
 #![cfg_attr(not(feature = "hashbrown"), doc = " - **hashbrown** (optional; *disabled* in this build):")]
 //!   an implementation using `alloc` and `hashbrown::hash_map`, placed in a module `hashbrown`
 //!   (e.g. `anymap::hashbrown::AnyMap`).
+//!
+//! There are also features that add functionality to those implementations:
+//!
+#![cfg_attr(feature = "type-names", doc = " - **type-names** (optional; *enabled* in this build):")]
+#![cfg_attr(not(feature = "type-names"), doc = " - **type-names** (optional; *disabled* in this build):")]
+//!   records the name of each type put in a `Map`, for `Map::type_names`, and adds the
+//!   `ByTypeName` key mode for `StableMap`.
+//!   This requires Rust 1.38.0.
+//!
+#![cfg_attr(feature = "registration", doc = " - **registration** (optional; *enabled* in this build):")]
+#![cfg_attr(not(feature = "registration"), doc = " - **registration** (optional; *disabled* in this build):")]
+//!   adds the `register!` macro, for crates to contribute values to `AnyMap::with_registered`.
+//!   This implies **std**, and requires Rust 1.37.0.
+//!
+#![cfg_attr(feature = "ffi", doc = " - **ffi** (optional; *enabled* in this build):")]
+#![cfg_attr(not(feature = "ffi"), doc = " - **ffi** (optional; *disabled* in this build):")]
+//!   adds the `ffi` module, a C interface for sharing values keyed by caller-supplied type tags.
+//!   This implies **std**.
+//!
+#![cfg_attr(feature = "track-caller", doc = " - **track-caller** (optional; *enabled* in this build):")]
+#![cfg_attr(not(feature = "track-caller"), doc = " - **track-caller** (optional; *disabled* in this build):")]
+//!   marks the methods that can panic, like `Map::with_mut2` and `Map::entry_many`, with
+//!   `#[track_caller]`, so that their panics report the location of the call rather than a line
+//!   in this crate.
+//!   This requires Rust 1.46.0.
+//!
+#![cfg_attr(feature = "try-reserve", doc = " - **try-reserve** (optional; *enabled* in this build):")]
+#![cfg_attr(not(feature = "try-reserve"), doc = " - **try-reserve** (optional; *disabled* in this build):")]
+//!   adds the methods whose allocations are all fallible, like `Map::try_reserve` and
+//!   `Map::insert_fallible`, to the std implementation (the hashbrown one always has them).
+//!   This implies **std**, and requires Rust 1.57.0.
+//!
+#![cfg_attr(feature = "debug-validate", doc = " - **debug-validate** (optional; *enabled* in this build):")]
+#![cfg_attr(not(feature = "debug-validate"), doc = " - **debug-validate** (optional; *disabled* in this build):")]
+//!   keeps `Map::debug_validate` in builds without debug assertions, where it is otherwise left
+//!   out.
 
 #![warn(missing_docs, unused_results)]
 
@@ -27,29 +64,412 @@ use core::hash::Hasher;
 #[cfg(not(feature = "std"))]
 extern crate alloc;
 
-pub use crate::any::CloneAny;
+/// Items used by exported macros. Not for use directly.
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(not(feature = "std"))]
+    pub use alloc::boxed::Box;
+    #[cfg(feature = "std")]
+    pub use std::boxed::Box;
+}
+
+#[cfg(feature = "std")]
+pub use crate::actor::{AnyMapActor, Reply, SendMap};
+pub use crate::any::{CloneAny, CoerceFrom, Downcast, IntoBox};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::budget::{HeapSize, OverBudget};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::counted::AccessCounts;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::errors::{
+    CloneError, MissingTypeError, OccupiedError, OpsError, TryReserveError, TryReserveErrorKind,
+};
+#[cfg(all(
+    any(feature = "std", feature = "hashbrown"),
+    any(debug_assertions, feature = "debug-validate"),
+))]
+pub use crate::errors::KeyMismatch;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::tuple::HasType;
+#[cfg(feature = "std")]
+pub use crate::observe::Event;
+pub use crate::frozen::{FrozenAnyMap, FrozenMap};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::keyed::Key;
+pub use crate::ordered::{OrderedAnyMap, OrderedMap, RawOrderedMap};
+pub use crate::persistent::{PersistentAnyMap, PersistentMap};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::policy::{Admission, Allowlist, MaxTypes, Policy};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::presence::TypeBit;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::manifest::{Manifest, ManifestError};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::stats::{SimulatedStats, Stats};
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub use crate::schema::{Schema, SchemaError, TypeInfo};
+pub use crate::stable::{ByUuid, StableKey, StableTypeId, TypeUuid};
+#[cfg(feature = "type-names")]
+pub use crate::stable::ByTypeName;
 
+#[cfg(feature = "std")]
+mod actor;
 mod any;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod budget;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod bus;
+#[cfg(feature = "std")]
+#[macro_use]
+mod channels;
+#[cfg(feature = "std")]
+#[macro_use]
+mod cold;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod counted;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub mod errors;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod fallback;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod frozen;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+pub(crate) mod keyed;
+mod macros;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+mod manifest;
+mod ordered;
+#[cfg(feature = "std")]
+#[macro_use]
+pub mod persist;
+mod persistent;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod ops;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod policy;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod presence;
+#[cfg(feature = "std")]
+#[macro_use]
+mod pool;
+#[cfg(feature = "registration")]
+#[doc(hidden)]
+pub mod registry;
+#[macro_use]
+mod stable;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod shared;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod tracked;
+#[cfg(feature = "std")]
+#[macro_use]
+mod expiring;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod meta;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod tuple;
+#[cfg(feature = "std")]
+#[macro_use]
+mod observe;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod cast;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod named;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+mod stats;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+mod schema;
+#[cfg(feature = "std")]
+#[macro_use]
+mod rwlock;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+#[macro_use]
+mod scoped;
+
+/// Implement `From<Map<$from>> for Map<$to>` for each of the pairs given, in terms of `Upcast`.
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+macro_rules! impl_from_flavour {
+    ($($from:ty => $($to:ty),+;)*) => {
+        $($(
+            impl From<Map<$from>> for Map<$to> {
+                #[inline]
+                fn from(map: Map<$from>) -> Map<$to> {
+                    map.upcast()
+                }
+            }
+        )+)*
+    };
+}
 
 #[cfg(any(feature = "std", feature = "hashbrown"))]
 macro_rules! everything {
-    ($example_init:literal, $($parent:ident)::+ $(, $entry_generics:ty)?) => {
+    (
+        $example_init:literal,
+        $example_use:literal,
+        $($parent:ident)::+
+        $(, $entry_generics:ty)?
+    ) => {
         use core::any::{Any, TypeId};
         use core::hash::BuildHasherDefault;
         use core::marker::PhantomData;
 
         #[cfg(not(feature = "std"))]
-        use alloc::boxed::Box;
+        use alloc::{boxed::Box, vec::Vec};
 
         use ::$($parent)::+::hash_map::{self, HashMap};
 
-        use crate::any::{Downcast, IntoBox};
+        // A glob, because the crate root already has these through its re-exports.
+        #[allow(unused_imports)]
+        use crate::any::*;
 
         /// Raw access to the underlying `HashMap`.
         ///
         /// This alias is provided for convenience because of the ugly third generic parameter.
         pub type RawMap<A> = HashMap<TypeId, Box<A>, BuildHasherDefault<TypeIdHasher>>;
 
+        /// Extra methods for [`RawMap`], which keep its keys matching its values’ types.
+        ///
+        /// A raw map filled only with `insert_checked` (or with `Extend` on a `Map`) satisfies the
+        /// safety requirements of `Map::from_raw` and `Map::as_raw_mut`, with no need to supply
+        /// keys.
+        pub trait RawMapExt<A: ?Sized + Downcast> {
+            /// Insert a value, keyed by its own type.
+            /// If the map already had a value of that type, that value is returned.
+            /// Otherwise, `None` is returned.
+            fn insert_checked(&mut self, value: Box<A>) -> Option<Box<A>>;
+
+            /// Returns a reference to the value stored for the type `T`, if it exists and really
+            /// is of type `T`.
+            fn get_typed<T: IntoBox<A>>(&self) -> Option<&T>;
+
+            /// Returns a mutable reference to the value stored for the type `T`, if it exists and
+            /// really is of type `T`.
+            fn get_typed_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T>;
+
+            /// Removes the value stored for the type `T`, returning it if it existed and really was
+            /// of type `T`. (A value of another type stored there is left alone.)
+            fn remove_typed<T: IntoBox<A>>(&mut self) -> Option<T>;
+
+            /// Returns an iterator which removes and yields each entry for which `pred` returns
+            /// true, lazily, as it is advanced.
+            ///
+            /// Entries not reached because the iterator is dropped early are kept. (The keys are
+            /// gathered up front, so entries inserted during the iteration are not visited.)
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            /// struct Transient;
+            /// let mut raw = RawMap::<dyn std::any::Any>::default();
+            /// raw.insert_checked(Box::new(Transient));
+            /// raw.insert_checked(Box::new(42i32));
+            /// let extracted: Vec<_> = raw.extract_if(|_, value| value.is::<Transient>()).collect();
+            /// assert_eq!(extracted.len(), 1);
+            /// assert_eq!(raw.len(), 1);
+            /// ```
+            fn extract_if<F: FnMut(&TypeId, &mut A) -> bool>(&mut self, pred: F) -> ExtractIf<'_, A, F>;
+
+            /// Returns a cursor over the map’s entries, which can remove the current entry without
+            /// disturbing the traversal. It starts before the first entry.
+            ///
+            /// The hash table has no stable positions to hold on to, so the cursor gathers the keys
+            /// up front, as `extract_if` does; entries inserted meanwhile are not visited.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            /// let mut raw = RawMap::<dyn std::any::Any>::default();
+            /// raw.insert_checked(Box::new(1u8));
+            /// raw.insert_checked(Box::new(2u16));
+            /// let mut cursor = raw.cursor_mut();
+            /// while cursor.move_next() {
+            ///     let (_, value) = cursor.current().unwrap();
+            ///     if let Some(n) = value.downcast_mut::<u16>() {
+            ///         *n += 1;
+            ///     } else {
+            ///         cursor.remove_current();
+            ///     }
+            /// }
+            /// assert_eq!(raw.get_typed::<u16>(), Some(&3));
+            /// assert_eq!(raw.len(), 1);
+            /// ```
+            fn cursor_mut(&mut self) -> CursorMut<'_, A>;
+
+            /// Returns mutable references to the values stored under each of the given keys, in
+            /// order, or `None` if any of them is missing or appears more than once.
+            ///
+            /// This is like the `get_disjoint_mut` of newer versions of std’s `HashMap`, but takes
+            /// a slice whose length needn’t be known at compile time, and doesn’t panic on
+            /// duplicates.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            /// use std::any::TypeId;
+            /// let mut raw = RawMap::<dyn std::any::Any>::default();
+            /// raw.insert_checked(Box::new(1u8));
+            /// raw.insert_checked(Box::new(2u16));
+            /// let keys = [TypeId::of::<u8>(), TypeId::of::<u16>()];
+            /// for value in raw.get_each_mut(&keys).unwrap() {
+            ///     if let Some(n) = value.downcast_mut::<u8>() {
+            ///         *n += 1;
+            ///     }
+            /// }
+            /// assert_eq!(raw.get_typed::<u8>(), Some(&2));
+            /// assert!(raw.get_each_mut(&[keys[0], keys[0]]).is_none());
+            /// ```
+            fn get_each_mut(&mut self, keys: &[TypeId]) -> Option<Vec<&mut A>>;
+        }
+
+        impl<A: ?Sized + Downcast> RawMapExt<A> for RawMap<A> {
+            #[inline]
+            fn insert_checked(&mut self, value: Box<A>) -> Option<Box<A>> {
+                self.insert(Downcast::type_id(&*value), value)
+            }
+
+            #[inline]
+            fn get_typed<T: IntoBox<A>>(&self) -> Option<&T> {
+                self.get(&TypeId::of::<T>())
+                    .filter(|any| Downcast::type_id(&***any) == TypeId::of::<T>())
+                    .map(|any| unsafe { any.downcast_ref_unchecked::<T>() })
+            }
+
+            #[inline]
+            fn get_typed_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+                self.get_mut(&TypeId::of::<T>())
+                    .filter(|any| Downcast::type_id(&***any) == TypeId::of::<T>())
+                    .map(|any| unsafe { any.downcast_mut_unchecked::<T>() })
+            }
+
+            #[inline]
+            fn extract_if<F: FnMut(&TypeId, &mut A) -> bool>(&mut self, pred: F) -> ExtractIf<'_, A, F> {
+                ExtractIf {
+                    cursor: self.cursor_mut(),
+                    pred,
+                }
+            }
+
+            #[inline]
+            fn cursor_mut(&mut self) -> CursorMut<'_, A> {
+                CursorMut {
+                    keys: self.keys().cloned().collect::<Vec<_>>().into_iter(),
+                    raw: self,
+                    current: None,
+                }
+            }
+
+            fn get_each_mut(&mut self, keys: &[TypeId]) -> Option<Vec<&mut A>> {
+                if keys.iter().enumerate().any(|(i, key)| keys[..i].contains(key)) {
+                    return None;
+                }
+                let mut values = Vec::with_capacity(keys.len());
+                for key in keys {
+                    values.push(&mut **self.get_mut(key)? as *mut A);
+                }
+                // SAFETY: the keys are distinct, so these point into different boxes, which stay
+                // put while the map is borrowed.
+                Some(values.into_iter().map(|value| unsafe { &mut *value }).collect())
+            }
+
+            #[inline]
+            fn remove_typed<T: IntoBox<A>>(&mut self) -> Option<T> {
+                let _ = self.get_typed::<T>()?;
+                self.remove(&TypeId::of::<T>()).map(|any| unsafe { *any.downcast_unchecked::<T>() })
+            }
+        }
+
+        /// The iterator returned by [`RawMapExt::extract_if`].
+        pub struct ExtractIf<'a, A: ?Sized + Downcast, F> {
+            cursor: CursorMut<'a, A>,
+            pred: F,
+        }
+
+        impl<'a, A: ?Sized + Downcast, F: FnMut(&TypeId, &mut A) -> bool> Iterator for ExtractIf<'a, A, F> {
+            type Item = (TypeId, Box<A>);
+
+            fn next(&mut self) -> Option<(TypeId, Box<A>)> {
+                while self.cursor.move_next() {
+                    let chosen = match self.cursor.current() {
+                        Some((key, value)) => (self.pred)(&key, value),
+                        None => false,
+                    };
+                    if chosen {
+                        return self.cursor.remove_current();
+                    }
+                }
+                None
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (0, self.cursor.keys.size_hint().1)
+            }
+        }
+
+        // #[derive(Debug)] would want F to implement Debug.
+        impl<'a, A: ?Sized + Downcast, F> core::fmt::Debug for ExtractIf<'a, A, F> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_struct("ExtractIf")
+                    .field("cursor", &self.cursor)
+                    .finish()
+            }
+        }
+
+        /// A cursor over the entries of a [`RawMap`], from [`RawMapExt::cursor_mut`].
+        pub struct CursorMut<'a, A: ?Sized + Downcast> {
+            raw: &'a mut RawMap<A>,
+            keys: <Vec<TypeId> as IntoIterator>::IntoIter,
+            current: Option<TypeId>,
+        }
+
+        // The values are left out, as A needn’t implement Debug.
+        impl<'a, A: ?Sized + Downcast> core::fmt::Debug for CursorMut<'a, A> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_struct("CursorMut")
+                    .field("current", &self.current)
+                    .field("remaining", &self.keys.len())
+                    .finish()
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast> CursorMut<'a, A> {
+            /// Move to the next entry, returning false (and pointing at nothing) if there are no
+            /// more.
+            pub fn move_next(&mut self) -> bool {
+                let raw = &*self.raw;
+                self.current = self.keys.by_ref().find(|key| raw.contains_key(key));
+                self.current.is_some()
+            }
+
+            /// The current entry’s key and value, or `None` if the cursor points at nothing,
+            /// because it hasn’t started, has finished, or has just removed the entry.
+            #[inline]
+            pub fn current(&mut self) -> Option<(TypeId, &mut A)> {
+                let key = self.current?;
+                self.raw.get_mut(&key).map(|value| (key, &mut **value))
+            }
+
+            /// Remove the current entry and return it, leaving the cursor pointing at nothing until
+            /// the next `move_next`.
+            #[inline]
+            pub fn remove_current(&mut self) -> Option<(TypeId, Box<A>)> {
+                let key = self.current.take()?;
+                self.raw.remove(&key).map(|value| (key, value))
+            }
+        }
+
         /// A collection containing zero or one values for any given type and allowing convenient,
         /// type-safe access to those values.
         ///
@@ -60,8 +480,11 @@ macro_rules! everything {
         ///   that, you can only add types that implement `Clone` to the map.
         /// - You can add on `+ Send` or `+ Send + Sync` (e.g. `Map<dyn Any + Send>`) to add those
         ///   auto traits.
+        /// - With the std feature, you can also add on `+ UnwindSafe + RefUnwindSafe` (from
+        ///   `std::panic`), so that the map can be used across `catch_unwind`.
         ///
-        /// Cumulatively, there are thus six forms of map:
+        /// Cumulatively, there are thus six forms of map, and with the std feature, twelve: each
+        /// of these, with or without `+ UnwindSafe + RefUnwindSafe`.
         ///
         /// - <code>[Map]&lt;dyn [core::any::Any]&gt;</code>,
         ///   also spelled [`AnyMap`] for convenience.
@@ -97,9 +520,29 @@ macro_rules! everything {
         /// ```
         ///
         /// Values containing non-static references are not permitted.
-        #[derive(Debug)]
+        ///
+        /// Values are always stored as `Box<A>`. For `Rc<A>` or `Arc<A>`, with cheap shared handles
+        /// to the values, use [`RcMap`] or [`ArcMap`] instead (which a `Map` converts into).
         pub struct Map<A: ?Sized + Downcast = dyn Any> {
             raw: RawMap<A>,
+            /// The names of types that have been put in the map by type (as distinct from through
+            /// the raw map). Never wrong, but may have extraneous entries or be missing some.
+            #[cfg(feature = "type-names")]
+            names: HashMap<TypeId, &'static str, BuildHasherDefault<TypeIdHasher>>,
+            /// How to clone values of the types registered with `register_clone` or
+            /// `insert_clonable`; allocated only once one is, so as to cost other maps nothing.
+            clone_fns: Option<Box<HashMap<TypeId, CloneFn<A>, BuildHasherDefault<TypeIdHasher>>>>,
+        }
+
+        // #[derive(Debug)] would show the clone functions, which are bookkeeping.
+        impl<A: ?Sized + Downcast> core::fmt::Debug for Map<A> where A: core::fmt::Debug {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                let mut map = f.debug_struct("Map");
+                let _ = map.field("raw", &self.raw);
+                #[cfg(feature = "type-names")]
+                let _ = map.field("names", &self.names);
+                map.finish()
+            }
         }
 
         // #[derive(Clone)] would want A to implement Clone, but in reality only Box<A> can.
@@ -108,7 +551,39 @@ macro_rules! everything {
             fn clone(&self) -> Map<A> {
                 Map {
                     raw: self.raw.clone(),
+                    #[cfg(feature = "type-names")]
+                    names: self.names.clone(),
+                    clone_fns: self.clone_fns.clone(),
+                }
+            }
+        }
+
+        /// A function cloning a value of one particular type, as recorded by
+        /// `Map::register_clone`.
+        struct CloneFn<A: ?Sized>(fn(&A) -> Box<A>);
+
+        impl<A: ?Sized + Downcast> CloneFn<A> {
+            fn of<T: IntoBox<A> + Clone>() -> CloneFn<A> {
+                fn clone_as<T: IntoBox<A> + Clone, A: ?Sized + Downcast>(value: &A) -> Box<A> {
+                    // SAFETY: clone functions are only ever used on values under their own
+                    // type’s key, which (as for all map operations) must match the value’s type.
+                    unsafe { value.downcast_ref_unchecked::<T>() }.clone().into_box()
                 }
+                CloneFn(clone_as::<T, A>)
+            }
+        }
+
+        // #[derive] would want A to implement these.
+        impl<A: ?Sized> Clone for CloneFn<A> {
+            #[inline]
+            fn clone(&self) -> CloneFn<A> {
+                CloneFn(self.0)
+            }
+        }
+
+        impl<A: ?Sized> core::fmt::Debug for CloneFn<A> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.pad("CloneFn")
             }
         }
 
@@ -130,16 +605,66 @@ macro_rules! everything {
             /// Create an empty collection.
             #[inline]
             pub fn new() -> Map<A> {
-                Map {
-                    raw: RawMap::with_hasher(Default::default()),
-                }
+                Map::wrap(RawMap::with_hasher(Default::default()))
             }
 
             /// Creates an empty collection with the given initial capacity.
             #[inline]
             pub fn with_capacity(capacity: usize) -> Map<A> {
+                Map::wrap(RawMap::with_capacity_and_hasher(capacity, Default::default()))
+            }
+
+            /// Creates an empty collection with room for the types in the tuple `T`, so that
+            /// inserting them allocates once, up front, and never rehashes.
+            ///
+            /// (There is no hashing to do ahead of time: the map’s hasher just takes the bits of the
+            /// `TypeId`.)
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            /// let mut data = AnyMap::with_types::<(u8, u16, String)>();
+            /// let capacity = data.capacity();
+            /// data.insert_all((1u8, 2u16, String::from("three")));
+            /// assert_eq!(data.capacity(), capacity);
+            /// ```
+            #[inline]
+            pub fn with_types<T: TypeTuple<A>>() -> Map<A> {
+                Map::with_capacity(T::LEN)
+            }
+
+            /// Wrap a raw map, which must satisfy the invariants of `from_raw`.
+            #[inline]
+            fn wrap(raw: RawMap<A>) -> Map<A> {
                 Map {
-                    raw: RawMap::with_capacity_and_hasher(capacity, Default::default()),
+                    raw,
+                    #[cfg(feature = "type-names")]
+                    names: HashMap::with_hasher(Default::default()),
+                    clone_fns: None,
+                }
+            }
+
+            /// Remember the name of the type `T`, if the type-names feature is enabled.
+            #[inline]
+            fn record_name<T: 'static>(&mut self) {
+                #[cfg(feature = "type-names")]
+                #[allow(clippy::incompatible_msrv)]  // The type-names feature requires 1.38.
+                {
+                    let _ = self.names.entry(TypeId::of::<T>())
+                        .or_insert_with(core::any::type_name::<T>);
+                }
+            }
+
+            /// The name of the type with the given `TypeId`, if it’s known.
+            #[inline]
+            fn name_of(&self, type_id: TypeId) -> Option<&'static str> {
+                #[cfg(feature = "type-names")]
+                {
+                    self.names.get(&type_id).cloned()
+                }
+                #[cfg(not(feature = "type-names"))]
+                {
+                    let _ = type_id;
+                    None
                 }
             }
 
@@ -169,8 +694,24 @@ macro_rules! everything {
                 self.raw.shrink_to_fit()
             }
 
+            /// Returns statistics about how full the hash table is.
+            #[inline]
+            pub fn stats(&self) -> crate::Stats {
+                crate::Stats::new(self.raw.len(), self.raw.capacity())
+            }
+
+            /// Returns statistics about how the keys would be spread across a hash table like
+            /// this one, to check that the `TypeId` hasher is behaving well on a particular
+            /// population of types. See [`SimulatedStats`](crate::SimulatedStats) for how far
+            /// that simulation goes.
+            ///
+            /// This walks the whole collection, so it isn’t cheap.
+            pub fn simulate_stats(&self) -> crate::SimulatedStats {
+                crate::SimulatedStats::new(self.raw.keys(), self.raw.capacity())
+            }
+
             // Additional stable methods (as of 1.60.0-nightly) that could be added:
-            // try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError>    (1.57.0)
+            // (try_reserve, from 1.57.0, is added by the try-reserve feature.)
             // shrink_to(&mut self, min_capacity: usize)                                   (1.56.0)
 
             /// Returns the number of items in the collection.
@@ -185,10 +726,50 @@ macro_rules! everything {
                 self.raw.is_empty()
             }
 
-            /// Removes all items from the collection. Keeps the allocated memory for reuse.
+            /// Removes all items from the collection. Keeps the allocated memory for reuse, and
+            /// the types registered with [`register_clone`](Map::register_clone).
             #[inline]
             pub fn clear(&mut self) {
-                self.raw.clear()
+                self.raw.clear();
+                #[cfg(feature = "type-names")]
+                self.names.clear();
+            }
+
+            /// An iterator visiting the `TypeId`s of all the values in the collection, in arbitrary
+            /// order.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// data.insert(42i32);
+            /// assert!(data.type_ids().eq([std::any::TypeId::of::<i32>()].iter().cloned()));
+            /// ```
+            #[inline]
+            pub fn type_ids(&self) -> impl Iterator<Item = TypeId> + '_ {
+                self.raw.keys().cloned()
+            }
+
+            /// An iterator visiting the names of the types of all the values in the collection,
+            /// in arbitrary order.
+            ///
+            /// The names are as given by [`core::any::type_name`], so they are for diagnostics
+            /// only, not to be relied upon. Values put in through the raw map or `Extend` rather
+            /// than by type have no name recorded, and are skipped.
+            ///
+            /// This requires the type-names Cargo feature.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// data.insert(42i32);
+            /// data.insert(String::new());
+            /// let mut names: Vec<_> = data.type_names().collect();
+            /// names.sort();
+            /// assert_eq!(names, ["alloc::string::String", "i32"]);
+            /// ```
+            #[cfg(feature = "type-names")]
+            #[inline]
+            pub fn type_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+                let names = &self.names;
+                self.raw.keys().filter_map(move |type_id| names.get(type_id).cloned())
             }
 
             /// Returns a reference to the value stored in the collection for the type `T`,
@@ -207,100 +788,810 @@ macro_rules! everything {
                     .map(|any| unsafe { any.downcast_mut_unchecked::<T>() })
             }
 
-            /// Sets the value stored in the collection for the type `T`.
-            /// If the collection already had a value of type `T`, that value is returned.
-            /// Otherwise, `None` is returned.
+            /// Returns a reference to the value stored in the collection for the type `T`, or an
+            /// error naming the type if there isn’t one, for use with `?`.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            /// struct Config { port: u16 }
+            ///
+            /// fn port(data: &AnyMap) -> Result<u16, anymap::MissingTypeError> {
+            ///     Ok(data.try_get::<Config>()?.port)
+            /// }
+            ///
+            #[doc = $example_init]
+            /// assert!(port(&data).is_err());
+            /// data.insert(Config { port: 80 });
+            /// assert_eq!(port(&data), Ok(80));
+            /// ```
             #[inline]
-            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> {
-                self.raw.insert(TypeId::of::<T>(), value.into_box())
-                    .map(|any| unsafe { *any.downcast_unchecked::<T>() })
+            pub fn try_get<T: IntoBox<A>>(&self) -> Result<&T, crate::MissingTypeError> {
+                self.get::<T>().ok_or_else(crate::MissingTypeError::of::<T>)
             }
 
-            // rustc 1.60.0-nightly has another method try_insert that would be nice when stable.
-
-            /// Removes the `T` value from the collection,
-            /// returning it if there was one or `None` if there was not.
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// or an error naming the type if there isn’t one, for use with `?`.
             #[inline]
-            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
-                self.raw.remove(&TypeId::of::<T>())
-                    .map(|any| *unsafe { any.downcast_unchecked::<T>() })
+            pub fn try_get_mut<T: IntoBox<A>>(&mut self) -> Result<&mut T, crate::MissingTypeError> {
+                self.get_mut::<T>().ok_or_else(crate::MissingTypeError::of::<T>)
             }
 
-            /// Returns true if the collection contains a value of type `T`.
+            /// Returns a reference to the value stored in the collection for the type `T`, without
+            /// checking that there is one.
+            ///
+            /// This is for hot paths where presence has already been established, such as just
+            /// after `contains` or `conforms`; [`prove`](Map::prove) is a safe alternative.
+            ///
+            /// # Safety
+            ///
+            /// The collection must contain a value of type `T`, or *undefined behaviour* will occur.
             #[inline]
-            pub fn contains<T: IntoBox<A>>(&self) -> bool {
-                self.raw.contains_key(&TypeId::of::<T>())
+            pub unsafe fn get_unchecked<T: IntoBox<A>>(&self) -> &T {
+                match self.raw.get(&TypeId::of::<T>()) {
+                    Some(any) => any.downcast_ref_unchecked::<T>(),
+                    None => core::hint::unreachable_unchecked(),
+                }
             }
 
-            /// Gets the entry for the given type in the collection for in-place manipulation
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// without checking that there is one.
+            ///
+            /// # Safety
+            ///
+            /// The collection must contain a value of type `T`, or *undefined behaviour* will occur.
             #[inline]
-            pub fn entry<T: IntoBox<A>>(&mut self) -> Entry<A, T> {
-                match self.raw.entry(TypeId::of::<T>()) {
-                    hash_map::Entry::Occupied(e) => Entry::Occupied(OccupiedEntry {
-                        inner: e,
-                        type_: PhantomData,
-                    }),
-                    hash_map::Entry::Vacant(e) => Entry::Vacant(VacantEntry {
-                        inner: e,
-                        type_: PhantomData,
-                    }),
+            pub unsafe fn get_unchecked_mut<T: IntoBox<A>>(&mut self) -> &mut T {
+                match self.raw.get_mut(&TypeId::of::<T>()) {
+                    Some(any) => any.downcast_mut_unchecked::<T>(),
+                    None => core::hint::unreachable_unchecked(),
                 }
             }
 
-            /// Get access to the raw hash map that backs this.
+            /// Returns a pointer to the value stored in the collection for the type `T`, if it
+            /// exists, for holding on to between calls (say, across a C callback).
             ///
-            /// This will seldom be useful, but it’s conceivable that you could wish to iterate
-            /// over all the items in the collection, and this lets you do that.
+            /// Each value is boxed, so its address doesn’t change while it’s in the collection,
+            /// however the table itself grows or shrinks. The pointer may be read and written
+            /// through until the value is removed or replaced, or the collection is cleared or
+            /// dropped; but not while a reference to the same value is live.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// data.insert(1i32);
+            /// let ptr = data.get_ptr::<i32>().unwrap();
+            /// data.insert(2u8);
+            /// data.shrink_to_fit();
+            /// unsafe { *ptr.as_ptr() += 1; }
+            /// assert_eq!(data.get::<i32>(), Some(&2));
+            /// ```
             #[inline]
-            pub fn as_raw(&self) -> &RawMap<A> {
-                &self.raw
+            pub fn get_ptr<T: IntoBox<A>>(&mut self) -> Option<core::ptr::NonNull<T>> {
+                self.get_mut::<T>().map(core::ptr::NonNull::from)
             }
 
-            /// Get mutable access to the raw hash map that backs this.
-            ///
-            /// This will seldom be useful, but it’s conceivable that you could wish to iterate
-            /// over all the items in the collection mutably, or drain or something, or *possibly*
-            /// even batch insert, and this lets you do that.
-            ///
-            /// # Safety
+            /// Returns a pointer to the value stored in the collection with the given `TypeId`,
+            /// if it exists, as [`get_ptr`](Map::get_ptr) does but still as a trait object.
             ///
-            /// If you insert any values to the raw map, the key (a `TypeId`) must match the
-            /// value’s type, or *undefined behaviour* will occur when you access those values.
+            /// The same rules for using the pointer apply.
+            #[inline]
+            pub fn get_raw(&mut self, type_id: &TypeId) -> Option<core::ptr::NonNull<A>> {
+                self.raw.get_mut(type_id).map(|any| core::ptr::NonNull::from(&mut **any))
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// inserting `T::default()` first if there isn’t one.
             ///
-            /// (*Removing* entries is perfectly safe.)
+            /// This is a shorthand for `map.entry::<T>().or_default()`.
             #[inline]
-            pub unsafe fn as_raw_mut(&mut self) -> &mut RawMap<A> {
-                &mut self.raw
+            pub fn get_mut_or_default<T: IntoBox<A> + Default>(&mut self) -> &mut T {
+                self.entry::<T>().or_default()
             }
 
-            /// Convert this into the raw hash map that backs this.
+            /// Calls `f` with a mutable reference to the value stored in the collection for the
+            /// type `T`, if it exists.
             ///
-            /// This will seldom be useful, but it’s conceivable that you could wish to consume all
-            /// the items in the collection and do *something* with some or all of them, and this
-            /// lets you do that, without the `unsafe` that `.as_raw_mut().drain()` would require.
+            /// Returns true if there was a value to modify, false otherwise. This is a shorthand
+            /// for `if let Some(value) = map.get_mut::<T>() { f(value) }`.
             #[inline]
-            pub fn into_raw(self) -> RawMap<A> {
-                self.raw
+            pub fn modify<T: IntoBox<A>, F: FnOnce(&mut T)>(&mut self, f: F) -> bool {
+                match self.get_mut::<T>() {
+                    Some(value) => {
+                        f(value);
+                        true
+                    },
+                    None => false,
+                }
             }
 
-            /// Construct a map from a collection of raw values.
+            /// Calls `f` with mutable references to the values stored in the collection for the
+            /// types `T` and `U`, if they both exist.
             ///
-            /// You know what? I can’t immediately think of any legitimate use for this, especially
-            /// because of the requirement of the `BuildHasherDefault<TypeIdHasher>` generic in the
-            /// map.
+            /// Returns the closure’s result, or `None` if either value was missing.
             ///
-            /// Perhaps this will be most practical as `unsafe { Map::from_raw(iter.collect()) }`,
-            /// `iter` being an iterator over `(TypeId, Box<A>)` pairs. Eh, this method provides
-            /// symmetry with `into_raw`, so I don’t care if literally no one ever uses it. I’m not
-            /// even going to write a test for it, it’s so trivial.
+            /// ```rust
+            #[doc = $example_init]
+            /// data.insert(1u8);
+            /// data.insert(2u16);
+            /// data.with_mut2(|a: &mut u8, b: &mut u16| *b += *a as u16);
+            /// assert_eq!(data.get(), Some(&3u16));
+            /// assert_eq!(data.with_mut2(|_: &mut u8, _: &mut u32| ()), None);
+            /// ```
             ///
-            /// # Safety
+            /// # Panics
             ///
-            /// For all entries in the raw map, the key (a `TypeId`) must match the value’s type,
-            /// or *undefined behaviour* will occur when you access that entry.
+            /// Panics if `T` and `U` are the same type.
+            #[inline]
+            #[cfg_attr(feature = "track-caller", track_caller)]
+            pub fn with_mut2<T, U, R, F>(&mut self, f: F) -> Option<R>
+            where
+                T: IntoBox<A>,
+                U: IntoBox<A>,
+                F: FnOnce(&mut T, &mut U) -> R,
+            {
+                crate::tuple::assert_distinct(
+                    &[crate::TypeInfo::of::<T>(), crate::TypeInfo::of::<U>()],
+                    "Map::with_mut2",
+                );
+                let t: *mut T = self.get_mut::<T>()?;
+                let u: *mut U = self.get_mut::<U>()?;
+                // SAFETY: the types are distinct, so these point into different boxes.
+                Some(f(unsafe { &mut *t }, unsafe { &mut *u }))
+            }
+
+            /// Calls `f` with mutable references to the values stored in the collection for the
+            /// types `T`, `U` and `V`, if they all exist.
+            ///
+            /// Returns the closure’s result, or `None` if any value was missing.
+            ///
+            /// # Panics
+            ///
+            /// Panics if any two of `T`, `U` and `V` are the same type.
+            #[inline]
+            #[cfg_attr(feature = "track-caller", track_caller)]
+            pub fn with_mut3<T, U, V, R, F>(&mut self, f: F) -> Option<R>
+            where
+                T: IntoBox<A>,
+                U: IntoBox<A>,
+                V: IntoBox<A>,
+                F: FnOnce(&mut T, &mut U, &mut V) -> R,
+            {
+                crate::tuple::assert_distinct(
+                    &[crate::TypeInfo::of::<T>(), crate::TypeInfo::of::<U>(), crate::TypeInfo::of::<V>()],
+                    "Map::with_mut3",
+                );
+                let t: *mut T = self.get_mut::<T>()?;
+                let u: *mut U = self.get_mut::<U>()?;
+                let v: *mut V = self.get_mut::<V>()?;
+                // SAFETY: the types are distinct, so these point into different boxes.
+                Some(f(unsafe { &mut *t }, unsafe { &mut *u }, unsafe { &mut *v }))
+            }
+
+            /// Sets the value stored in the collection for the type `T`.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> {
+                self.record_name::<T>();
+                self.raw.insert(TypeId::of::<T>(), value.into_box())
+                    .map(|any| unsafe { *any.downcast_unchecked::<T>() })
+            }
+
+            /// Sets the value stored in the collection for the type `T`, initialising it in place,
+            /// directly in its box, rather than moving it there from the stack.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            ///
+            /// This is for large values, which `insert` would need to build on the stack first
+            /// (unless the optimiser is kind).
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// struct Buffer([u8; 65536]);
+            /// unsafe {
+            ///     data.emplace::<Buffer, _>(|slot| {
+            ///         let buffer = slot.as_mut_ptr();
+            ///         for i in 0..65536 {
+            ///             (*buffer).0[i] = i as u8;
+            ///         }
+            ///     });
+            /// }
+            /// assert_eq!(data.get::<Buffer>().unwrap().0[257], 1);
+            /// ```
+            ///
+            /// # Safety
+            ///
+            /// `init` must initialise the value completely, or *undefined behaviour* will occur.
+            /// (If it panics, nothing is inserted and the partial value is not dropped.)
+            pub unsafe fn emplace<T, F>(&mut self, init: F) -> Option<T>
+            where
+                T: IntoBox<A>,
+                F: FnOnce(&mut core::mem::MaybeUninit<T>),
+            {
+                let mut slot = Box::new(core::mem::MaybeUninit::<T>::uninit());
+                init(&mut slot);
+                let value = Box::from_raw(Box::into_raw(slot) as *mut T);
+                self.record_name::<T>();
+                self.raw.insert(TypeId::of::<T>(), T::box_into(value))
+                    .map(|any| *any.downcast_unchecked::<T>())
+            }
+
+            /// Sets the value stored in the collection for the type `T`, as `insert` does, and
+            /// remembers how to clone it, for [`clone_clonable`](Map::clone_clonable).
+            #[inline]
+            pub fn insert_clonable<T: IntoBox<A> + Clone>(&mut self, value: T) -> Option<T> {
+                self.register_clone::<T>();
+                self.insert(value)
+            }
+
+            /// Remembers how to clone values of type `T`, for
+            /// [`clone_clonable`](Map::clone_clonable) and [`try_clone`](Map::try_clone), without
+            /// inserting one.
+            ///
+            /// This is for maps used with a bound (like plain `Any`) that doesn’t allow `Clone`:
+            /// register the types that can be cloned once, up front, and values of them inserted
+            /// with plain `insert` or `entry` will be cloned too.
+            #[inline]
+            pub fn register_clone<T: IntoBox<A> + Clone>(&mut self) {
+                self.record_name::<T>();
+                let _ = self.clone_fns.get_or_insert_with(Default::default)
+                    .insert(TypeId::of::<T>(), CloneFn::of::<T>());
+            }
+
+            /// The clone function registered for the type `type_id`, if there is one.
+            #[inline]
+            fn clone_fn(&self, type_id: &TypeId) -> Option<&CloneFn<A>> {
+                self.clone_fns.as_ref()?.get(type_id)
+            }
+
+            /// Clones the collection, if a clone function has been registered for the type of
+            /// every value in it, with [`register_clone`](Map::register_clone) or
+            /// [`insert_clonable`](Map::insert_clonable).
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// struct Handle;
+            /// data.register_clone::<String>();
+            /// data.insert(String::from("state"));
+            /// assert_eq!(data.try_clone().unwrap().get::<String>().unwrap(), "state");
+            /// data.insert(Handle);
+            /// assert_eq!(data.try_clone().unwrap_err().unregistered().len(), 1);
+            /// ```
+            pub fn try_clone(&self) -> Result<Map<A>, crate::CloneError> {
+                let unregistered: Vec<crate::TypeInfo> = self.raw.keys()
+                    .filter(|key| self.clone_fn(key).is_none())
+                    .map(|&type_id| crate::TypeInfo { type_id, name: self.name_of(type_id) })
+                    .collect();
+                if unregistered.is_empty() {
+                    Ok(self.clone_clonable().0)
+                } else {
+                    Err(crate::CloneError { unregistered })
+                }
+            }
+
+            /// Clones the values which were put in the collection with
+            /// [`insert_clonable`](Map::insert_clonable), returning the new collection and the
+            /// `TypeId`s of the values which were skipped.
+            ///
+            /// This is for best-effort snapshots of maps which hold a few values that can’t be
+            /// cloned; a map of [`CloneAny`] can be cloned completely, with `Clone`.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// struct Handle;
+            /// data.insert_clonable(String::from("state"));
+            /// data.insert(Handle);
+            /// let (snapshot, skipped) = data.clone_clonable();
+            /// assert_eq!(snapshot.get::<String>().unwrap(), "state");
+            /// assert_eq!(skipped, [std::any::TypeId::of::<Handle>()]);
+            /// ```
+            pub fn clone_clonable(&self) -> (Map<A>, Vec<TypeId>) {
+                let mut map = Map::with_capacity(self.raw.len());
+                let mut skipped = Vec::new();
+                for (key, value) in &self.raw {
+                    match self.clone_fn(key) {
+                        Some(clone_fn) => {
+                            let _ = map.raw.insert(*key, (clone_fn.0)(&**value));
+                        }
+                        None => skipped.push(*key),
+                    }
+                }
+                #[cfg(feature = "type-names")]
+                {
+                    map.names = self.names.clone();
+                }
+                map.clone_fns = self.clone_fns.clone();
+                (map, skipped)
+            }
+
+            /// Sets the value stored in the collection for the type `T`, if there isn’t one
+            /// already, returning a mutable reference to it.
+            /// If the collection already had a value of type `T`, it is left alone, and an error
+            /// holding the new value is returned.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// assert_eq!(*data.try_insert(1u8).unwrap(), 1);
+            /// assert_eq!(data.try_insert(2u8).unwrap_err().into_value(), 2);
+            /// assert_eq!(data.get::<u8>(), Some(&1));
+            /// ```
+            #[inline]
+            pub fn try_insert<T: IntoBox<A>>(&mut self, value: T)
+                -> Result<&mut T, crate::errors::OccupiedError<T>>
+            {
+                match self.entry::<T>() {
+                    Entry::Occupied(_) => Err(crate::errors::OccupiedError::new(value)),
+                    Entry::Vacant(entry) => Ok(entry.insert(value)),
+                }
+            }
+
+            /// Removes the `T` value from the collection,
+            /// returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+                self.raw.remove(&TypeId::of::<T>())
+                    .map(|any| *unsafe { any.downcast_unchecked::<T>() })
+            }
+
+            /// Exchanges the values of type `T` between this collection and `other`, moving the
+            /// boxes rather than reallocating. If only one of them has a `T`, it moves to the
+            /// other.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            #[doc = $example_init]
+            /// let mut previous = AnyMap::new();
+            /// data.insert(1u32);
+            /// data.swap::<u32>(&mut previous);
+            /// assert_eq!(data.get::<u32>(), None);
+            /// assert_eq!(previous.get::<u32>(), Some(&1));
+            /// ```
+            pub fn swap<T: IntoBox<A>>(&mut self, other: &mut Map<A>) {
+                let key = TypeId::of::<T>();
+                self.record_name::<T>();
+                other.record_name::<T>();
+                if let (Some(mine), Some(theirs)) = (self.raw.get_mut(&key), other.raw.get_mut(&key)) {
+                    core::mem::swap(mine, theirs);
+                } else if let Some(mine) = self.raw.remove(&key) {
+                    let _ = other.raw.insert(key, mine);
+                } else if let Some(theirs) = other.raw.remove(&key) {
+                    let _ = self.raw.insert(key, theirs);
+                }
+            }
+
+            /// Moves the value of type `T`, if there is one, from this collection into `dest`,
+            /// which may be of another flavour, such as a plain map from a `Send + Sync` one.
+            ///
+            /// The box moves across rather than being reallocated. If `dest` already had a value
+            /// of type `T`, that value is returned. Otherwise, `None` is returned.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            /// let mut shared = Map::<dyn core::any::Any + Send + Sync>::new();
+            /// shared.insert(1u32);
+            #[doc = $example_init]
+            /// shared.transfer_entry::<u32, _>(&mut data);
+            /// assert_eq!(shared.get::<u32>(), None);
+            /// assert_eq!(data.get::<u32>(), Some(&1));
+            /// ```
+            pub fn transfer_entry<T, B>(&mut self, dest: &mut Map<B>) -> Option<T>
+            where
+                T: IntoBox<A> + IntoBox<B>,
+                B: ?Sized + Downcast,
+            {
+                let value = self.raw.remove(&TypeId::of::<T>())
+                    .map(|any| unsafe { any.downcast_unchecked::<T>() })?;
+                dest.record_name::<T>();
+                dest.raw.insert(TypeId::of::<T>(), <T as IntoBox<B>>::box_into(value))
+                    .map(|any| *unsafe { any.downcast_unchecked::<T>() })
+            }
+
+            /// Removes the `T` value from the collection, if there is one, and returns a guard
+            /// which puts it back when dropped.
+            ///
+            /// See [`ValueGuard`] for details.
+            #[inline]
+            pub fn take_guard<T: IntoBox<A>>(&mut self) -> Option<ValueGuard<'_, T, A>> {
+                let value = self.raw.remove(&TypeId::of::<T>())
+                    .map(|any| unsafe { any.downcast_unchecked::<T>() })?;
+                Some(ValueGuard { map: self, value: Some(value) })
+            }
+
+            /// Returns true if the collection contains a value of type `T`.
+            #[inline]
+            pub fn contains<T: IntoBox<A>>(&self) -> bool {
+                self.raw.contains_key(&TypeId::of::<T>())
+            }
+
+            /// Returns true if the two collections contain values of the same types, whatever the
+            /// values are.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            #[doc = $example_init]
+            /// let mut other = AnyMap::new();
+            /// data.insert(1u8);
+            /// other.insert(2u8);
+            /// assert!(data.eq_types(&other));
+            /// other.insert("more");
+            /// assert!(!data.eq_types(&other));
+            /// ```
+            #[inline]
+            pub fn eq_types<B: ?Sized + Downcast>(&self, other: &Map<B>) -> bool {
+                self.raw.len() == other.raw.len()
+                    && self.raw.keys().all(|key| other.raw.contains_key(key))
+            }
+
+            /// Returns proof that the collection contains a value of type `T`, if it does.
+            ///
+            /// See [`Proven`] for details.
+            #[inline]
+            pub fn prove<T: IntoBox<A>>(&self) -> Option<Proven<'_, T, A>> {
+                self.get::<T>().map(|value| Proven { map: self, value })
+            }
+
+            /// Checks the collection against `schema`, reporting which required types are missing
+            /// and which unexpected types are present.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// struct Config;
+            /// struct Cache;
+            ///
+            /// let schema = anymap::Schema::new().require::<Config>().allow::<Cache>();
+            /// data.insert(42i32);
+            /// let error = data.conforms(&schema).unwrap_err();
+            /// assert_eq!(error.missing().len(), 1);
+            /// assert_eq!(error.unexpected().len(), 1);
+            ///
+            /// data.remove::<i32>();
+            /// data.insert(Config);
+            /// assert!(data.conforms(&schema).is_ok());
+            /// ```
+            pub fn conforms(&self, schema: &crate::Schema) -> Result<(), crate::SchemaError> {
+                schema.check(self.raw.keys(), |type_id| self.name_of(type_id))
+            }
+
+            /// Lists the types the collection contains, with their names if they are known (which
+            /// needs the type-names Cargo feature).
+            ///
+            /// See [`Manifest`](crate::Manifest) for details.
+            pub fn manifest(&self) -> crate::Manifest {
+                crate::Manifest::new(self.raw.keys()
+                    .map(|&type_id| crate::TypeInfo { type_id, name: self.name_of(type_id) })
+                    .collect())
+            }
+
+            /// Reserves capacity for the types in `manifest` which the collection doesn’t already
+            /// contain, so that inserting them won’t reallocate.
+            pub fn reserve_for_manifest(&mut self, manifest: &crate::Manifest) {
+                let additional = manifest.types().iter()
+                    .filter(|info| !self.raw.contains_key(&info.type_id))
+                    .count();
+                self.reserve(additional);
+            }
+
+            /// Gets a view of the collection restricted to the types in the tuple `S`.
+            ///
+            /// See [`SubsetView`] for details.
+            #[inline]
+            pub fn subset<S>(&mut self) -> SubsetView<'_, S, A> {
+                SubsetView::new(self)
+            }
+
+            /// Gets a read-only view of the collection.
+            ///
+            /// See [`ReadOnlyMap`] for details.
+            #[inline]
+            pub fn read_only(&self) -> ReadOnlyMap<'_, A> {
+                ReadOnlyMap { map: self }
+            }
+
+            /// Gets the capability to write the value of type `T`, and only that, in the
+            /// collection.
+            ///
+            /// See [`WriteCap`] for details.
+            #[inline]
+            pub fn write_cap<T: IntoBox<A>>(&mut self) -> WriteCap<'_, T, A> {
+                WriteCap { map: self, type_: PhantomData }
+            }
+
+            /// Gets the entry for the given type in the collection for in-place manipulation
+            #[inline]
+            pub fn entry<T: IntoBox<A>>(&mut self) -> Entry<'_, A, T> {
+                self.record_name::<T>();
+                Entry::from_raw(self.raw.entry(TypeId::of::<T>()))
+            }
+
+            /// Replaces the value stored in the collection for the type `T` with what `f` makes
+            /// of it: `f` is given the current value, if there is one, by value, and if it returns
+            /// `None` the entry is left empty.
+            ///
+            /// This is one lookup, and an existing value’s box is reused.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// // Count to three, then stop counting, all from the one call.
+            /// let step = |count: Option<u32>| match count {
+            ///     None => Some(1),
+            ///     Some(3) => None,
+            ///     Some(count) => Some(count + 1),
+            /// };
+            /// data.update(step);
+            /// data.update(step);
+            /// assert_eq!(data.get::<u32>(), Some(&2));
+            /// data.update(step);
+            /// data.update(step);
+            /// assert_eq!(data.get::<u32>(), None);
+            /// ```
+            pub fn update<T: IntoBox<A>, F: FnOnce(Option<T>) -> Option<T>>(&mut self, f: F) {
+                /// An occupied entry whose value has been moved out. Unless it’s refilled and
+                /// taken back, it is removed on drop, its box freed without dropping the value
+                /// again; this happens if `f` panics, too.
+                struct Hole<'a, A: ?Sized + Downcast, T: 'a> {
+                    entry: Option<OccupiedEntry<'a, A, T>>,
+                }
+
+                impl<'a, A: ?Sized + Downcast, T: 'a> Drop for Hole<'a, A, T> {
+                    fn drop(&mut self) {
+                        if let Some(entry) = self.entry.take() {
+                            let raw = Box::into_raw(entry.inner.remove());
+                            // SAFETY: the box holds a (moved out) T, and MaybeUninit<T> has the
+                            // same layout but no drop glue.
+                            drop(unsafe { Box::from_raw(raw as *mut core::mem::MaybeUninit<T>) });
+                        }
+                    }
+                }
+
+                match self.entry::<T>() {
+                    Entry::Vacant(entry) => {
+                        if let Some(value) = f(None) {
+                            let _ = entry.insert(value);
+                        }
+                    },
+                    Entry::Occupied(mut entry) => {
+                        let ptr: *mut T = unsafe { entry.inner.get_mut().downcast_mut_unchecked::<T>() };
+                        let mut hole = Hole { entry: Some(entry) };
+                        // SAFETY: the hole takes care that the value isn’t used or dropped again.
+                        if let Some(value) = f(Some(unsafe { ptr.read() })) {
+                            unsafe { ptr.write(value) };
+                            let _ = hole.entry.take();
+                        }
+                    },
+                }
+            }
+
+            /// Gets the entries for several types at once, for in-place manipulation.
+            ///
+            /// `T` is a tuple of types, e.g. `(Foo, Bar)`. The entries themselves are got from the
+            /// returned [`Entries`] with its `entries` method, and can all be used at the same
+            /// time: filling one slot with a default computed from another is no trouble.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// #[derive(Debug, PartialEq)] struct Width(u32);
+            /// #[derive(Debug, PartialEq)] struct Height(u32);
+            ///
+            /// data.insert(Width(16));
+            /// {
+            ///     let mut entries = data.entry_many::<(Width, Height)>();
+            ///     let (width, height) = entries.entries();
+            ///     let width = width.or_insert(Width(4));
+            ///     height.or_insert_with(|| Height(width.0 * 9 / 16));
+            /// }
+            /// assert_eq!(data.get(), Some(&Height(9)));
+            /// ```
+            ///
+            /// # Panics
+            ///
+            /// Panics if the same type appears more than once in `T`. (With the `std` feature,
+            /// the `entry_many!` macro checks this at compile time instead.)
+            #[inline]
+            #[cfg_attr(feature = "track-caller", track_caller)]
+            pub fn entry_many<T: EntryMany<A>>(&mut self) -> Entries<'_, A, T> {
+                Entries {
+                    slots: T::take(self),
+                    map: self,
+                }
+            }
+
+            /// Sets the values stored in the collection for each of the types in the tuple,
+            /// reserving space for them all first.
+            /// A tuple of the values displaced is returned, with `None` where there was none.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// data.insert(1u8);
+            /// let old = data.insert_all((2u8, 3u16, String::from("four")));
+            /// assert_eq!(old, (Some(1u8), None, None));
+            /// assert_eq!(data.len(), 3);
+            /// ```
+            #[inline]
+            pub fn insert_all<T: TypeTuple<A>>(&mut self, values: T) -> T::Options {
+                values.insert_all(self)
+            }
+
+            /// Returns references to the values stored in the collection for each of the types in
+            /// the tuple, with `None` for any that don’t exist.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// data.insert(1u8);
+            /// data.insert(2u16);
+            /// assert_eq!(data.get_all::<(u8, u16, u32)>(), (Some(&1), Some(&2), None));
+            /// ```
+            #[inline]
+            pub fn get_all<'b, T: TypeTupleRef<'b, A>>(&'b self) -> T::Refs {
+                T::get_all(self)
+            }
+
+            /// Returns references to the values stored in the collection for each of the types in
+            /// the tuple, if they *all* exist.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// data.insert(1u8);
+            /// data.insert(2u16);
+            /// assert_eq!(data.get_all_strict::<(u8, u16)>(), Some((&1, &2)));
+            /// assert_eq!(data.get_all_strict::<(u8, u16, u32)>(), None);
+            /// ```
+            #[inline]
+            pub fn get_all_strict<'b, T: TypeTupleRef<'b, A>>(&'b self) -> Option<T::Required> {
+                T::get_all_strict(self)
+            }
+
+            /// Removes the values stored in the collection for each of the types in the tuple,
+            /// returning them, with `None` for any that didn’t exist.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// data.insert(1u8);
+            /// data.insert(2u16);
+            /// assert_eq!(data.remove_all::<(u8, u32)>(), (Some(1), None));
+            /// assert_eq!(data.len(), 1);
+            /// ```
+            #[inline]
+            pub fn remove_all<T: TypeTuple<A>>(&mut self) -> T::Options {
+                T::remove_all(self)
+            }
+
+            /// Extracts references to values in the collection, as described by `T`, such as
+            /// `(&Foo, Option<&Bar>)`.
+            ///
+            /// See [`FromAnyMap`] for details.
+            #[inline]
+            pub fn extract<'a, T: FromAnyMap<'a, A>>(&'a self) -> Result<T, crate::MissingTypeError> {
+                T::from_map(self)
+            }
+
+            /// Converts the collection into a read-only [`FrozenMap`](crate::FrozenMap), which is
+            /// faster to read from.
+            ///
+            /// ```rust
+            #[doc = $example_init]
+            /// data.insert(1u8);
+            /// data.insert("two");
+            /// let data = data.freeze();
+            /// assert_eq!(data.get::<u8>(), Some(&1));
+            /// assert!(!data.contains::<u16>());
+            /// ```
+            #[inline]
+            pub fn freeze(self) -> crate::FrozenMap<A> {
+                crate::FrozenMap::new(self.raw.into_iter().collect())
+            }
+
+            /// Get access to the raw hash map that backs this.
+            ///
+            /// This will seldom be useful, but it’s conceivable that you could wish to iterate
+            /// over all the items in the collection, and this lets you do that.
+            #[inline]
+            pub fn as_raw(&self) -> &RawMap<A> {
+                &self.raw
+            }
+
+            /// Get mutable access to the raw hash map that backs this.
+            ///
+            /// This will seldom be useful, but it’s conceivable that you could wish to iterate
+            /// over all the items in the collection mutably, or drain or something, or *possibly*
+            /// even batch insert, and this lets you do that.
+            ///
+            /// # Safety
+            ///
+            /// If you insert any values to the raw map, the key (a `TypeId`) must match the
+            /// value’s type, or *undefined behaviour* will occur when you access those values.
+            ///
+            /// (*Removing* entries is perfectly safe.)
+            #[inline]
+            pub unsafe fn as_raw_mut(&mut self) -> &mut RawMap<A> {
+                &mut self.raw
+            }
+
+            /// Convert this into the raw hash map that backs this.
+            ///
+            /// This will seldom be useful, but it’s conceivable that you could wish to consume all
+            /// the items in the collection and do *something* with some or all of them, and this
+            /// lets you do that, without the `unsafe` that `.as_raw_mut().drain()` would require.
+            #[inline]
+            pub fn into_raw(self) -> RawMap<A> {
+                self.raw
+            }
+
+            /// Construct a map from a collection of raw values.
+            ///
+            /// You know what? I can’t immediately think of any legitimate use for this, especially
+            /// because of the requirement of the `BuildHasherDefault<TypeIdHasher>` generic in the
+            /// map.
+            ///
+            /// Perhaps this will be most practical as `unsafe { Map::from_raw(iter.collect()) }`,
+            /// `iter` being an iterator over `(TypeId, Box<A>)` pairs. Eh, this method provides
+            /// symmetry with `into_raw`, so I don’t care if literally no one ever uses it. I’m not
+            /// even going to write a test for it, it’s so trivial.
+            ///
+            /// # Safety
+            ///
+            /// For all entries in the raw map, the key (a `TypeId`) must match the value’s type,
+            /// or *undefined behaviour* will occur when you access that entry.
             #[inline]
             pub unsafe fn from_raw(raw: RawMap<A>) -> Map<A> {
-                Self { raw }
+                Map::wrap(raw)
+            }
+
+            /// Construct a map from a collection of raw values, checking that every key matches its
+            /// value’s type.
+            ///
+            /// This is the safe counterpart of [`from_raw`](Map::from_raw), costing one pass over
+            /// the entries. On failure, the `TypeId` of the first offending key found is returned.
+            /// (It’s also available as `TryFrom<RawMap<A>>`.)
+            #[inline]
+            pub fn try_from_raw(raw: RawMap<A>) -> Result<Map<A>, TypeId> {
+                match raw.iter().find(|&(key, value)| *key != Downcast::type_id(&**value)) {
+                    Some((&key, _)) => Err(key),
+                    None => Ok(Map::wrap(raw)),
+                }
+            }
+
+            /// Check every entry’s key against its value’s type, returning all the mismatches.
+            ///
+            /// A map can only become invalid through misuse of the unsafe raw APIs
+            /// ([`as_raw_mut`](Map::as_raw_mut) and [`from_raw`](Map::from_raw)), which makes
+            /// accessing the affected entries undefined behaviour; this is for catching that early,
+            /// in tests and debug builds, before anything goes further wrong:
+            ///
+            // Doctests are built with debug assertions even when the crate isn’t, so this can
+            // only be run where the feature guarantees the method is there.
+            #[cfg_attr(feature = "debug-validate", doc = "```rust")]
+            #[cfg_attr(not(feature = "debug-validate"), doc = "```rust,ignore")]
+            #[doc = $example_init]
+            /// data.insert(42i32);
+            /// unsafe {
+            ///     let value = data.as_raw_mut().remove(&std::any::TypeId::of::<i32>()).unwrap();
+            ///     data.as_raw_mut().insert(std::any::TypeId::of::<u8>(), value);
+            /// }
+            /// let report = data.debug_validate();
+            /// assert_eq!(report.len(), 1);
+            /// assert_eq!(report[0].key(), std::any::TypeId::of::<u8>());
+            /// assert_eq!(report[0].actual(), std::any::TypeId::of::<i32>());
+            /// ```
+            ///
+            /// This is only present in builds with debug assertions enabled, unless the
+            /// debug-validate Cargo feature is enabled.
+            #[cfg(any(debug_assertions, feature = "debug-validate"))]
+            pub fn debug_validate(&self) -> Vec<crate::errors::KeyMismatch> {
+                self.raw.iter()
+                    .map(|(&key, value)| crate::errors::KeyMismatch {
+                        key,
+                        actual: Downcast::type_id(&**value),
+                    })
+                    .filter(|mismatch| mismatch.key != mismatch.actual)
+                    .collect()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> core::convert::TryFrom<RawMap<A>> for Map<A> {
+            type Error = TypeId;
+
+            #[inline]
+            fn try_from(raw: RawMap<A>) -> Result<Map<A>, TypeId> {
+                Map::try_from_raw(raw)
             }
         }
 
@@ -308,11 +1599,36 @@ macro_rules! everything {
             #[inline]
             fn extend<T: IntoIterator<Item = Box<A>>>(&mut self, iter: T) {
                 for item in iter {
-                    let _ = self.raw.insert(Downcast::type_id(&*item), item);
+                    let _ = self.raw.insert_checked(item);
+                }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> Map<A> {
+            /// Convert into a flavour of map with weaker bounds, moving the boxes.
+            ///
+            /// Clone functions from `register_clone` are for the old flavour, and so are lost.
+            fn upcast<B: ?Sized + Downcast>(self) -> Map<B> where A: Upcast<B> {
+                Map {
+                    raw: self.raw.into_iter().map(|(key, value)| (key, value.upcast())).collect(),
+                    #[cfg(feature = "type-names")]
+                    names: self.names,
+                    clone_fns: None,
                 }
             }
         }
 
+        // A map converts into any flavour with weaker bounds: fewer auto traits, or Any rather than
+        // CloneAny. (Not the UnwindSafe flavours, for now.)
+        impl_from_flavour! {
+            dyn Any + Send => dyn Any;
+            dyn Any + Send + Sync => dyn Any + Send, dyn Any;
+            dyn CloneAny => dyn Any;
+            dyn CloneAny + Send => dyn CloneAny, dyn Any + Send, dyn Any;
+            dyn CloneAny + Send + Sync =>
+                dyn CloneAny + Send, dyn CloneAny, dyn Any + Send + Sync, dyn Any + Send, dyn Any;
+        }
+
         /// A view into a single occupied location in an `Map`.
         pub struct OccupiedEntry<'a, A: ?Sized + Downcast, V: 'a> {
             inner: hash_map::OccupiedEntry<'a, TypeId, Box<A>, $($entry_generics)?>,
@@ -333,18 +1649,60 @@ macro_rules! everything {
             Vacant(VacantEntry<'a, A, V>),
         }
 
-        impl<'a, A: ?Sized + Downcast, V: IntoBox<A>> Entry<'a, A, V> {
-            /// Ensures a value is in the entry by inserting the default if empty, and returns
-            /// a mutable reference to the value in the entry.
-            #[inline]
-            pub fn or_insert(self, default: V) -> &'a mut V {
-                match self {
-                    Entry::Occupied(inner) => inner.into_mut(),
-                    Entry::Vacant(inner) => inner.insert(default),
+        // These show just the type, which is all there is to a vacant entry, and all that can be
+        // shown of an occupied one without requiring V to implement Debug.
+        impl<'a, A: ?Sized + Downcast, V: IntoBox<A>> core::fmt::Debug for OccupiedEntry<'a, A, V> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_struct("OccupiedEntry")
+                    .field("type", &format_args!("{}", crate::TypeInfo::of::<V>()))
+                    .finish()
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast, V: IntoBox<A>> core::fmt::Debug for VacantEntry<'a, A, V> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_struct("VacantEntry")
+                    .field("type", &format_args!("{}", crate::TypeInfo::of::<V>()))
+                    .finish()
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast, V: IntoBox<A>> core::fmt::Debug for Entry<'a, A, V> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                match *self {
+                    Entry::Occupied(ref entry) => f.debug_tuple("Occupied").field(entry).finish(),
+                    Entry::Vacant(ref entry) => f.debug_tuple("Vacant").field(entry).finish(),
                 }
             }
+        }
 
-            /// Ensures a value is in the entry by inserting the result of the default function if
+        impl<'a, A: ?Sized + Downcast, V: IntoBox<A>> Entry<'a, A, V> {
+            /// Wrap the raw entry for `TypeId::of::<V>()`.
+            #[inline]
+            fn from_raw(entry: hash_map::Entry<'a, TypeId, Box<A>, $($entry_generics)?>) -> Self {
+                match entry {
+                    hash_map::Entry::Occupied(e) => Entry::Occupied(OccupiedEntry {
+                        inner: e,
+                        type_: PhantomData,
+                    }),
+                    hash_map::Entry::Vacant(e) => Entry::Vacant(VacantEntry {
+                        inner: e,
+                        type_: PhantomData,
+                    }),
+                }
+            }
+
+            /// Ensures a value is in the entry by inserting the default if empty, and returns
+            /// a mutable reference to the value in the entry.
+            #[inline]
+            pub fn or_insert(self, default: V) -> &'a mut V {
+                match self {
+                    Entry::Occupied(inner) => inner.into_mut(),
+                    Entry::Vacant(inner) => inner.insert(default),
+                }
+            }
+
+            /// Ensures a value is in the entry by inserting the result of the default function if
             /// empty, and returns a mutable reference to the value in the entry.
             #[inline]
             pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
@@ -388,175 +1746,2149 @@ macro_rules! everything {
                 unsafe { self.inner.get().downcast_ref_unchecked() }
             }
 
-            /// Gets a mutable reference to the value in the entry
-            #[inline]
-            pub fn get_mut(&mut self) -> &mut V {
-                unsafe { self.inner.get_mut().downcast_mut_unchecked() }
-            }
+            /// Gets a mutable reference to the value in the entry
+            #[inline]
+            pub fn get_mut(&mut self) -> &mut V {
+                unsafe { self.inner.get_mut().downcast_mut_unchecked() }
+            }
+
+            /// Converts the OccupiedEntry into a mutable reference to the value in the entry
+            /// with a lifetime bound to the collection itself
+            #[inline]
+            pub fn into_mut(self) -> &'a mut V {
+                unsafe { self.inner.into_mut().downcast_mut_unchecked() }
+            }
+
+            /// Sets the value of the entry, and returns the entry's old value
+            #[inline]
+            pub fn insert(&mut self, value: V) -> V {
+                unsafe { *self.inner.insert(value.into_box()).downcast_unchecked() }
+            }
+
+            /// Takes the value out of the entry, and returns it
+            #[inline]
+            pub fn remove(self) -> V {
+                unsafe { *self.inner.remove().downcast_unchecked() }
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast, V: IntoBox<A>> VacantEntry<'a, A, V> {
+            /// Sets the value of the entry with the VacantEntry's key,
+            /// and returns a mutable reference to it
+            #[inline]
+            pub fn insert(self, value: V) -> &'a mut V {
+                unsafe { self.inner.insert(value.into_box()).downcast_mut_unchecked() }
+            }
+        }
+
+        /// A tuple of types whose entries can all be used at once, for [`Map::entry_many`].
+        ///
+        /// This is implemented for tuples of up to twelve types.
+        pub trait EntryMany<A: ?Sized + Downcast> {
+            #[doc(hidden)]
+            type Slots;
+            #[doc(hidden)]
+            #[cfg_attr(feature = "track-caller", track_caller)]
+            fn take(map: &mut Map<A>) -> Self::Slots;
+            #[doc(hidden)]
+            fn restore(raw: &mut RawMap<A>, slots: &mut Self::Slots);
+        }
+
+        /// The entries of an [`EntryMany`] tuple, as borrowed for the lifetime `'b`.
+        ///
+        /// This is a separate trait only for want of generic associated types.
+        pub trait EntryManyRef<'b, A: ?Sized + Downcast + 'b>: EntryMany<A> {
+            /// The tuple of entries, e.g. `(Entry<'b, A, Foo>, Entry<'b, A, Bar>)`.
+            type Entries;
+            #[doc(hidden)]
+            fn entries(slots: &'b mut Self::Slots) -> Self::Entries;
+        }
+
+        for_each_tuple!(impl_entry_many);
+
+        /// A tuple of types, for [`Map::insert_all`] and the like.
+        ///
+        /// This is implemented for tuples of up to twelve types.
+        pub trait TypeTuple<A: ?Sized + Downcast> {
+            /// The tuple of each type wrapped in `Option`, e.g. `(Option<Foo>, Option<Bar>)`.
+            type Options;
+            #[doc(hidden)]
+            const LEN: usize;
+            #[doc(hidden)]
+            fn insert_all(self, map: &mut Map<A>) -> Self::Options;
+            #[doc(hidden)]
+            fn remove_all(map: &mut Map<A>) -> Self::Options;
+        }
+
+        /// A [`TypeTuple`], borrowed from a map for the lifetime `'b`, for [`Map::get_all`].
+        ///
+        /// This is a separate trait only for want of generic associated types.
+        pub trait TypeTupleRef<'b, A: ?Sized + Downcast + 'b>: TypeTuple<A> {
+            /// The tuple of optional references, e.g. `(Option<&'b Foo>, Option<&'b Bar>)`.
+            type Refs;
+            /// The tuple of references, e.g. `(&'b Foo, &'b Bar)`.
+            type Required;
+            #[doc(hidden)]
+            fn get_all(map: &'b Map<A>) -> Self::Refs;
+            #[doc(hidden)]
+            fn get_all_strict(map: &'b Map<A>) -> Option<Self::Required>;
+        }
+
+        for_each_tuple!(impl_type_tuple);
+
+        /// A type which can be extracted from a [`Map`], for [`Map::extract`].
+        ///
+        /// This is implemented for `&T` (which fails if the map has no `T`), `Option<&T>` (which
+        /// doesn’t), and tuples of up to twelve such types, so that handlers can declare what they
+        /// need of a map in their signatures, and be called generically:
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// use anymap::MissingTypeError;
+        ///
+        /// fn call<'a, Args, R, F>(data: &'a AnyMap, handler: F) -> Result<R, MissingTypeError>
+        /// where
+        ///     Args: FromAnyMap<'a>,
+        ///     F: FnOnce(Args) -> R,
+        /// {
+        ///     Ok(handler(data.extract()?))
+        /// }
+        ///
+        /// struct Db(&'static str);
+        /// struct User(&'static str);
+        ///
+        /// let mut data = AnyMap::new();
+        /// data.insert(Db("main"));
+        /// let greet = |(db, user): (&Db, Option<&User>)| {
+        ///     format!("{} on {}", user.map_or("nobody", |user| user.0), db.0)
+        /// };
+        /// assert_eq!(call(&data, greet).unwrap(), "nobody on main");
+        /// assert!(call(&data, |_: &User| ()).is_err());
+        /// ```
+        pub trait FromAnyMap<'a, A: ?Sized + Downcast = dyn Any>: Sized {
+            /// Extract `Self` from `map`.
+            fn from_map(map: &'a Map<A>) -> Result<Self, crate::MissingTypeError>;
+        }
+
+        impl<'a, A: ?Sized + Downcast, T: IntoBox<A>> FromAnyMap<'a, A> for &'a T {
+            #[inline]
+            fn from_map(map: &'a Map<A>) -> Result<&'a T, crate::MissingTypeError> {
+                map.try_get::<T>()
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast, T: IntoBox<A>> FromAnyMap<'a, A> for Option<&'a T> {
+            #[inline]
+            fn from_map(map: &'a Map<A>) -> Result<Option<&'a T>, crate::MissingTypeError> {
+                Ok(map.get::<T>())
+            }
+        }
+
+        for_each_tuple!(impl_from_any_map);
+
+        /// The entries for several types at once, as returned by [`Map::entry_many`].
+        ///
+        /// While this exists, the values for those types are held apart from the rest of the map;
+        /// they are put back when it is dropped.
+        pub struct Entries<'a, A: ?Sized + Downcast, T: EntryMany<A>> {
+            map: &'a mut Map<A>,
+            slots: T::Slots,
+        }
+
+        impl<'a, A: ?Sized + Downcast, T: EntryMany<A>> Entries<'a, A, T> {
+            /// Gets the tuple of entries.
+            #[inline]
+            pub fn entries<'b>(&'b mut self) -> <T as EntryManyRef<'b, A>>::Entries
+                where T: EntryManyRef<'b, A>
+            {
+                T::entries(&mut self.slots)
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast, T: EntryMany<A>> Drop for Entries<'a, A, T> {
+            #[inline]
+            fn drop(&mut self) {
+                T::restore(&mut self.map.raw, &mut self.slots);
+            }
+        }
+
+        /// A view of a [`Map`] restricted to the types listed in the tuple `S`.
+        ///
+        /// Only values of those types can be got or inserted through it, which is checked at
+        /// compile time; so you can hand one of these to code which has no business with the rest
+        /// of the map’s contents. (The index parameter `I` on each method is inferred; see
+        /// [`HasType`](crate::HasType).)
+        ///
+        /// ```rust
+        #[doc = $example_init]
+        /// # #[derive(Debug, PartialEq)] struct Config;
+        /// # #[derive(Debug, PartialEq)] struct Counter(u32);
+        /// # struct FrameworkInternals;
+        /// data.insert(Config);
+        /// data.insert(FrameworkInternals);
+        /// {
+        ///     let mut view = data.subset::<(Config, Counter)>();
+        ///     assert_eq!(view.get(), Some(&Config));
+        ///     view.insert(Counter(1));
+        ///     // view.get::<FrameworkInternals, _>();  // ← would not compile
+        /// }
+        /// assert_eq!(data.get(), Some(&Counter(1)));
+        /// ```
+        pub struct SubsetView<'a, S, A: ?Sized + Downcast = dyn Any> {
+            map: &'a mut Map<A>,
+            types: PhantomData<S>,
+        }
+
+        impl<'a, S, A: ?Sized + Downcast> SubsetView<'a, S, A> {
+            /// Create a view of `map` restricted to the types in `S`.
+            #[inline]
+            pub fn new(map: &'a mut Map<A>) -> SubsetView<'a, S, A> {
+                SubsetView {
+                    map,
+                    types: PhantomData,
+                }
+            }
+
+            /// Returns a reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get<T: IntoBox<A>, I>(&self) -> Option<&T> where S: crate::HasType<T, I> {
+                self.map.get::<T>()
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get_mut<T: IntoBox<A>, I>(&mut self) -> Option<&mut T> where S: crate::HasType<T, I> {
+                self.map.get_mut::<T>()
+            }
+
+            /// Sets the value stored in the collection for the type `T`.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: IntoBox<A>, I>(&mut self, value: T) -> Option<T> where S: crate::HasType<T, I> {
+                self.map.insert(value)
+            }
+
+            /// Returns true if the collection contains a value of type `T`.
+            #[inline]
+            pub fn contains<T: IntoBox<A>, I>(&self) -> bool where S: crate::HasType<T, I> {
+                self.map.contains::<T>()
+            }
+        }
+
+        /// A read-only view of a [`Map`], from [`Map::read_only`]: its values can be got, but not
+        /// inserted, replaced or removed.
+        ///
+        /// This is for APIs to say in their signatures that they only read, and to hand out to
+        /// code that must not do any more, like plugins. Together with [`WriteCap`], access can be
+        /// granted type by type.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// struct Config { verbose: bool }
+        ///
+        /// fn plugin(data: ReadOnlyAnyMap) -> bool {
+        ///     data.get::<Config>().map_or(false, |config| config.verbose)
+        /// }
+        ///
+        /// let mut data = AnyMap::new();
+        /// data.insert(Config { verbose: true });
+        /// assert!(plugin(data.read_only()));
+        /// ```
+        pub struct ReadOnlyMap<'a, A: ?Sized + Downcast = dyn Any> {
+            map: &'a Map<A>,
+        }
+
+        /// The most common type of `ReadOnlyMap`, over an [`AnyMap`];
+        /// <code>[ReadOnlyMap]&lt;'a, dyn [Any]&gt;</code>.
+        pub type ReadOnlyAnyMap<'a> = ReadOnlyMap<'a, dyn Any>;
+
+        // #[derive(Clone, Copy)] would want A: Clone.
+        impl<'a, A: ?Sized + Downcast> Clone for ReadOnlyMap<'a, A> {
+            #[inline]
+            fn clone(&self) -> ReadOnlyMap<'a, A> {
+                *self
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast> Copy for ReadOnlyMap<'a, A> { }
+
+        impl<'a, A: ?Sized + Downcast> core::fmt::Debug for ReadOnlyMap<'a, A> where Map<A>: core::fmt::Debug {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_tuple("ReadOnlyMap").field(self.map).finish()
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast> From<&'a Map<A>> for ReadOnlyMap<'a, A> {
+            #[inline]
+            fn from(map: &'a Map<A>) -> ReadOnlyMap<'a, A> {
+                ReadOnlyMap { map }
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast> ReadOnlyMap<'a, A> {
+            /// Returns a reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get<T: IntoBox<A>>(&self) -> Option<&'a T> {
+                self.map.get::<T>()
+            }
+
+            /// Returns true if the collection contains a value of type `T`.
+            #[inline]
+            pub fn contains<T: IntoBox<A>>(&self) -> bool {
+                self.map.contains::<T>()
+            }
+
+            /// Returns the number of items in the collection.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.map.len()
+            }
+
+            /// Returns true if there are no items in the collection.
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.map.is_empty()
+            }
+
+            /// Returns the `TypeId`s of the values in the collection, in arbitrary order.
+            #[inline]
+            pub fn type_ids(&self) -> impl Iterator<Item = TypeId> + 'a {
+                self.map.raw.keys().cloned()
+            }
+
+            /// Returns the values in the collection, with their `TypeId`s, in arbitrary order.
+            #[inline]
+            pub fn iter(&self) -> impl Iterator<Item = (TypeId, &'a A)> + 'a {
+                self.map.raw.iter().map(|(type_id, value)| (*type_id, &**value))
+            }
+        }
+
+        /// The capability to write the value of type `T` in a [`Map`], and only that, from
+        /// [`Map::write_cap`]; the rest of the map can only be read, through
+        /// [`map`](WriteCap::map).
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// struct Config { verbose: bool }
+        /// struct Hits(u32);
+        ///
+        /// fn plugin(mut hits: WriteCap<Hits>) {
+        ///     if hits.map().get::<Config>().map_or(false, |config| config.verbose) {
+        ///         hits.insert(Hits(hits.get().map_or(0, |hits| hits.0) + 1));
+        ///     }
+        /// }
+        ///
+        /// let mut data = AnyMap::new();
+        /// data.insert(Config { verbose: true });
+        /// plugin(data.write_cap());
+        /// plugin(data.write_cap());
+        /// assert_eq!(data.get::<Hits>().unwrap().0, 2);
+        /// ```
+        pub struct WriteCap<'a, T, A: ?Sized + Downcast = dyn Any> {
+            map: &'a mut Map<A>,
+            type_: PhantomData<T>,
+        }
+
+        impl<'a, T, A: ?Sized + Downcast> core::fmt::Debug for WriteCap<'a, T, A> where Map<A>: core::fmt::Debug {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.debug_tuple("WriteCap").field(&self.map).finish()
+            }
+        }
+
+        impl<'a, T: IntoBox<A>, A: ?Sized + Downcast> WriteCap<'a, T, A> {
+            /// Returns a reference to the value of type `T`, if there is one.
+            #[inline]
+            pub fn get(&self) -> Option<&T> {
+                self.map.get::<T>()
+            }
+
+            /// Returns a mutable reference to the value of type `T`, if there is one.
+            #[inline]
+            pub fn get_mut(&mut self) -> Option<&mut T> {
+                self.map.get_mut::<T>()
+            }
+
+            /// Sets the value of type `T`, returning the previous one, if there was one.
+            #[inline]
+            pub fn insert(&mut self, value: T) -> Option<T> {
+                self.map.insert(value)
+            }
+
+            /// Removes the value of type `T`, returning it, if there was one.
+            #[inline]
+            pub fn remove(&mut self) -> Option<T> {
+                self.map.remove::<T>()
+            }
+
+            /// Returns a read-only view of the whole map.
+            #[inline]
+            pub fn map(&self) -> ReadOnlyMap<'_, A> {
+                self.map.read_only()
+            }
+        }
+
+        /// Proof that a [`Map`] contains a value of type `T`, from [`Map::prove`].
+        ///
+        /// This lets code say in its signature that it needs a map with a `T` in it, and then
+        /// get that `T` without unwrapping. The rest of the map is available through
+        /// [`map`](Proven::map).
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// struct Config { verbose: bool }
+        ///
+        /// fn run(data: Proven<Config>) -> bool {
+        ///     data.get().verbose
+        /// }
+        ///
+        /// let mut data = AnyMap::new();
+        /// assert!(data.prove::<Config>().is_none());
+        /// data.insert(Config { verbose: true });
+        /// assert!(run(data.prove().unwrap()));
+        /// ```
+        pub struct Proven<'a, T, A: ?Sized + Downcast = dyn Any> {
+            map: &'a Map<A>,
+            value: &'a T,
+        }
+
+        // #[derive(Clone, Copy)] would want T: Clone and A: Clone.
+        impl<'a, T, A: ?Sized + Downcast> Clone for Proven<'a, T, A> {
+            #[inline]
+            fn clone(&self) -> Proven<'a, T, A> {
+                *self
+            }
+        }
+
+        impl<'a, T, A: ?Sized + Downcast> Copy for Proven<'a, T, A> { }
+
+        impl<'a, T, A: ?Sized + Downcast> Proven<'a, T, A> {
+            /// Returns a reference to the value of type `T`.
+            #[inline]
+            pub fn get(&self) -> &'a T {
+                self.value
+            }
+
+            /// Returns the whole map.
+            #[inline]
+            pub fn map(&self) -> &'a Map<A> {
+                self.map
+            }
+        }
+
+        /// A value taken out of a [`Map`] by [`Map::take_guard`], which puts it back when dropped,
+        /// even when that is because of a panic.
+        ///
+        /// The rest of the map is available through [`map_mut`](ValueGuard::map_mut) meanwhile,
+        /// and [`replace_with`](ValueGuard::replace_with) supports methods which take `self` by
+        /// value.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// struct Counter(u32);
+        /// struct Machine(u32);
+        /// impl Machine {
+        ///     fn step(self, counter: &mut Counter) -> Machine {
+        ///         counter.0 += 1;
+        ///         Machine(self.0 + 1)
+        ///     }
+        /// }
+        ///
+        /// let mut data = AnyMap::new();
+        /// data.insert(Counter(0));
+        /// data.insert(Machine(0));
+        /// {
+        ///     let mut machine = data.take_guard::<Machine>().unwrap();
+        ///     assert!(!machine.map().contains::<Machine>());
+        ///     let mut counter = machine.map_mut().remove::<Counter>().unwrap();
+        ///     machine.replace_with(|machine| machine.step(&mut counter));
+        ///     machine.map_mut().insert(counter);
+        /// }
+        /// assert_eq!(data.get::<Machine>().unwrap().0, 1);
+        /// ```
+        pub struct ValueGuard<'a, T: IntoBox<A>, A: ?Sized + Downcast = dyn Any> {
+            map: &'a mut Map<A>,
+            /// Only `None` in `into_inner`, `drop`, or after `replace_with` panics.
+            value: Option<Box<T>>,
+        }
+
+        impl<'a, T: IntoBox<A>, A: ?Sized + Downcast> ValueGuard<'a, T, A> {
+            /// Returns the rest of the map.
+            #[inline]
+            pub fn map(&self) -> &Map<A> {
+                self.map
+            }
+
+            /// Returns the rest of the map, mutably. (A value of type `T` inserted meanwhile will
+            /// be replaced when the guard is dropped.)
+            #[inline]
+            pub fn map_mut(&mut self) -> &mut Map<A> {
+                self.map
+            }
+
+            /// Replace the value with the result of `f` on it. If `f` panics, the value is lost,
+            /// and the map is left without one.
+            pub fn replace_with<F: FnOnce(T) -> T>(&mut self, f: F) {
+                if let Some(value) = self.value.take() {
+                    self.value = Some(Box::new(f(*value)));
+                }
+            }
+
+            /// Keep the value out of the map, returning it.
+            #[inline]
+            #[cfg_attr(feature = "track-caller", track_caller)]
+            pub fn into_inner(mut self) -> T {
+                match self.value.take() {
+                    Some(value) => *value,
+                    None => Self::no_value(),
+                }
+            }
+
+            #[cold]
+            #[cfg_attr(feature = "track-caller", track_caller)]
+            fn no_value() -> ! {
+                panic!("ValueGuard for {} has no value, having lost it in replace_with", crate::TypeInfo::of::<T>())
+            }
+        }
+
+        impl<'a, T: IntoBox<A>, A: ?Sized + Downcast> core::ops::Deref for ValueGuard<'a, T, A> {
+            type Target = T;
+
+            #[inline]
+            #[cfg_attr(feature = "track-caller", track_caller)]
+            fn deref(&self) -> &T {
+                match self.value {
+                    Some(ref value) => value,
+                    None => Self::no_value(),
+                }
+            }
+        }
+
+        impl<'a, T: IntoBox<A>, A: ?Sized + Downcast> core::ops::DerefMut for ValueGuard<'a, T, A> {
+            #[inline]
+            #[cfg_attr(feature = "track-caller", track_caller)]
+            fn deref_mut(&mut self) -> &mut T {
+                match self.value {
+                    Some(ref mut value) => value,
+                    None => Self::no_value(),
+                }
+            }
+        }
+
+        impl<'a, T: IntoBox<A>, A: ?Sized + Downcast> Drop for ValueGuard<'a, T, A> {
+            fn drop(&mut self) {
+                if let Some(value) = self.value.take() {
+                    self.map.record_name::<T>();
+                    let _ = self.map.raw.insert(TypeId::of::<T>(), T::box_into(value));
+                }
+            }
+        }
+
+        #[cfg(feature = "std")]
+        observed_map!($example_use);
+
+        shared_map! {
+            /// A collection like [`Map`], but storing its values as `Rc<A>` instead of `Box<A>`,
+            /// so that `get_rc` can hand out cheap shared handles to them, and so that cloning the
+            /// collection is cheap, whether or not the values implement `Clone`.
+            ///
+            /// This is for single-threaded code; [`ArcMap`] is the thread-safe equivalent.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            /// let mut data = RcMap::<dyn std::any::Any>::new();
+            /// data.insert(String::from("hello"));
+            /// let handle = data.get_rc::<String>().unwrap();
+            /// data.remove::<String>();
+            /// assert_eq!(*handle, "hello");
+            /// ```
+            pub struct RcMap<A = dyn Any>(Rc, get_rc, downcast_rc_unchecked);
+        }
+
+        shared_map! {
+            /// A collection like [`Map`], but storing its values as `Arc<A>` instead of `Box<A>`,
+            /// so that `get_arc` can hand out cheap shared handles to them, and so that cloning the
+            /// collection is cheap, whether or not the values implement `Clone`.
+            ///
+            /// With `A` being `dyn Any + Send + Sync` (the default), this and the handles can be
+            /// shared between threads; [`RcMap`] is the cheaper single-threaded equivalent.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            /// let mut data = ArcMap::<dyn std::any::Any + Send + Sync>::new();
+            /// data.insert(String::from("hello"));
+            /// let handle = data.get_arc::<String>().unwrap();
+            /// std::thread::spawn(move || assert_eq!(*handle, "hello")).join().unwrap();
+            /// ```
+            pub struct ArcMap<A = dyn Any + Send + Sync>(Arc, get_arc, downcast_arc_unchecked);
+        }
+
+        tracked_map!($example_use);
+
+        #[cfg(feature = "std")]
+        expiring_map!($example_use);
+
+        meta_map!($example_use);
+
+        cast_registry!($example_use);
+
+        stable_map!($example_use);
+
+        named_map!($example_use);
+
+        scoped_map!($example_use);
+
+        event_bus!($example_use);
+
+        #[cfg(feature = "std")]
+        typed_channels!($example_use);
+
+        fallback_map!($example_use);
+
+        #[cfg(feature = "std")]
+        map_pool!($example_use);
+
+        policy_map!($example_use);
+
+        counted_map!($example_use);
+
+        presence_map!($example_use);
+
+        ops!($example_init, $example_use);
+
+        #[cfg(feature = "std")]
+        rwlock_map!($example_use);
+
+        #[cfg(feature = "std")]
+        persist_map!($example_init);
+
+        #[cfg(feature = "std")]
+        cold_map!($example_use);
+
+        keyed_map!($example_init);
+
+        budget_map!($example_use);
+
+        #[cfg(test)]
+        mod tests {
+            use crate::CloneAny;
+            use super::*;
+            #[cfg(not(feature = "std"))]
+            use alloc::vec::Vec;
+            // Only the tests of type names need it.
+            #[cfg(all(not(feature = "std"), feature = "type-names"))]
+            use alloc::string::ToString;
+
+            #[derive(Clone, Debug, PartialEq)] struct A(i32);
+            #[derive(Clone, Debug, PartialEq)] struct B(i32);
+            #[derive(Clone, Debug, PartialEq)] struct C(i32);
+            #[derive(Clone, Debug, PartialEq)] struct D(i32);
+            #[derive(Clone, Debug, PartialEq)] struct E(i32);
+            #[derive(Clone, Debug, PartialEq)] struct F(i32);
+            #[derive(Clone, Debug, PartialEq)] struct J(i32);
+
+            macro_rules! test_entry {
+                ($name:ident, $init:ty) => {
+                    #[test]
+                    fn $name() {
+                        let mut map = <$init>::new();
+                        assert_eq!(map.insert(A(10)), None);
+                        assert_eq!(map.insert(B(20)), None);
+                        assert_eq!(map.insert(C(30)), None);
+                        assert_eq!(map.insert(D(40)), None);
+                        assert_eq!(map.insert(E(50)), None);
+                        assert_eq!(map.insert(F(60)), None);
+
+                        // Existing key (insert)
+                        match map.entry::<A>() {
+                            Entry::Vacant(_) => unreachable!(),
+                            Entry::Occupied(mut view) => {
+                                assert_eq!(view.get(), &A(10));
+                                assert_eq!(view.insert(A(100)), A(10));
+                            }
+                        }
+                        assert_eq!(map.get::<A>().unwrap(), &A(100));
+                        assert_eq!(map.len(), 6);
+
+
+                        // Existing key (update)
+                        match map.entry::<B>() {
+                            Entry::Vacant(_) => unreachable!(),
+                            Entry::Occupied(mut view) => {
+                                let v = view.get_mut();
+                                let new_v = B(v.0 * 10);
+                                *v = new_v;
+                            }
+                        }
+                        assert_eq!(map.get::<B>().unwrap(), &B(200));
+                        assert_eq!(map.len(), 6);
+
+
+                        // Existing key (remove)
+                        match map.entry::<C>() {
+                            Entry::Vacant(_) => unreachable!(),
+                            Entry::Occupied(view) => {
+                                assert_eq!(view.remove(), C(30));
+                            }
+                        }
+                        assert_eq!(map.get::<C>(), None);
+                        assert_eq!(map.len(), 5);
+
+
+                        // Inexistent key (insert)
+                        match map.entry::<J>() {
+                            Entry::Occupied(_) => unreachable!(),
+                            Entry::Vacant(view) => {
+                                assert_eq!(*view.insert(J(1000)), J(1000));
+                            }
+                        }
+                        assert_eq!(map.get::<J>().unwrap(), &J(1000));
+                        assert_eq!(map.len(), 6);
+
+                        // Entry.or_insert on existing key
+                        map.entry::<B>().or_insert(B(71)).0 += 1;
+                        assert_eq!(map.get::<B>().unwrap(), &B(201));
+                        assert_eq!(map.len(), 6);
+
+                        // Entry.or_insert on nonexisting key
+                        map.entry::<C>().or_insert(C(300)).0 += 1;
+                        assert_eq!(map.get::<C>().unwrap(), &C(301));
+                        assert_eq!(map.len(), 7);
+                    }
+                }
+            }
+
+            test_entry!(test_entry_any, AnyMap);
+            test_entry!(test_entry_cloneany, Map<dyn CloneAny>);
+
+            #[test]
+            fn test_default() {
+                let map: AnyMap = Default::default();
+                assert_eq!(map.len(), 0);
+            }
+
+            #[test]
+            fn test_clone() {
+                let mut map: Map<dyn CloneAny> = Map::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let _ = map.insert(D(3));
+                let _ = map.insert(E(4));
+                let _ = map.insert(F(5));
+                let _ = map.insert(J(6));
+                let map2 = map.clone();
+                assert_eq!(map2.len(), 6);
+                assert_eq!(map2.get::<A>(), Some(&A(1)));
+                assert_eq!(map2.get::<B>(), Some(&B(2)));
+                assert_eq!(map2.get::<C>(), None);
+                assert_eq!(map2.get::<D>(), Some(&D(3)));
+                assert_eq!(map2.get::<E>(), Some(&E(4)));
+                assert_eq!(map2.get::<F>(), Some(&F(5)));
+                assert_eq!(map2.get::<J>(), Some(&J(6)));
+            }
+
+            #[test]
+            fn test_varieties() {
+                fn assert_send<T: Send>() { }
+                fn assert_sync<T: Sync>() { }
+                fn assert_clone<T: Clone>() { }
+                fn assert_debug<T: ::core::fmt::Debug>() { }
+                assert_send::<Map<dyn Any + Send>>();
+                assert_send::<Map<dyn Any + Send + Sync>>();
+                assert_sync::<Map<dyn Any + Send + Sync>>();
+                assert_debug::<Map<dyn Any>>();
+                assert_debug::<Map<dyn Any + Send>>();
+                assert_debug::<Map<dyn Any + Send + Sync>>();
+                assert_send::<Map<dyn CloneAny + Send>>();
+                assert_send::<Map<dyn CloneAny + Send + Sync>>();
+                assert_sync::<Map<dyn CloneAny + Send + Sync>>();
+                assert_clone::<Map<dyn CloneAny + Send>>();
+                assert_clone::<Map<dyn CloneAny + Send + Sync>>();
+                assert_clone::<Map<dyn CloneAny + Send + Sync>>();
+                assert_debug::<Map<dyn CloneAny>>();
+                assert_debug::<Map<dyn CloneAny + Send>>();
+                assert_debug::<Map<dyn CloneAny + Send + Sync>>();
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_unwind_safe_varieties() {
+                use std::panic::{RefUnwindSafe, UnwindSafe};
+                fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() { }
+                fn assert_send_sync<T: Send + Sync>() { }
+                fn assert_clone<T: Clone>() { }
+                assert_unwind_safe::<Map<dyn Any + UnwindSafe + RefUnwindSafe>>();
+                assert_unwind_safe::<Map<dyn Any + Send + UnwindSafe + RefUnwindSafe>>();
+                assert_unwind_safe::<Map<dyn Any + Send + Sync + UnwindSafe + RefUnwindSafe>>();
+                assert_send_sync::<Map<dyn Any + Send + Sync + UnwindSafe + RefUnwindSafe>>();
+                assert_unwind_safe::<Map<dyn CloneAny + UnwindSafe + RefUnwindSafe>>();
+                assert_unwind_safe::<Map<dyn CloneAny + Send + Sync + UnwindSafe + RefUnwindSafe>>();
+                assert_clone::<Map<dyn CloneAny + Send + UnwindSafe + RefUnwindSafe>>();
+
+                let mut map = Map::<dyn CloneAny + UnwindSafe + RefUnwindSafe>::new();
+                let _ = map.insert(A(1));
+                let copy = map.clone();
+                let result = std::panic::catch_unwind(|| {
+                    assert_eq!(copy.get::<A>(), Some(&A(1)));
+                    panic!("handler failed");
+                });
+                assert!(result.is_err());
+                assert_eq!(map.get::<A>(), Some(&A(1)));
+            }
+
+            #[test]
+            fn test_modify() {
+                let mut map = AnyMap::new();
+                assert!(!map.modify::<A, _>(|a| a.0 += 1));
+                let _ = map.insert(A(1));
+                assert!(map.modify::<A, _>(|a| a.0 += 1));
+                assert_eq!(map.get::<A>(), Some(&A(2)));
+                assert_eq!(map.get::<B>(), None);
+            }
+
+            #[test]
+            fn test_entry_many() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(C(3));
+                {
+                    let mut entries = map.entry_many::<(A, B)>();
+                    let (a, b) = entries.entries();
+                    let a = a.or_insert(A(10));
+                    let b = b.or_insert_with(|| B(a.0 + 1));
+                    a.0 += 10;
+                    b.0 += 10;
+                }
+                assert_eq!(map.len(), 3);
+                assert_eq!(map.get::<A>(), Some(&A(11)));
+                assert_eq!(map.get::<B>(), Some(&B(12)));
+                assert_eq!(map.get::<C>(), Some(&C(3)));
+
+                {
+                    let mut entries = map.entry_many::<(B, C, D)>();
+                    match entries.entries() {
+                        (Entry::Occupied(b), Entry::Occupied(c), Entry::Vacant(_)) => {
+                            assert_eq!(b.remove(), B(12));
+                            assert_eq!(c.get(), &C(3));
+                        },
+                        _ => unreachable!(),
+                    }
+                }
+                assert_eq!(map.len(), 2);
+                assert_eq!(map.get::<B>(), None);
+                assert_eq!(map.get::<C>(), Some(&C(3)));
+            }
+
+            #[test]
+            #[should_panic(expected = "distinct")]
+            fn test_entry_many_duplicate() {
+                let mut map = AnyMap::new();
+                let _ = map.entry_many::<(A, B, A)>();
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_entry_debug() {
+                let mut map = AnyMap::new();
+                let vacant = format!("{:?}", map.entry::<A>());
+                assert!(vacant.starts_with("Vacant(VacantEntry { type: "), "{}", vacant);
+                let _ = map.insert(A(1));
+                let occupied = format!("{:?}", map.entry::<A>());
+                assert!(occupied.starts_with("Occupied(OccupiedEntry { type: "), "{}", occupied);
+                #[cfg(feature = "type-names")]
+                assert!(occupied.ends_with("::A })"), "{}", occupied);
+                let mut raw = map.into_raw();
+                let cursor = format!("{:?}", raw.cursor_mut());
+                assert_eq!(cursor, "CursorMut { current: None, remaining: 1 }");
+            }
+
+            #[test]
+            fn test_with_mut() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                assert_eq!(map.with_mut3(|_: &mut A, _: &mut B, _: &mut C| ()), None);
+                let _ = map.insert(C(3));
+                assert_eq!(map.with_mut2(|a: &mut A, b: &mut B| {
+                    core::mem::swap(&mut a.0, &mut b.0);
+                    a.0 + b.0
+                }), Some(3));
+                assert_eq!(map.with_mut3(|a: &mut A, b: &mut B, c: &mut C| {
+                    c.0 += a.0 * b.0;
+                }), Some(()));
+                assert_eq!(map.get::<A>(), Some(&A(2)));
+                assert_eq!(map.get::<B>(), Some(&B(1)));
+                assert_eq!(map.get::<C>(), Some(&C(5)));
+                assert_eq!(map.with_mut2(|_: &mut A, _: &mut D| ()), None);
+            }
+
+            #[test]
+            #[should_panic(expected = "distinct, but")]
+            fn test_with_mut_duplicate() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.with_mut2(|_: &mut A, _: &mut A| ());
+            }
+
+            #[cfg(any(debug_assertions, feature = "debug-validate"))]
+            #[test]
+            fn test_debug_validate() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                assert_eq!(map.debug_validate(), []);
+                unsafe {
+                    let value = map.as_raw_mut().remove(&TypeId::of::<A>()).unwrap();
+                    let _ = map.as_raw_mut().insert(TypeId::of::<C>(), value);
+                }
+                let report = map.debug_validate();
+                assert_eq!(report.len(), 1);
+                assert_eq!(report[0].key(), TypeId::of::<C>());
+                assert_eq!(report[0].actual(), TypeId::of::<A>());
+            }
+
+            #[test]
+            fn test_try_from_raw() {
+                use core::convert::TryFrom;
+                let mut raw = RawMap::<dyn Any>::default();
+                let _ = raw.insert(TypeId::of::<A>(), Box::new(A(1)));
+                let _ = raw.insert(TypeId::of::<B>(), Box::new(B(2)));
+                let map = AnyMap::try_from(raw).unwrap();
+                assert_eq!(map.get::<A>(), Some(&A(1)));
+                assert_eq!(map.get::<B>(), Some(&B(2)));
+
+                let mut raw = map.into_raw();
+                let _ = raw.insert(TypeId::of::<C>(), Box::new(D(3)));
+                assert_eq!(AnyMap::try_from_raw(raw).unwrap_err(), TypeId::of::<C>());
+            }
+
+            #[test]
+            fn test_subset_view() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(C(3));
+                {
+                    let mut view = map.subset::<(A, B)>();
+                    assert_eq!(view.get::<A, _>(), Some(&A(1)));
+                    assert!(!view.contains::<B, _>());
+                    assert_eq!(view.insert(B(2)), None);
+                    view.get_mut::<B, _>().unwrap().0 += 10;
+                }
+                assert_eq!(map.get::<B>(), Some(&B(12)));
+                assert_eq!(map.len(), 3);
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_observed_map() {
+                use crate::Event;
+                let mut map = ObservedMap::<dyn Any>::new();
+                let events = map.subscribe();
+                {
+                    let _hung_up = map.subscribe();
+                }
+                assert_eq!(map.insert(A(1)), None);
+                assert_eq!(map.insert(A(2)), Some(A(1)));
+                let _ = map.insert(B(3));
+                map.get_mut::<B>().unwrap().0 += 1;
+                assert_eq!(map.remove::<C>(), None);
+                assert_eq!(map.remove::<A>(), Some(A(2)));
+                assert_eq!(map.get::<B>(), Some(&B(4)));
+                map.clear();
+                assert!(map.is_empty());
+                assert_eq!(events.try_iter().collect::<Vec<_>>(), [
+                    Event::Inserted(TypeId::of::<A>()),
+                    Event::Replaced(TypeId::of::<A>()),
+                    Event::Inserted(TypeId::of::<B>()),
+                    Event::Removed(TypeId::of::<A>()),
+                    Event::Removed(TypeId::of::<B>()),
+                ]);
+                assert_eq!(map.subscribers.len(), 1);
+            }
+
+            #[test]
+            fn test_shared_maps() {
+                let mut map = RcMap::<dyn Any>::new();
+                assert!(map.insert(A(1)).is_none());
+                let a = map.get_rc::<A>().unwrap();
+                let clone = map.clone();
+                assert_eq!(*map.insert(A(2)).unwrap(), A(1));
+                assert_eq!(*a, A(1));
+                assert_eq!(clone.get::<A>(), Some(&A(1)));
+                assert_eq!(map.get::<A>(), Some(&A(2)));
+                assert_eq!(map.get_rc::<B>(), None);
+                assert_eq!(*map.remove::<A>().unwrap(), A(2));
+                assert!(map.is_empty());
+
+                let mut map = ArcMap::<dyn Any + Send + Sync>::new();
+                let _ = map.insert(B(3));
+                assert!(map.contains::<B>());
+                assert_eq!(*map.get_arc::<B>().unwrap(), B(3));
+                assert_eq!(map.len(), 1);
+            }
+
+            #[test]
+            fn test_tracked_map() {
+                let mut map = TrackedMap::<dyn Any>::new();
+                assert_eq!(map.tick(), 0);
+                assert_eq!(map.insert(A(1)), None);
+                assert_eq!(map.insert(B(2)), None);
+                assert_eq!(map.insert(C(3)), None);
+                assert_eq!(map.tick(), 3);
+                assert_eq!(map.last_access::<B>(), Some(2));
+                let mark = map.tick();
+                assert_eq!(map.get::<A>(), Some(&A(1)));
+                map.get_mut::<C>().unwrap().0 += 1;
+                assert_eq!(map.get::<D>(), None);
+                assert_eq!(map.last_access::<A>(), Some(4));
+                assert_eq!(map.last_access::<C>(), Some(5));
+                assert!(map.contains::<B>());
+                assert_eq!(map.prune_unused(mark), 1);
+                assert!(!map.contains::<B>());
+                assert_eq!(map.prune_unused(mark), 0);
+                let map = map.into_map();
+                assert_eq!(map.len(), 2);
+                assert_eq!(map.get::<C>(), Some(&C(4)));
+                let map = TrackedMap::from(map);
+                assert_eq!(map.last_access::<A>(), Some(0));
+            }
+
+            #[test]
+            fn test_tracked_map_get_or_compute() {
+                let mut map = TrackedMap::<dyn Any>::new();
+                let deps = [TypeId::of::<A>(), TypeId::of::<B>()];
+                let sum = |map: &TrackedMap| C(map.get::<A>().map_or(0, |a| a.0) + map.get::<B>().map_or(0, |b| b.0));
+                assert_eq!(map.get_or_compute(&deps, sum), &C(0));
+                let _ = map.insert(A(1));
+                assert_eq!(map.get_or_compute(&deps, sum), &C(1));
+                // Nothing has changed, so there is no recomputation.
+                assert_eq!(map.get_or_compute(&deps, |_| C(100)), &C(1));
+                let _ = map.insert(D(4));
+                assert_eq!(map.get_or_compute(&deps, |_| C(100)), &C(1));
+                map.get_mut::<A>().unwrap().0 = 10;
+                assert_eq!(map.get_or_compute(&deps, sum), &C(10));
+            }
+
+            #[test]
+            fn test_tracked_map_changes() {
+                let mut map = TrackedMap::<dyn Any>::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let _ = map.insert(C(3));
+                let last_run = map.tick();
+                assert_eq!(map.changed_since(last_run).count(), 0);
+                assert_eq!(map.get::<A>(), Some(&A(1)));
+                map.get_mut::<B>().unwrap().0 += 1;
+                let _ = map.insert(D(4));
+                assert_eq!(map.last_change::<A>(), Some(1));
+                assert_eq!(map.last_change::<B>(), Some(5));
+                let mut changed = map.changed_since(last_run).map(|(id, _)| id).collect::<Vec<_>>();
+                changed.sort();
+                let mut expected = [TypeId::of::<B>(), TypeId::of::<D>()];
+                expected.sort();
+                assert_eq!(changed, expected);
+                let (_, b) = map.changed_since(5).next().unwrap();
+                assert_eq!(b.downcast_ref::<D>(), Some(&D(4)));
+                assert_eq!(map.changed_since(0).count(), 4);
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_expiring_map() {
+                use std::time::Duration;
+                let mut map = ExpiringMap::<dyn Any>::new();
+                assert_eq!(map.insert_with_ttl(A(1), Duration::from_secs(0)), None);
+                assert_eq!(map.insert_with_ttl(B(2), Duration::from_secs(3600)), None);
+                assert_eq!(map.insert(C(3)), None);
+                assert_eq!(map.get::<A>(), None);
+                assert!(!map.contains::<A>());
+                assert_eq!(map.get_mut::<B>(), Some(&mut B(2)));
+                assert_eq!(map.get::<C>(), Some(&C(3)));
+                assert!(map.ttl::<A>().is_none());
+                assert!(map.ttl::<B>().unwrap() > Duration::from_secs(3500));
+                assert!(map.ttl::<C>().is_none());
+                assert_eq!(map.len(), 3);
+
+                // An expired value is not handed back when replaced or removed.
+                assert_eq!(map.insert_with_ttl(D(4), Duration::from_secs(0)), None);
+                assert_eq!(map.insert(D(5)), None);
+                assert_eq!(map.insert_with_ttl(D(6), Duration::from_secs(0)), Some(D(5)));
+                assert_eq!(map.remove::<D>(), None);
+
+                assert_eq!(map.sweep_expired(), 1);
+                assert_eq!(map.len(), 2);
+                assert_eq!(map.remove::<B>(), Some(B(2)));
+            }
+
+            #[test]
+            fn test_meta_map() {
+                let mut map = MetaMap::<u32>::new();
+                assert_eq!(map.insert_with_meta(A(1), 10), None);
+                assert_eq!(map.insert(B(2)), None);
+                assert_eq!(map.meta::<A>(), Some(&10));
+                assert_eq!(map.meta::<B>(), Some(&0));
+                assert_eq!(map.meta::<C>(), None);
+                *map.meta_mut::<B>().unwrap() += 5;
+                map.get_mut::<B>().unwrap().0 += 5;
+                assert_eq!(map.insert_with_meta(B(3), 20), Some((B(7), 5)));
+                let mut metas = map.iter_raw().map(|(_, _, &meta)| meta).collect::<Vec<_>>();
+                metas.sort();
+                assert_eq!(metas, [10, 20]);
+                assert_eq!(map.remove::<A>(), Some((A(1), 10)));
+                assert!(!map.contains::<A>());
+                let map = map.into_map();
+                assert_eq!(map.get::<B>(), Some(&B(3)));
+            }
+
+            #[test]
+            fn test_cast_registry() {
+                trait Number {
+                    fn number(&self) -> i32;
+                }
+                impl Number for A {
+                    fn number(&self) -> i32 { self.0 }
+                }
+                impl Number for B {
+                    fn number(&self) -> i32 { self.0 * 10 }
+                }
+
+                let mut casts = CastRegistry::<dyn Number>::new();
+                casts.register::<A>(|x| x);
+                casts.register::<B>(|x| x);
+                assert!(casts.contains::<A>());
+                assert!(!casts.contains::<C>());
+
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let _ = map.insert(C(3));
+                assert_eq!(map.get_as::<A, _>(&casts).map(|x| x.number()), Some(1));
+                assert_eq!(map.get_as::<B, _>(&casts).map(|x| x.number()), Some(20));
+                assert!(map.get_as::<C, _>(&casts).is_none());
+                assert!(map.get_as::<D, _>(&casts).is_none());
+                let mut numbers = map.iter_as(&casts).map(|x| x.number()).collect::<Vec<_>>();
+                numbers.sort();
+                assert_eq!(numbers, [1, 20]);
+            }
+
+            unsafe impl crate::TypeUuid for A { const UUID: u128 = 1; }
+            unsafe impl crate::TypeUuid for B { const UUID: u128 = 2; }
+
+            #[test]
+            fn test_stable_map() {
+                let mut map = StableMap::<crate::ByUuid>::new();
+                assert_eq!(map.insert(A(1)), None);
+                assert_eq!(map.insert(A(2)), Some(A(1)));
+                assert_eq!(map.insert(B(3)), None);
+                assert_eq!(map.len(), 2);
+                map.get_mut::<A>().unwrap().0 += 10;
+                assert_eq!(map.get::<A>(), Some(&A(12)));
+                assert_eq!(map.remove::<B>(), Some(B(3)));
+                assert!(!map.contains::<B>());
+
+                #[cfg(feature = "type-names")]
+                {
+                    let mut map = unsafe { StableMap::<crate::ByTypeName>::new_by_type_name() };
+                    assert_eq!(map.insert(C(1)), None);
+                    assert_eq!(map.get::<C>(), Some(&C(1)));
+                    assert!(!map.contains::<D>());
+                    assert_eq!(map.remove::<C>(), Some(C(1)));
+                }
+
+                assert_eq!(
+                    crate::StableTypeId::of_name("a").as_u128(),
+                    0xd228cb696f1a8caf78912b704e4a8964,
+                );
+            }
+
+            #[test]
+            fn test_rc_map_from_map() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let rc = RcMap::from(map);
+                assert_eq!(rc.len(), 2);
+                assert_eq!(rc.get_rc::<A>().map(|a| a.0), Some(1));
+                assert_eq!(rc.get::<B>(), Some(&B(2)));
+            }
+
+            #[test]
+            fn test_scoped_map() {
+                let a = A(1);
+                let mut b = B(2);
+                let c = C(3);
+                {
+                    let mut map = ScopedAnyMap::new();
+                    assert_eq!(map.insert(&a), None);
+                    assert_eq!(map.insert_mut(&mut b), None);
+                    assert_eq!(map.get::<A>(), Some(&A(1)));
+                    assert!(map.get_mut::<A>().is_none());
+                    map.get_mut::<B>().unwrap().0 = 20;
+                    assert_eq!(map.insert(&c), None);
+                    assert_eq!(map.remove::<C>(), Some(&C(3)));
+                    assert!(!map.contains::<C>());
+                    assert_eq!(map.len(), 2);
+                }
+                assert_eq!(b, B(20));
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_anymap_macro() {
+                let map = crate::anymap! {};
+                assert!(map.is_empty());
+                let map = crate::anymap! { A => A(1), B => B(2) };
+                assert_eq!(map.len(), 2);
+                assert_eq!(map.get::<A>(), Some(&A(1)));
+                assert_eq!(map.get::<B>(), Some(&B(2)));
+            }
+
+            #[test]
+            fn test_manifest() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let manifest = map.manifest();
+                assert_eq!(manifest.len(), 2);
+                assert!(manifest.types().iter().any(|info| info.type_id == TypeId::of::<A>()));
+
+                let mut new = AnyMap::new();
+                let _ = new.insert(B(3));
+                new.reserve_for_manifest(&manifest);
+                assert!(new.capacity() >= 2);
+
+                #[cfg(feature = "type-names")]
+                {
+                    let names = manifest.to_string();
+                    assert_eq!(names.lines().count(), 2);
+                    assert!(names.lines().all(|name| name.starts_with("anymap::")));
+                    assert!(manifest.check_names(names.lines()).is_ok());
+                    let error = manifest.check_names(names.lines().skip(1).chain(Some("C")))
+                        .unwrap_err();
+                    assert_eq!(error.missing(), &manifest.types()[..1]);
+                    assert_eq!(error.unexpected(), ["C"]);
+                }
+                #[cfg(not(feature = "type-names"))]
+                {
+                    assert_eq!(manifest.names().count(), 0);
+                    assert_eq!(manifest.check_names(None).unwrap_err().missing().len(), 2);
+                }
+            }
+
+            #[test]
+            fn test_emplace() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let old = unsafe {
+                    map.emplace::<A, _>(|slot| *slot = core::mem::MaybeUninit::new(A(2)))
+                };
+                assert_eq!(old, Some(A(1)));
+                assert_eq!(map.get::<A>(), Some(&A(2)));
+
+                let mut map = Map::<dyn CloneAny>::new();
+                let _ = unsafe { map.emplace::<B, _>(|slot| slot.as_mut_ptr().write(B(3))) };
+                assert_eq!(map.clone().get::<B>(), Some(&B(3)));
+            }
+
+            #[test]
+            fn test_freeze() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let map = map.freeze();
+                assert_eq!(map.len(), 2);
+                assert_eq!(map.get::<A>(), Some(&A(1)));
+                assert_eq!(map.get::<B>(), Some(&B(2)));
+                assert!(!map.contains::<C>());
+            }
+
+            #[test]
+            fn test_try_get() {
+                let mut map = AnyMap::new();
+                let error = map.try_get::<A>().unwrap_err();
+                assert_eq!(error.type_info().type_id, TypeId::of::<A>());
+                #[cfg(feature = "type-names")]
+                assert!(error.to_string().ends_with("::A"));
+                assert_eq!(map.try_get_mut::<A>().unwrap_err(), error);
+                let _ = map.insert(A(1));
+                map.try_get_mut::<A>().unwrap().0 = 2;
+                assert_eq!(map.try_get::<A>(), Ok(&A(2)));
+            }
+
+            #[test]
+            fn test_impl_downcast() {
+                trait Number: Any + Send {
+                    fn number(&self) -> i32;
+                }
+                crate::impl_downcast!(Number + Send);
+                impl Number for A { fn number(&self) -> i32 { self.0 } }
+                impl Number for B { fn number(&self) -> i32 { self.0 * 10 } }
+
+                let mut map = Map::<dyn Number + Send>::new();
+                let _ = map.insert(A(1));
+                // Extend keys the box by its value’s type, not by the trait object’s.
+                map.extend(Some(Box::new(B(2)) as Box<dyn Number + Send>));
+                assert_eq!(map.get::<B>(), Some(&B(2)));
+                assert_eq!(map.as_raw().values().map(|n| n.number()).sum::<i32>(), 21);
+                assert_eq!(map.remove::<A>(), Some(A(1)));
+            }
+
+            #[test]
+            fn test_from_flavour() {
+                let mut map = Map::<dyn CloneAny + Send + Sync>::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let map: Map<dyn Any + Send + Sync> = map.into();
+                assert_eq!(map.get::<A>(), Some(&A(1)));
+                // The boxes have the new flavour’s vtables, not just its type.
+                assert!(map.as_raw()[&TypeId::of::<B>()].is::<B>());
+                let map: Map<dyn Any> = map.into();
+                assert_eq!(map.get::<B>(), Some(&B(2)));
+                assert_eq!(map.len(), 2);
+            }
+
+            #[test]
+            fn test_transfer_entry() {
+                let mut shared = Map::<dyn CloneAny + Send + Sync>::new();
+                let mut plain = AnyMap::new();
+                assert_eq!(shared.transfer_entry::<A, _>(&mut plain), None);
+                let _ = shared.insert(A(1));
+                let _ = plain.insert(A(2));
+                assert_eq!(shared.transfer_entry::<A, _>(&mut plain), Some(A(2)));
+                assert!(!shared.contains::<A>());
+                assert_eq!(plain.get::<A>(), Some(&A(1)));
+                assert!(plain.as_raw()[&TypeId::of::<A>()].is::<A>());
+            }
+
+            #[test]
+            fn test_get_ptr() {
+                let mut map = AnyMap::new();
+                assert!(map.get_ptr::<A>().is_none());
+                let _ = map.insert(A(1));
+                let ptr = map.get_ptr::<A>().unwrap();
+                let raw = map.get_raw(&TypeId::of::<A>()).unwrap();
+                assert_eq!(raw.as_ptr() as *mut A, ptr.as_ptr());
+                // Growing the table moves the boxes, not the values.
+                let _ = map.insert(B(2));
+                let _ = map.insert(C(3));
+                let _ = map.insert(D(4));
+                let _ = map.insert(E(5));
+                let _ = map.insert(F(6));
+                let _ = map.insert(J(7));
+                map.shrink_to_fit();
+                unsafe { ptr.as_ptr().write(A(10)); }
+                assert_eq!(map.get::<A>(), Some(&A(10)));
+            }
+
+            #[test]
+            fn test_take_guard() {
+                let mut map = AnyMap::new();
+                assert!(map.take_guard::<A>().is_none());
+                let _ = map.insert(A(1));
+                {
+                    let mut guard = map.take_guard::<A>().unwrap();
+                    assert!(!guard.map().contains::<A>());
+                    guard.0 = 2;
+                    guard.replace_with(|a| A(a.0 * 10));
+                }
+                assert_eq!(map.get::<A>(), Some(&A(20)));
+
+                assert_eq!(map.take_guard::<A>().unwrap().into_inner(), A(20));
+                assert!(!map.contains::<A>());
+
+                // A panic while the value is out still puts it back.
+                #[cfg(feature = "std")]
+                {
+                    let _ = map.insert(A(3));
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        let _guard = map.take_guard::<A>().unwrap();
+                        panic!("oops");
+                    }));
+                    assert!(result.is_err());
+                    assert_eq!(map.get::<A>(), Some(&A(3)));
+                }
+            }
+
+            #[test]
+            fn test_extract() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let (a, b, c): (&A, Option<&B>, Option<&C>) = map.extract().unwrap();
+                assert_eq!((a, b, c), (&A(1), Some(&B(2)), None));
+                let error = map.extract::<(&A, &C)>().unwrap_err();
+                assert_eq!(error.type_info().type_id, TypeId::of::<C>());
+                let ((a,), b) = map.extract::<((&A,), &B)>().unwrap();
+                assert_eq!((a, b), (&A(1), &B(2)));
+            }
+
+            #[test]
+            fn test_try_insert() {
+                let mut map = AnyMap::new();
+                map.try_insert(A(1)).unwrap().0 = 2;
+                let error = map.try_insert(A(3)).unwrap_err();
+                assert_eq!(error.type_info().type_id, TypeId::of::<A>());
+                assert_eq!(error.into_value(), A(3));
+                assert_eq!(map.get::<A>(), Some(&A(2)));
+            }
+
+            #[test]
+            fn test_get_unchecked() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                if map.contains::<A>() {
+                    unsafe {
+                        assert_eq!(map.get_unchecked::<A>(), &A(1));
+                        map.get_unchecked_mut::<A>().0 = 2;
+                    }
+                }
+                assert_eq!(map.get::<A>(), Some(&A(2)));
+            }
+
+            #[test]
+            fn test_with_types() {
+                let mut map = AnyMap::with_types::<(A, B, C, D)>();
+                let capacity = map.capacity();
+                assert!(capacity >= 4);
+                let _ = map.insert_all((A(1), B(2), C(3), D(4)));
+                assert_eq!(map.capacity(), capacity);
+            }
+
+            #[test]
+            fn test_type_ids() {
+                let mut map = AnyMap::new();
+                assert_eq!(map.type_ids().count(), 0);
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let mut type_ids = map.type_ids().collect::<Vec<_>>();
+                type_ids.sort();
+                let mut expected = [TypeId::of::<A>(), TypeId::of::<B>()];
+                expected.sort();
+                assert_eq!(type_ids, expected);
+            }
+
+            #[test]
+            fn test_eq_types() {
+                let mut map = AnyMap::new();
+                let mut other = Map::<dyn CloneAny>::new();
+                assert!(map.eq_types(&other));
+                let _ = map.insert(A(1));
+                let _ = other.insert(B(2));
+                assert!(!map.eq_types(&other));
+                let _ = map.insert(B(3));
+                let _ = other.insert(A(4));
+                assert!(map.eq_types(&other));
+                assert!(other.eq_types(&map));
+                let _ = other.remove::<A>();
+                assert!(!map.eq_types(&other));
+            }
+
+            #[test]
+            fn test_swap() {
+                let mut current = AnyMap::new();
+                let mut previous = AnyMap::new();
+                let _ = current.insert(A(1));
+                let _ = current.insert(B(2));
+                let _ = previous.insert(B(20));
+                let _ = previous.insert(C(30));
+                let b: *const B = current.get::<B>().unwrap();
+                current.swap::<A>(&mut previous);
+                current.swap::<B>(&mut previous);
+                current.swap::<C>(&mut previous);
+                current.swap::<D>(&mut previous);
+                assert_eq!(current.get::<A>(), None);
+                assert_eq!(previous.get::<A>(), Some(&A(1)));
+                assert_eq!(current.get::<B>(), Some(&B(20)));
+                assert_eq!(previous.get::<B>(), Some(&B(2)));
+                assert_eq!(current.get::<C>(), Some(&C(30)));
+                assert_eq!(previous.get::<C>(), None);
+                assert!(!current.contains::<D>() && !previous.contains::<D>());
+                // The box moved, rather than the value being moved into a new one.
+                assert_eq!(previous.get::<B>().unwrap() as *const B, b);
+            }
+
+            #[test]
+            fn test_clone_clonable() {
+                struct NotClone;
+                let mut map = AnyMap::new();
+                let _ = map.insert_clonable(A(1));
+                let _ = map.insert(B(2));
+                let _ = map.insert(NotClone);
+                let (mut copy, mut skipped) = map.clone_clonable();
+                skipped.sort();
+                let mut expected = [TypeId::of::<B>(), TypeId::of::<NotClone>()];
+                expected.sort();
+                assert_eq!(skipped, expected);
+                assert_eq!(copy.len(), 1);
+                copy.get_mut::<A>().unwrap().0 = 10;
+                assert_eq!(map.get::<A>(), Some(&A(1)));
+
+                // The copy remembers how to clone too.
+                let (copy2, skipped) = copy.clone_clonable();
+                assert!(skipped.is_empty());
+                assert_eq!(copy2.get::<A>(), Some(&A(10)));
+            }
+
+            #[test]
+            fn test_try_clone() {
+                let mut map = AnyMap::new();
+                map.register_clone::<A>();
+                let _ = map.insert(A(1));
+                let _ = map.entry::<A>().or_insert(A(2));
+                let copy = map.try_clone().unwrap();
+                assert_eq!(copy.get::<A>(), Some(&A(1)));
+
+                let _ = map.insert(B(2));
+                let error = map.try_clone().unwrap_err();
+                assert_eq!(error.unregistered().len(), 1);
+                assert_eq!(error.unregistered()[0].type_id, TypeId::of::<B>());
+                #[cfg(feature = "type-names")]
+                assert!(error.to_string().ends_with("::B"));
+
+                // Clearing keeps the registrations.
+                map.clear();
+                let _ = map.insert(A(3));
+                assert_eq!(map.try_clone().unwrap().get::<A>(), Some(&A(3)));
+            }
+
+            #[test]
+            fn test_get_mut_or_default() {
+                #[derive(Debug, Default, PartialEq)] struct Count(u32);
+                let mut map = AnyMap::new();
+                map.get_mut_or_default::<Count>().0 += 1;
+                map.get_mut_or_default::<Count>().0 += 1;
+                assert_eq!(map.get::<Count>(), Some(&Count(2)));
+            }
+
+            #[test]
+            fn test_insert_all() {
+                let mut map = AnyMap::new();
+                assert_eq!(map.insert_all((A(1),)), (None,));
+                assert_eq!(map.insert_all((A(2), B(3), C(4))), (Some(A(1)), None, None));
+                assert_eq!(map.len(), 3);
+                assert_eq!(map.get::<A>(), Some(&A(2)));
+                assert_eq!(map.get::<C>(), Some(&C(4)));
+            }
+
+            #[test]
+            fn test_get_all() {
+                let mut map = AnyMap::new();
+                let _ = map.insert_all((A(1), B(2)));
+                assert_eq!(map.get_all::<(A, B, C)>(), (Some(&A(1)), Some(&B(2)), None));
+                assert_eq!(map.get_all::<(B, B)>(), (Some(&B(2)), Some(&B(2))));
+                assert_eq!(map.get_all_strict::<(A, B)>(), Some((&A(1), &B(2))));
+                assert_eq!(map.get_all_strict::<(A, C)>(), None);
+            }
+
+            #[test]
+            fn test_remove_all() {
+                let mut map = AnyMap::new();
+                let _ = map.insert_all((A(1), B(2), C(3)));
+                assert_eq!(map.remove_all::<(A, C, D)>(), (Some(A(1)), Some(C(3)), None));
+                assert_eq!(map.remove_all::<(B, B)>(), (Some(B(2)), None));
+                assert!(map.is_empty());
+            }
+
+            #[test]
+            fn test_prove() {
+                let mut map = AnyMap::new();
+                assert!(map.prove::<A>().is_none());
+                let _ = map.insert_all((A(1), B(2)));
+                let proof = map.prove::<A>().unwrap();
+                let copy = proof;
+                assert_eq!(proof.get(), &A(1));
+                assert_eq!(copy.map().get::<B>(), Some(&B(2)));
+                assert_eq!(copy.map().len(), 2);
+            }
+
+            #[test]
+            fn test_conforms() {
+                let schema = crate::Schema::new().require::<A>().require::<B>().allow::<C>();
+                let mut map = AnyMap::new();
+                let _ = map.insert_all((A(1), D(4)));
+                let error = map.conforms(&schema).unwrap_err();
+                assert_eq!(error.missing(), [crate::TypeInfo::of::<B>()]);
+                assert_eq!(error.unexpected().len(), 1);
+                assert_eq!(error.unexpected()[0].type_id, TypeId::of::<D>());
+                #[cfg(feature = "type-names")]
+                assert!(error.to_string().ends_with("::B; unexpected: anymap::tests::D")
+                    || error.to_string().ends_with("::B; unexpected: anymap::hashbrown::tests::D"));
+                let _ = map.remove::<D>();
+                let _ = map.insert_all((B(2), C(3)));
+                assert_eq!(map.conforms(&schema), Ok(()));
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_map_struct() {
+                use core::convert::TryFrom;
+                crate::map_struct! {
+                    #[derive(Debug, PartialEq)]
+                    struct Both { a: A, pub b: B }
+                }
+                let mut map = crate::anymap! { A => A(1), C => C(3) };
+                map = Both::try_from(map).unwrap_err();
+                assert_eq!(map.len(), 2);
+                map = Both::try_from_map(map).unwrap_err();
+                let _ = map.insert(B(2));
+                map = Both::try_from(map).unwrap_err();
+                assert_eq!(map.len(), 3);
+                let (both, rest) = Both::try_from_map(map).unwrap();
+                assert_eq!(both, Both { a: A(1), b: B(2) });
+                assert_eq!(rest.get::<C>(), Some(&C(3)));
+                assert_eq!(rest.len(), 1);
+                let both = Both::try_from(crate::AnyMap::from(both)).unwrap();
+                let map = crate::AnyMap::from(both);
+                assert_eq!(map.get_all_strict::<(A, B)>(), Some((&A(1), &B(2))));
+                assert_eq!(map.len(), 2);
+            }
+
+            #[test]
+            fn test_read_only_and_write_cap() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                {
+                    let view = map.read_only();
+                    let copy = view;
+                    assert_eq!(copy.get::<A>(), Some(&A(1)));
+                    assert!(!view.contains::<B>());
+                    assert_eq!((view.len(), view.is_empty()), (1, false));
+                    assert!(view.type_ids().eq(Some(TypeId::of::<A>())));
+                    assert_eq!(view.iter().count(), 1);
+                }
+                let mut cap = map.write_cap::<B>();
+                assert_eq!(cap.insert(B(2)), None);
+                cap.get_mut().unwrap().0 += cap.map().get::<A>().unwrap().0;
+                assert_eq!(cap.get(), Some(&B(3)));
+                assert_eq!(cap.remove(), Some(B(3)));
+                assert_eq!(cap.map().len(), 1);
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_accessors() {
+                crate::accessors! {
+                    trait Context {
+                        a: A,
+                        b: B,
+                    }
+                }
+                let map = crate::anymap! { A => A(1) };
+                assert_eq!(map.a(), Some(&A(1)));
+                assert_eq!(map.b(), None);
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_project() {
+                crate::project! {
+                    struct View<'a> {
+                        a: &'a A,
+                        pub b: &'a mut B,
+                        c: &'a C,
+                    }
+                }
+                let mut map = crate::anymap! { A => A(1), B => B(2) };
+                assert!(View::project(&mut map).is_none());
+                let _ = map.insert(C(3));
+                let view = View::project(&mut map).unwrap();
+                view.b.0 += view.a.0 + view.c.0;
+                assert_eq!(map.get::<B>(), Some(&B(6)));
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_query() {
+                let mut map = crate::anymap! { A => A(1), B => B(2) };
+                {
+                    let (a, b, c, d) =
+                        crate::query!(&mut map, (&A, &mut B, Option<&mut C>, Option<&D>)).unwrap();
+                    b.0 += a.0;
+                    assert!(c.is_none() && d.is_none());
+                }
+                assert_eq!(map.get::<B>(), Some(&B(3)));
+                assert!(crate::query!(&mut map, (&A, &C)).is_none());
+                let (b,) = crate::query!(&mut map, (&mut B,)).unwrap();
+                b.0 = 0;
+                assert_eq!(map.get::<B>(), Some(&B(0)));
+            }
+
+            #[test]
+            fn test_stats() {
+                let stats = AnyMap::new().stats();
+                assert_eq!((stats.len(), stats.capacity()), (0, 0));
+                assert_eq!(stats.load_factor(), 0.0);
+                let simulated = AnyMap::new().simulate_stats();
+                assert_eq!(simulated.buckets(), 0);
+                assert!(simulated.probe_lengths().is_empty());
+
+                let mut map = AnyMap::with_capacity(100);
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let _ = map.insert(C(3));
+                let _ = map.insert(D(4));
+                let stats = map.stats();
+                assert_eq!(stats.len(), 4);
+                assert!(stats.capacity() >= 100);
+                assert!(stats.load_factor() > 0.0 && stats.load_factor() < 0.1);
+                let simulated = map.simulate_stats();
+                assert!(simulated.buckets() > stats.capacity());
+                assert_eq!(simulated.probe_lengths().iter().sum::<usize>(), 4);
+                assert!(simulated.occupied_home_buckets() >= 1);
+                assert!(simulated.max_probe_length() < 4);
+            }
+
+            #[test]
+            fn test_event_bus() {
+                use core::cell::Cell;
+                #[cfg(not(feature = "std"))]
+                use alloc::rc::Rc;
+                #[cfg(feature = "std")]
+                use std::rc::Rc;
 
-            /// Converts the OccupiedEntry into a mutable reference to the value in the entry
-            /// with a lifetime bound to the collection itself
-            #[inline]
-            pub fn into_mut(self) -> &'a mut V {
-                unsafe { self.inner.into_mut().downcast_mut_unchecked() }
+                let log = Rc::new(Cell::new(0));
+                let mut bus = EventBus::new();
+                assert_eq!(bus.emit(A(1)), 0);
+                let handle = log.clone();
+                bus.subscribe(move |a: &A| handle.set(handle.get() + a.0));
+                let handle = log.clone();
+                bus.subscribe(move |a: &A| handle.set(handle.get() * a.0));
+                let handle = log.clone();
+                bus.subscribe(move |b: &B| handle.set(handle.get() - b.0));
+                assert_eq!(bus.subscribers::<A>(), 2);
+                assert_eq!(bus.emit(A(3)), 2);
+                assert_eq!(log.get(), 9);
+                assert_eq!(bus.emit(B(4)), 1);
+                assert_eq!(log.get(), 5);
+                assert_eq!(bus.unsubscribe_all::<A>(), 2);
+                assert_eq!(bus.emit(A(3)), 0);
+                assert_eq!(log.get(), 5);
             }
 
-            /// Sets the value of the entry, and returns the entry's old value
-            #[inline]
-            pub fn insert(&mut self, value: V) -> V {
-                unsafe { *self.inner.insert(value.into_box()).downcast_unchecked() }
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_typed_channels() {
+                let mut channels = TypedChannels::new();
+                assert!(!channels.contains::<A>());
+                let a_sender = channels.sender::<A>();
+                assert!(channels.contains::<A>());
+                let a_receiver = channels.receiver::<A>().unwrap();
+                assert!(channels.receiver::<A>().is_none());
+                let b_receiver = channels.receiver::<B>().unwrap();
+                a_sender.send(A(1)).unwrap();
+                channels.sender::<A>().send(A(2)).unwrap();
+                channels.sender::<B>().send(B(3)).unwrap();
+                assert_eq!(a_receiver.try_iter().collect::<Vec<_>>(), [A(1), A(2)]);
+                assert_eq!(b_receiver.try_recv(), Ok(B(3)));
             }
 
-            /// Takes the value out of the entry, and returns it
-            #[inline]
-            pub fn remove(self) -> V {
-                unsafe { *self.inner.remove().downcast_unchecked() }
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_map_pool() {
+                let pool = AnyMapPool::new();
+                assert_eq!(pool.idle(), 0);
+                {
+                    let mut map = pool.take();
+                    let _ = map.insert(A(1));
+                    map.reserve(100);
+                }
+                assert_eq!(pool.idle(), 1);
+                let map = pool.take();
+                assert_eq!(pool.idle(), 0);
+                assert!(map.is_empty());
+                assert!(map.capacity() >= 100);
+                let second = pool.take();
+                drop(map);
+                let kept = second.into_inner();
+                assert!(kept.is_empty());
+                assert_eq!(pool.idle(), 1);
+                pool.clear();
+                assert_eq!(pool.idle(), 0);
+
+                fn assert_sync<T: Sync>() { }
+                assert_sync::<MapPool<dyn Any + Send>>();
             }
-        }
 
-        impl<'a, A: ?Sized + Downcast, V: IntoBox<A>> VacantEntry<'a, A, V> {
-            /// Sets the value of the entry with the VacantEntry's key,
-            /// and returns a mutable reference to it
-            #[inline]
-            pub fn insert(self, value: V) -> &'a mut V {
-                unsafe { self.inner.insert(value.into_box()).downcast_mut_unchecked() }
+            #[test]
+            fn test_policy_map() {
+                use crate::{Admission, MaxTypes, Policy, TypeInfo};
+                let mut map = PolicyMap::<_, dyn Any>::new(MaxTypes(2));
+                assert_eq!(map.insert(A(1)), Ok(None));
+                assert_eq!(map.insert(B(2)), Ok(None));
+                assert_eq!(map.insert(C(3)), Err(C(3)));
+                assert_eq!(map.insert(A(10)), Ok(Some(A(1))));
+                assert_eq!(map.remove::<B>(), Some(B(2)));
+                assert_eq!(map.insert(C(3)), Ok(None));
+
+                // Evict the oldest type to make room, keeping track of the order with `removed`.
+                struct Lru(Vec<TypeId>);
+                impl Policy for Lru {
+                    fn admit(&mut self, incoming: TypeInfo, len: usize) -> Admission {
+                        self.0.push(incoming.type_id);
+                        if len < 2 { Admission::Admit } else { Admission::Evict(self.0[0]) }
+                    }
+                    fn removed(&mut self, type_id: TypeId) {
+                        self.0.retain(|&t| t != type_id);
+                    }
+                }
+                let mut map = PolicyMap::<_, dyn Any>::new(Lru(Vec::new()));
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let _ = map.insert(C(3));
+                assert!(!map.contains::<A>());
+                assert!(map.contains::<B>() && map.contains::<C>());
+                map.clear();
+                assert!(map.policy().0.is_empty());
             }
-        }
 
-        #[cfg(test)]
-        mod tests {
-            use crate::CloneAny;
-            use super::*;
+            #[test]
+            fn test_budget_map() {
+                use crate::{HeapSize, OverBudget};
+                #[derive(Debug, PartialEq)]
+                struct Heavy(i32);
+                impl HeapSize for Heavy {
+                    fn heap_size(&self) -> usize {
+                        self.0 as usize
+                    }
+                }
+                let size = core::mem::size_of::<A>();
+                let mut map = BudgetMap::<dyn Any>::new(3 * size, OverBudget::Reject);
+                assert_eq!(map.insert(A(1)), Ok(None));
+                assert_eq!(map.insert(B(2)), Ok(None));
+                assert_eq!(map.insert(A(3)), Ok(Some(A(1))));
+                assert_eq!(map.total_bytes(), 2 * size);
+                map.measure::<Heavy>();
+                assert_eq!(map.insert(Heavy(size as i32)), Err(Heavy(size as i32)));
+                assert_eq!(map.insert(Heavy(0)), Ok(None));
+                assert_eq!(map.with_mut(|c: &mut Heavy| c.0 = 1), Some(()));
+                assert_eq!(map.total_bytes(), 3 * size + 1);
+                assert_eq!(map.remove::<Heavy>(), Some(Heavy(1)));
+                assert_eq!(map.total_bytes(), 2 * size);
 
-            #[derive(Clone, Debug, PartialEq)] struct A(i32);
-            #[derive(Clone, Debug, PartialEq)] struct B(i32);
-            #[derive(Clone, Debug, PartialEq)] struct C(i32);
-            #[derive(Clone, Debug, PartialEq)] struct D(i32);
-            #[derive(Clone, Debug, PartialEq)] struct E(i32);
-            #[derive(Clone, Debug, PartialEq)] struct F(i32);
-            #[derive(Clone, Debug, PartialEq)] struct J(i32);
+                let mut map = BudgetMap::<dyn Any>::new(3 * size, OverBudget::EvictOldest);
+                map.measure::<Heavy>();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let _ = map.insert(Heavy(0));
+                let _ = map.insert(A(4));
+                assert_eq!(map.insert(D(5)), Ok(None));
+                assert!(!map.contains::<B>());
+                assert_eq!(map.with_mut(|c: &mut Heavy| c.0 = size as i32), Some(()));
+                assert!(!map.contains::<A>() && map.contains::<Heavy>() && map.contains::<D>());
+                assert_eq!(map.total_bytes(), 3 * size);
+                assert_eq!(map.insert(E(6)), Ok(None));
+                assert!(!map.contains::<D>());
+                assert_eq!(map.len(), 2);
+                map.clear();
+                assert_eq!(map.total_bytes(), 0);
+            }
 
-            macro_rules! test_entry {
-                ($name:ident, $init:ty) => {
-                    #[test]
-                    fn $name() {
-                        let mut map = <$init>::new();
-                        assert_eq!(map.insert(A(10)), None);
-                        assert_eq!(map.insert(B(20)), None);
-                        assert_eq!(map.insert(C(30)), None);
-                        assert_eq!(map.insert(D(40)), None);
-                        assert_eq!(map.insert(E(50)), None);
-                        assert_eq!(map.insert(F(60)), None);
+            #[test]
+            fn test_counted_map() {
+                let mut map = CountedMap::<dyn Any>::new();
+                assert_eq!(map.get::<A>(), None);
+                let _ = map.insert(A(1));
+                assert_eq!(map.get::<A>(), Some(&A(1)));
+                map.get_mut::<A>().unwrap().0 = 2;
+                assert_eq!(map.remove::<B>(), None);
+                assert_eq!(map.counts::<A>(), crate::AccessCounts { hits: 2, misses: 1, mutations: 2 });
+                assert_eq!(map.counts::<B>(), crate::AccessCounts::default());
 
-                        // Existing key (insert)
-                        match map.entry::<A>() {
-                            Entry::Vacant(_) => unreachable!(),
-                            Entry::Occupied(mut view) => {
-                                assert_eq!(view.get(), &A(10));
-                                assert_eq!(view.insert(A(100)), A(10));
-                            }
-                        }
-                        assert_eq!(map.get::<A>().unwrap(), &A(100));
-                        assert_eq!(map.len(), 6);
+                let mut map = CountedMap::from(Map::<dyn Any>::new());
+                let _ = map.get::<B>();
+                let _ = map.insert(A(1));
+                let _ = map.get::<A>();
+                let report = map.report();
+                assert_eq!(report.len(), 2);
+                assert_eq!(report[0].0.type_id, TypeId::of::<B>());
+                map.reset_counts();
+                assert_eq!(map.report()[0].1, crate::AccessCounts::default());
+            }
 
+            #[test]
+            fn test_presence_map() {
+                let mut map = PresenceMap::<dyn Any>::from({
+                    let mut map = Map::new();
+                    let _ = map.insert(A(1));
+                    map
+                });
+                let a = map.register::<A>();
+                assert_eq!(map.register::<A>().index(), a.index());
+                assert!(map.has(a));
+                let b = map.register::<B>();
+                assert!(!map.has(b));
+                let _ = map.insert(B(2));
+                assert!(map.has(b));
+                assert_eq!(map.remove::<A>(), Some(A(1)));
+                assert!(!map.has(a));
+                // Unregistered types work as usual, and don’t disturb the bits.
+                let _ = map.insert(C(3));
+                assert!(map.contains::<C>());
+                map.clear();
+                assert!(!map.has(b));
+            }
 
-                        // Existing key (update)
-                        match map.entry::<B>() {
-                            Entry::Vacant(_) => unreachable!(),
-                            Entry::Occupied(mut view) => {
-                                let v = view.get_mut();
-                                let new_v = B(v.0 * 10);
-                                *v = new_v;
-                            }
-                        }
-                        assert_eq!(map.get::<B>().unwrap(), &B(200));
-                        assert_eq!(map.len(), 6);
+            #[test]
+            fn test_update() {
+                let mut map = AnyMap::new();
+                map.update::<A, _>(|a| a);
+                assert!(!map.contains::<A>());
+                map.update(|a: Option<A>| Some(A(a.map_or(1, |a| a.0 * 10))));
+                map.update(|a: Option<A>| Some(A(a.map_or(1, |a| a.0 * 10))));
+                assert_eq!(map.get::<A>(), Some(&A(10)));
+                map.update::<A, _>(|_| None);
+                assert!(!map.contains::<A>());
 
+                // A panic leaves the entry empty, rather than dropping the value twice.
+                #[cfg(feature = "std")]
+                {
+                    let _ = map.insert(String::from("value"));
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        map.update::<String, _>(|_| panic!("oops"));
+                    }));
+                    assert!(result.is_err());
+                    assert!(!map.contains::<String>());
+                }
+            }
 
-                        // Existing key (remove)
-                        match map.entry::<C>() {
-                            Entry::Vacant(_) => unreachable!(),
-                            Entry::Occupied(view) => {
-                                assert_eq!(view.remove(), C(30));
-                            }
-                        }
-                        assert_eq!(map.get::<C>(), None);
-                        assert_eq!(map.len(), 5);
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_shared_map() {
+                let map = SharedMap::<dyn Any + Send + Sync>::new();
+                assert!(map.read::<A>().is_none());
+                assert!(map.write::<A>().is_none());
+                let _ = map.write_map().insert(A(1));
+                {
+                    let a = map.read::<A>().unwrap();
+                    let again = map.read::<A>().unwrap();
+                    assert_eq!((a.0, again.0), (1, 1));
+                }
+                map.write::<A>().unwrap().0 = 2;
+                let map = std::sync::Arc::new(map);
+                let threads: Vec<_> = (0..4).map(|_| {
+                    let map = map.clone();
+                    std::thread::spawn(move || map.write::<A>().unwrap().0 += 1)
+                }).collect();
+                for thread in threads {
+                    thread.join().unwrap();
+                }
+                assert_eq!(map.read::<A>().map(|a| a.0), Some(6));
+            }
 
+            #[test]
+            fn test_keyed() {
+                struct Key;
+                impl crate::Key for Key {
+                    type Value = A;
+                }
+                let mut map = Map::<dyn CloneAny>::new();
+                assert_eq!(map.insert_keyed::<Key>(A(1)), None);
+                let _ = map.insert(A(2));
+                assert_eq!(map.insert_keyed::<Key>(A(3)), Some(A(1)));
+                map.get_keyed_mut::<Key>().unwrap().0 += 1;
+                let copy = map.clone();
+                assert_eq!(copy.get_keyed::<Key>(), Some(&A(4)));
+                assert_eq!(map.remove_keyed::<Key>(), Some(A(4)));
+                assert!(!map.contains_keyed::<Key>());
+                assert_eq!(map.get::<A>(), Some(&A(2)));
+            }
 
-                        // Inexistent key (insert)
-                        match map.entry::<J>() {
-                            Entry::Occupied(_) => unreachable!(),
-                            Entry::Vacant(view) => {
-                                assert_eq!(*view.insert(J(1000)), J(1000));
-                            }
-                        }
-                        assert_eq!(map.get::<J>().unwrap(), &J(1000));
-                        assert_eq!(map.len(), 6);
+            #[test]
+            fn test_apply() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.insert(B(2));
+                let ops = Ops::new()
+                    .replace(A(10))
+                    .remove::<B>()
+                    .insert(B(20))
+                    .insert(C(3));
+                assert_eq!(ops.len(), 4);
+                let displaced = map.apply(ops).unwrap().into_displaced();
+                assert_eq!(displaced.get::<A>(), Some(&A(1)));
+                assert_eq!(displaced.get::<B>(), Some(&B(2)));
+                assert_eq!(map.get::<B>(), Some(&B(20)));
+                assert_eq!(map.get::<C>(), Some(&C(3)));
 
-                        // Entry.or_insert on existing key
-                        map.entry::<B>().or_insert(B(71)).0 += 1;
-                        assert_eq!(map.get::<B>().unwrap(), &B(201));
-                        assert_eq!(map.len(), 6);
+                // Each operation is checked against the state left by the ones before it.
+                let error = map.apply(Ops::new().remove::<D>()).unwrap_err();
+                assert_eq!((error.index(), error.occupied()), (0, false));
+                assert_eq!(error.type_info().type_id, TypeId::of::<D>());
+                let error = map.apply(Ops::new().insert(D(4)).insert(D(5))).unwrap_err();
+                assert_eq!((error.index(), error.occupied()), (1, true));
+                assert!(!map.contains::<D>());
+                assert!(map.apply(Ops::new()).unwrap().displaced().is_empty());
+            }
 
-                        // Entry.or_insert on nonexisting key
-                        map.entry::<C>().or_insert(C(300)).0 += 1;
-                        assert_eq!(map.get::<C>().unwrap(), &C(301));
-                        assert_eq!(map.len(), 7);
+            #[test]
+            fn test_fallback_map() {
+                let mut map = FallbackMap::<dyn Any>::new();
+                assert!(map.get_or_fallback::<A>().is_none());
+                let mut calls = 0;
+                map.set_fallback(move |type_id| {
+                    calls += 1;
+                    if type_id == TypeId::of::<A>() {
+                        Some(Box::new(A(calls)))
+                    } else if type_id == TypeId::of::<B>() {
+                        // The wrong type: discarded.
+                        Some(Box::new(A(0)))
+                    } else {
+                        None
                     }
+                });
+                assert_eq!(map.get_or_fallback::<A>(), Some(&mut A(1)));
+                // Now it’s in the map, the hook isn’t called again.
+                assert_eq!(map.get_or_fallback::<A>(), Some(&mut A(1)));
+                assert!(map.get_or_fallback::<B>().is_none());
+                assert!(map.get_or_fallback::<C>().is_none());
+                assert_eq!(map.len(), 1);
+                map.clear_fallback();
+                let _ = map.remove::<A>();
+                assert!(map.get_or_fallback::<A>().is_none());
+                assert!(map.into_inner().is_empty());
+            }
+
+            #[test]
+            fn test_named_map() {
+                let mut map = NamedMap::<dyn Any>::new();
+                assert_eq!(map.insert_named("a", A(1)), None);
+                map.register_name::<B>("b");
+                map.register_name::<B>("bee");
+                assert!(map.get_by_name("b").is_none());
+                let _ = map.insert(B(2));
+                assert_eq!(map.get_by_name("bee").and_then(|b| b.downcast_ref()), Some(&B(2)));
+                map.get_mut_by_name("a").and_then(|a| a.downcast_mut::<A>()).unwrap().0 = 10;
+                assert_eq!(map.get::<A>(), Some(&A(10)));
+                let names = map.iter_named().map(|(name, _)| name).collect::<Vec<_>>();
+                assert_eq!(names, ["a", "b", "bee"]);
+                assert_eq!(map.unregister_name("a"), Some(TypeId::of::<A>()));
+                assert!(map.get_by_name("a").is_none());
+                assert!(map.contains::<A>());
+            }
+
+            #[cfg(feature = "type-names")]
+            #[test]
+            fn test_type_names() {
+                let mut map = AnyMap::new();
+                let _ = map.insert(A(1));
+                let _ = map.entry::<J>().or_insert(J(1));
+                {
+                    let mut entries = map.entry_many::<(B, C)>();
+                    let (b, _) = entries.entries();
+                    let _ = b.or_insert(B(2));
                 }
+                let mut names = map.type_names().collect::<Vec<_>>();
+                names.sort();
+                assert_eq!(names.len(), 3);
+                assert!(names[0].ends_with("::A"));
+                assert!(names[1].ends_with("::B"));
+                assert!(names[2].ends_with("::J"));
+                let _ = map.remove::<A>();
+                assert_eq!(map.type_names().count(), 2);
+                map.clear();
+                assert_eq!(map.type_names().count(), 0);
             }
 
-            test_entry!(test_entry_any, AnyMap);
-            test_entry!(test_entry_cloneany, Map<dyn CloneAny>);
+            #[test]
+            fn test_raw_insert_checked() {
+                let mut raw = RawMap::<dyn Any>::default();
+                assert!(raw.insert_checked(Box::new(A(1))).is_none());
+                let old = raw.insert_checked(Box::new(A(2))).unwrap();
+                assert_eq!(old.downcast_ref::<A>(), Some(&A(1)));
+                assert!(raw.insert_checked(Box::new(B(3))).is_none());
+                let map = Map::try_from_raw(raw).unwrap();
+                assert_eq!(map.get::<A>(), Some(&A(2)));
+                assert_eq!(map.get::<B>(), Some(&B(3)));
+            }
 
             #[test]
-            fn test_default() {
-                let map: AnyMap = Default::default();
-                assert_eq!(map.len(), 0);
+            fn test_raw_typed() {
+                let mut raw = RawMap::<dyn Any>::default();
+                let _ = raw.insert_checked(Box::new(A(1)));
+                let _ = raw.insert(TypeId::of::<B>(), Box::new(C(2)));
+                assert_eq!(raw.get_typed::<A>(), Some(&A(1)));
+                raw.get_typed_mut::<A>().unwrap().0 = 10;
+                assert_eq!(raw.remove_typed::<A>(), Some(A(10)));
+                assert_eq!(raw.remove_typed::<A>(), None);
+                // B’s key holding a C is found by neither.
+                assert_eq!(raw.get_typed::<B>(), None);
+                assert_eq!(raw.get_typed::<C>(), None);
+                assert_eq!(raw.remove_typed::<B>(), None);
+                assert_eq!(raw.len(), 1);
             }
 
             #[test]
-            fn test_clone() {
-                let mut map: Map<dyn CloneAny> = Map::new();
-                let _ = map.insert(A(1));
-                let _ = map.insert(B(2));
-                let _ = map.insert(D(3));
-                let _ = map.insert(E(4));
-                let _ = map.insert(F(5));
-                let _ = map.insert(J(6));
-                let map2 = map.clone();
-                assert_eq!(map2.len(), 6);
-                assert_eq!(map2.get::<A>(), Some(&A(1)));
-                assert_eq!(map2.get::<B>(), Some(&B(2)));
-                assert_eq!(map2.get::<C>(), None);
-                assert_eq!(map2.get::<D>(), Some(&D(3)));
-                assert_eq!(map2.get::<E>(), Some(&E(4)));
-                assert_eq!(map2.get::<F>(), Some(&F(5)));
-                assert_eq!(map2.get::<J>(), Some(&J(6)));
+            fn test_raw_extract_if() {
+                let mut raw = RawMap::<dyn Any>::default();
+                let _ = raw.insert_checked(Box::new(A(1)));
+                let _ = raw.insert_checked(Box::new(B(2)));
+                let _ = raw.insert_checked(Box::new(C(3)));
+                let mut visited = 0;
+                let mut extracted = raw.extract_if(|_, value| {
+                    visited += 1;
+                    value.is::<A>() || value.is::<C>()
+                }).map(|(key, _)| key).collect::<Vec<_>>();
+                extracted.sort();
+                let mut expected = [TypeId::of::<A>(), TypeId::of::<C>()];
+                expected.sort();
+                assert_eq!(extracted, expected);
+                assert_eq!(visited, 3);
+                assert_eq!(raw.len(), 1);
+
+                // Dropped without iterating: nothing is removed.
+                let _ = raw.extract_if(|_, _| true);
+                assert_eq!(raw.len(), 1);
             }
 
             #[test]
-            fn test_varieties() {
-                fn assert_send<T: Send>() { }
-                fn assert_sync<T: Sync>() { }
-                fn assert_clone<T: Clone>() { }
-                fn assert_debug<T: ::core::fmt::Debug>() { }
-                assert_send::<Map<dyn Any + Send>>();
-                assert_send::<Map<dyn Any + Send + Sync>>();
-                assert_sync::<Map<dyn Any + Send + Sync>>();
-                assert_debug::<Map<dyn Any>>();
-                assert_debug::<Map<dyn Any + Send>>();
-                assert_debug::<Map<dyn Any + Send + Sync>>();
-                assert_send::<Map<dyn CloneAny + Send>>();
-                assert_send::<Map<dyn CloneAny + Send + Sync>>();
-                assert_sync::<Map<dyn CloneAny + Send + Sync>>();
-                assert_clone::<Map<dyn CloneAny + Send>>();
-                assert_clone::<Map<dyn CloneAny + Send + Sync>>();
-                assert_clone::<Map<dyn CloneAny + Send + Sync>>();
-                assert_debug::<Map<dyn CloneAny>>();
-                assert_debug::<Map<dyn CloneAny + Send>>();
-                assert_debug::<Map<dyn CloneAny + Send + Sync>>();
+            fn test_raw_cursor_mut() {
+                let mut raw = RawMap::<dyn Any>::default();
+                let _ = raw.insert_checked(Box::new(A(1)));
+                let _ = raw.insert_checked(Box::new(B(2)));
+                let _ = raw.insert_checked(Box::new(C(3)));
+                let mut cursor = raw.cursor_mut();
+                assert!(cursor.current().is_none());
+                let mut seen = 0;
+                while cursor.move_next() {
+                    seen += 1;
+                    let (key, value) = cursor.current().unwrap();
+                    if let Some(b) = value.downcast_mut::<B>() {
+                        b.0 = 20;
+                    } else {
+                        assert_eq!(cursor.remove_current().unwrap().0, key);
+                        assert!(cursor.current().is_none());
+                        assert!(cursor.remove_current().is_none());
+                    }
+                }
+                assert_eq!(seen, 3);
+                assert!(cursor.current().is_none());
+                assert_eq!(raw.len(), 1);
+                assert_eq!(raw.get_typed::<B>(), Some(&B(20)));
+            }
+
+            #[test]
+            fn test_raw_get_each_mut() {
+                let mut raw = RawMap::<dyn Any>::default();
+                let _ = raw.insert_checked(Box::new(A(1)));
+                let _ = raw.insert_checked(Box::new(B(2)));
+                let (a, b, c) = (TypeId::of::<A>(), TypeId::of::<B>(), TypeId::of::<C>());
+                {
+                    let values = raw.get_each_mut(&[b, a]).unwrap();
+                    assert_eq!(values.len(), 2);
+                    assert!(values[0].is::<B>() && values[1].is::<A>());
+                    for value in values {
+                        if let Some(a) = value.downcast_mut::<A>() {
+                            a.0 = 10;
+                        }
+                    }
+                }
+                assert_eq!(raw.get_typed::<A>(), Some(&A(10)));
+                assert!(raw.get_each_mut(&[a, b, a]).is_none());
+                assert!(raw.get_each_mut(&[a, c]).is_none());
+                assert_eq!(raw.get_each_mut(&[]).unwrap().len(), 0);
             }
 
             #[test]
@@ -574,12 +3906,184 @@ macro_rules! everything {
     };
 }
 
+/// The methods of a `Map` whose allocations are all fallible, for a backend with `try_reserve`.
+#[cfg(any(feature = "hashbrown", feature = "try-reserve"))]
+macro_rules! fallible_alloc {
+    ($example_init:literal, $example_use:literal, $from_error:path) => {
+        impl<A: ?Sized + Downcast> Map<A> {
+            /// Creates an empty collection with the given initial capacity, returning an error
+            /// rather than panicking or aborting if the capacity overflows or the allocator fails.
+            #[inline]
+            pub fn try_with_capacity(capacity: usize)
+                -> Result<Map<A>, crate::errors::TryReserveError>
+            {
+                let mut map = Map::new();
+                map.try_reserve(capacity)?;
+                Ok(map)
+            }
+
+            /// Tries to reserve capacity for at least `additional` more elements to be inserted in
+            /// the collection, returning an error rather than panicking or aborting if the capacity
+            /// overflows or the allocator fails.
+            #[inline]
+            #[allow(clippy::incompatible_msrv)]  // The try-reserve feature requires 1.57.
+            pub fn try_reserve(&mut self, additional: usize)
+                -> Result<(), crate::errors::TryReserveError>
+            {
+                self.raw.try_reserve(additional).map_err($from_error)
+            }
+
+            /// Reserve room for the type `T` in the table, and for its name, if they’re needed.
+            #[allow(clippy::incompatible_msrv)]  // The try-reserve feature requires 1.57.
+            fn try_reserve_for<T: 'static>(&mut self)
+                -> Result<(), crate::errors::TryReserveError>
+            {
+                #[cfg(feature = "type-names")]
+                {
+                    if !self.names.contains_key(&TypeId::of::<T>()) {
+                        self.names.try_reserve(1).map_err($from_error)?;
+                    }
+                }
+                if !self.raw.contains_key(&TypeId::of::<T>()) {
+                    self.try_reserve(1)?;
+                }
+                Ok(())
+            }
+
+            /// Sets the value stored in the collection for the type `T`, as `insert` does, but
+            /// returning an error rather than aborting if allocating the value’s box or growing
+            /// the table fails. On failure, the collection’s values are unchanged, and `value` is
+            /// returned with the error.
+            ///
+            /// Between this, [`try_entry`](Map::try_entry),
+            /// [`try_with_capacity`](Map::try_with_capacity) and
+            /// [`try_reserve`](Map::try_reserve), a map can be used without any allocation being
+            /// infallible. (This isn’t called `try_insert` because [`try_insert`](Map::try_insert),
+            /// as on std’s `HashMap`, fails if a value is present.)
+            pub fn insert_fallible<T: IntoBox<A>>(&mut self, value: T)
+                -> Result<Option<T>, (crate::errors::TryReserveError, T)>
+            {
+                if let Err(error) = self.try_reserve_for::<T>() {
+                    return Err((error, value));
+                }
+                let value = crate::try_box(value)?;
+                self.record_name::<T>();
+                Ok(self.raw.insert(TypeId::of::<T>(), T::box_into(value))
+                    .map(|any| *unsafe { any.downcast_unchecked::<T>() }))
+            }
+
+            /// Gets the entry for the type `T`, as `entry` does, having first reserved room for it
+            /// in the table, returning an error rather than aborting if that fails.
+            ///
+            /// A vacant entry from this can be filled without aborting on failure with
+            /// [`VacantEntry::insert_fallible`].
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            #[doc = $example_init]
+            /// if let Entry::Vacant(entry) = data.try_entry::<u32>().unwrap() {
+            ///     *entry.insert_fallible(1).unwrap() += 1;
+            /// }
+            /// assert_eq!(data.get::<u32>(), Some(&2));
+            /// ```
+            pub fn try_entry<T: IntoBox<A>>(&mut self)
+                -> Result<Entry<'_, A, T>, crate::errors::TryReserveError>
+            {
+                self.try_reserve_for::<T>()?;
+                Ok(self.entry::<T>())
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast, V: IntoBox<A>> VacantEntry<'a, A, V> {
+            /// Sets the value of the entry, as `insert` does, but returning an error rather than
+            /// aborting if allocating the value’s box fails, with `value` given back.
+            ///
+            /// This only avoids every infallible allocation for an entry from
+            /// [`Map::try_entry`], which has already reserved room in the table.
+            pub fn insert_fallible(self, value: V)
+                -> Result<&'a mut V, (crate::errors::TryReserveError, V)>
+            {
+                let value = crate::try_box(value)?;
+                Ok(unsafe { self.inner.insert(V::box_into(value)).downcast_mut_unchecked() })
+            }
+        }
+
+        #[cfg(test)]
+        mod fallible_tests {
+            use super::*;
+
+            #[test]
+            fn test_try_reserve() {
+                let mut map = AnyMap::new();
+                assert!(map.try_reserve(10).is_ok());
+                assert!(map.capacity() >= 10);
+                let error = map.try_reserve(usize::max_value()).unwrap_err();
+                assert_eq!(error.kind(), crate::TryReserveErrorKind::CapacityOverflow);
+            }
+
+            #[test]
+            fn test_fallible() {
+                #[derive(Debug, PartialEq)] struct Big([u64; 4]);
+                #[derive(Debug, PartialEq)] struct Marker;
+                let mut map = AnyMap::try_with_capacity(2).unwrap();
+                assert!(map.capacity() >= 2);
+                assert_eq!(map.insert_fallible(Big([1; 4])), Ok(None));
+                assert_eq!(map.insert_fallible(Big([2; 4])), Ok(Some(Big([1; 4]))));
+                assert_eq!(map.insert_fallible(Marker), Ok(None));
+                assert_eq!(map.get::<Big>(), Some(&Big([2; 4])));
+                assert!(AnyMap::try_with_capacity(usize::max_value()).is_err());
+
+                match map.try_entry::<u8>().unwrap() {
+                    Entry::Vacant(entry) => assert_eq!(entry.insert_fallible(1), Ok(&mut 1)),
+                    Entry::Occupied(_) => unreachable!(),
+                }
+                assert!(map.try_entry::<u8>().unwrap().or_insert(2) == &1);
+            }
+        }
+    };
+}
+
+/// Box `value`, returning an error (and the value) if allocation fails rather than aborting.
+#[cfg(any(feature = "hashbrown", feature = "try-reserve"))]
+fn try_box<T>(value: T) -> Result<crate::__private::Box<T>, (crate::errors::TryReserveError, T)> {
+    #[cfg(not(feature = "std"))]
+    use alloc::alloc::{alloc, Layout};
+    #[cfg(feature = "std")]
+    use std::alloc::{alloc, Layout};
+    use crate::__private::Box;
+
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        // Boxing a zero-sized value doesn’t allocate.
+        return Ok(Box::new(value));
+    }
+    // SAFETY: the layout is not zero-sized, and the pointer, once checked, is valid for
+    // writes of T. Box uses the global allocator with this same layout, so may free it.
+    unsafe {
+        let ptr = alloc(layout) as *mut T;
+        if ptr.is_null() {
+            let error = crate::errors::TryReserveError::new(crate::TryReserveErrorKind::AllocError);
+            return Err((error, value));
+        }
+        ptr.write(value);
+        Ok(Box::from_raw(ptr))
+    }
+}
+
 #[cfg(feature = "std")]
 everything!(
     "let mut data = anymap::AnyMap::new();",
+    "# use anymap::*;",
     std::collections
 );
 
+#[cfg(feature = "try-reserve")]
+fallible_alloc!(
+    "let mut data = anymap::AnyMap::new();",
+    "# use anymap::*;",
+    crate::errors::TryReserveError::from_std
+);
+
 #[cfg(feature = "hashbrown")]
 /// AnyMap backed by `hashbrown`.
 ///
@@ -591,9 +4095,16 @@ pub mod hashbrown {
 
     everything!(
         "let mut data = anymap::hashbrown::AnyMap::new();",
+        "# use anymap::hashbrown::*;",
         hashbrown,
         BuildHasherDefault<TypeIdHasher>
     );
+
+    fallible_alloc!(
+        "let mut data = anymap::hashbrown::AnyMap::new();",
+        "# use anymap::hashbrown::*;",
+        crate::errors::TryReserveError::from_hashbrown
+    );
 }
 
 /// A hasher designed to eke a little more speed out, given `TypeId`’s known characteristics.
@@ -634,8 +4145,16 @@ fn type_id_hasher() {
     fn verify_hashing_with(type_id: TypeId) {
         let mut hasher = TypeIdHasher::default();
         type_id.hash(&mut hasher);
-        // SAFETY: u64 is valid for all bit patterns.
-        assert_eq!(hasher.finish(), unsafe { core::mem::transmute::<TypeId, u64>(type_id) });
+        // TypeId used to be a u64, but newer compilers have grown it to 128 bits, of which only
+        // one half gets hashed. So just check the hash is one of its 64-bit chunks.
+        // SAFETY: TypeId is plain old data, and u8 is valid for all bit patterns.
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                &type_id as *const TypeId as *const u8,
+                core::mem::size_of::<TypeId>(),
+            )
+        };
+        assert!(bytes.chunks(8).any(|chunk| chunk == &hasher.finish().to_ne_bytes()[..]));
     }
     // Pick a variety of types, just to demonstrate it’s all sane. Normal, zero-sized, unsized, &c.
     verify_hashing_with(TypeId::of::<usize>());
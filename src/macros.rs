@@ -0,0 +1,416 @@
+//! Exported macros.
+
+/// Create an [`AnyMap`](crate::AnyMap) containing the given values, each one preceded by its type.
+///
+/// ```rust
+/// let data = anymap::anymap! {
+///     i32 => 42,
+///     String => String::from("hello"),
+/// };
+/// assert_eq!(data.get::<i32>(), Some(&42));
+/// assert_eq!(data.get::<String>().unwrap(), "hello");
+/// ```
+///
+/// A type given twice is a compile error, rather than the first value being silently dropped:
+///
+/// ```rust,compile_fail
+/// let data = anymap::anymap! {
+///     i32 => 1,
+///     i32 => 2,  // error[E0119]: conflicting implementations of trait `EachTypeOnlyOnce`
+/// };
+/// ```
+///
+/// (This works by implementing a local trait for each type, so type parameters of the enclosing
+/// function can’t be used.)
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! anymap {
+    ($($T:ty => $value:expr),* $(,)?) => {{
+        #[allow(dead_code)]
+        trait EachTypeOnlyOnce {}
+        $(impl EachTypeOnlyOnce for $T {})*
+
+        #[allow(unused_mut)]
+        let mut map = $crate::AnyMap::new();
+        $(
+            let _ = map.insert::<$T>($value);
+        )*
+        map
+    }};
+}
+
+/// Define a struct which can be converted to and from an [`AnyMap`](crate::AnyMap), one field per
+/// type, by value.
+///
+/// This is for following a dynamic assembly phase with a fully typed phase: build up an `AnyMap`,
+/// then move the values out into a struct. There are two ways of doing that:
+///
+/// - `try_from_map`, an associated function, which takes the fields’ values and hands back what’s
+///   left in the map alongside the struct;
+/// - `TryFrom<AnyMap>`, which insists that the map hold exactly the fields’ types, so that nothing
+///   is lost.
+///
+/// Both fail, giving the map back untouched, if any field’s type is missing; `TryFrom` also fails
+/// if the map holds anything else.
+///
+/// ```rust
+/// use std::convert::TryFrom;
+///
+/// #[derive(Debug)] pub struct Config(u32);
+/// #[derive(Debug)] pub struct Database(&'static str);
+///
+/// anymap::map_struct! {
+///     #[derive(Debug)]
+///     pub struct Context {
+///         pub config: Config,
+///         database: Database,
+///     }
+/// }
+///
+/// let mut data = anymap::AnyMap::new();
+/// data.insert(Config(1));
+/// let mut data = Context::try_from(data).unwrap_err();
+/// data.insert(Database("db"));
+/// let context = Context::try_from(data).unwrap();
+/// assert_eq!(context.config.0, 1);
+///
+/// let mut data = anymap::AnyMap::from(context);
+/// assert_eq!(data.get::<Database>().unwrap().0, "db");
+///
+/// data.insert(42u8);
+/// let data = Context::try_from(data).unwrap_err();
+/// let (context, rest) = Context::try_from_map(data).unwrap();
+/// assert_eq!(context.config.0, 1);
+/// assert_eq!(rest.get::<u8>(), Some(&42));
+/// ```
+///
+/// As with [`anymap!`](crate::anymap), each field must be of a different type, which is checked
+/// at compile time. Generic structs are not supported.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! map_struct {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident: $T:ty),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($(#[$field_attr])* $field_vis $field: $T),*
+        }
+
+        impl $name {
+            /// Moves the fields’ values out of the map, returning what’s left in it too, or the
+            /// map untouched if any field’s type is missing.
+            #[allow(dead_code)]
+            $vis fn try_from_map(mut map: $crate::AnyMap)
+                -> ::core::result::Result<($name, $crate::AnyMap), $crate::AnyMap>
+            {
+                #[allow(dead_code)]
+                trait EachTypeOnlyOnce {}
+                $(impl EachTypeOnlyOnce for $T {})*
+
+                if true $(&& map.contains::<$T>())* {
+                    let value = $name {
+                        $($field: map.remove::<$T>().unwrap()),*
+                    };
+                    ::core::result::Result::Ok((value, map))
+                } else {
+                    ::core::result::Result::Err(map)
+                }
+            }
+        }
+
+        impl ::core::convert::TryFrom<$crate::AnyMap> for $name {
+            type Error = $crate::AnyMap;
+
+            fn try_from(map: $crate::AnyMap) -> ::core::result::Result<$name, $crate::AnyMap> {
+                // The types are distinct, so if they’re all present this means nothing else is.
+                let fields: &[&str] = &[$(stringify!($field)),*];
+                if map.len() != fields.len() {
+                    return ::core::result::Result::Err(map);
+                }
+                $name::try_from_map(map).map(|(value, _)| value)
+            }
+        }
+
+        impl ::core::convert::From<$name> for $crate::AnyMap {
+            fn from(value: $name) -> $crate::AnyMap {
+                #[allow(unused_mut)]
+                let mut map = $crate::AnyMap::new();
+                $(let _ = map.insert(value.$field);)*
+                map
+            }
+        }
+    };
+}
+
+/// Define a struct of references into an [`AnyMap`](crate::AnyMap), a mix of `&'a T` and
+/// `&'a mut U` fields, borrowed all at once from one `&'a mut AnyMap` by its `project` function.
+///
+/// This is [`Map::with_mut2`](crate::Map::with_mut2) and friends for any number of types, where
+/// the shape of the access is known up front, and left in a struct to be passed around.
+///
+/// ```rust
+/// #[derive(Debug)] pub struct Config(u32);
+/// #[derive(Debug)] pub struct Counter(u32);
+///
+/// anymap::project! {
+///     pub struct Step<'a> {
+///         config: &'a Config,
+///         pub counter: &'a mut Counter,
+///     }
+/// }
+///
+/// let mut data = anymap::AnyMap::new();
+/// data.insert(Config(2));
+/// assert!(Step::project(&mut data).is_none());
+/// data.insert(Counter(0));
+/// let step = Step::project(&mut data).unwrap();
+/// step.counter.0 += step.config.0;
+/// assert_eq!(data.get::<Counter>().unwrap().0, 2);
+/// ```
+///
+/// `project` returns `None` if any field’s type is missing. As with [`anymap!`](crate::anymap),
+/// each field must be of a different type, which is checked at compile time:
+///
+/// ```rust,compile_fail
+/// anymap::project! {
+///     struct Aliased<'a> {
+///         shared: &'a u32,
+///         unique: &'a mut u32,  // error[E0119]: conflicting implementations of trait `EachTypeOnlyOnce`
+///     }
+/// }
+/// ```
+///
+/// The struct must have exactly one lifetime parameter, and no other generics.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! project {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident<$lt:lifetime> {
+            $($fields:tt)*
+        }
+    ) => {
+        $crate::project!(@fields [$(#[$attr])* $vis $name $lt] [] $($fields)*);
+    };
+
+    // Each field is munched separately, as which kind of reference it is takes lookahead.
+    (
+        @fields $head:tt [$($done:tt)*]
+        $(#[$field_attr:meta])* $field_vis:vis $field:ident: &$field_lt:lifetime mut $T:ty
+        $(, $($rest:tt)*)?
+    ) => {
+        $crate::project!(
+            @fields $head
+            [$($done)* [$(#[$field_attr])* $field_vis $field: &$field_lt mut $T, $T]]
+            $($($rest)*)?
+        );
+    };
+    (
+        @fields $head:tt [$($done:tt)*]
+        $(#[$field_attr:meta])* $field_vis:vis $field:ident: &$field_lt:lifetime $T:ty
+        $(, $($rest:tt)*)?
+    ) => {
+        $crate::project!(
+            @fields $head
+            [$($done)* [$(#[$field_attr])* $field_vis $field: &$field_lt $T, $T]]
+            $($($rest)*)?
+        );
+    };
+
+    (
+        @fields [$(#[$attr:meta])* $vis:vis $name:ident $lt:lifetime]
+        [$([$(#[$field_attr:meta])* $field_vis:vis $field:ident: $field_ty:ty, $T:ty])*]
+    ) => {
+        $(#[$attr])*
+        $vis struct $name<$lt> {
+            $($(#[$field_attr])* $field_vis $field: $field_ty),*
+        }
+
+        impl<$lt> $name<$lt> {
+            /// Borrow each field’s value from `map`, or return `None` if any is missing.
+            #[allow(dead_code)]
+            $vis fn project(map: &$lt mut $crate::AnyMap) -> ::core::option::Option<$name<$lt>> {
+                #[allow(dead_code)]
+                trait EachTypeOnlyOnce {}
+                $(impl EachTypeOnlyOnce for $T {})*
+
+                $(let $field: *mut $T = map.get_mut::<$T>()?;)*
+                // SAFETY: the types are distinct, so these point into different boxes.
+                ::core::option::Option::Some($name {
+                    $($field: unsafe { &mut *$field }),*
+                })
+            }
+        }
+    };
+}
+
+/// Borrow a tuple of values from a [`Map`](crate::Map) at once, a mix of `&T`, `&mut U` and
+/// `Option`s of them, such as `(&Foo, &mut Bar, Option<&Baz>)`.
+///
+/// This is [`project!`](crate::project) for one-off use, without defining a struct.
+///
+/// ```rust
+/// let mut data = anymap::AnyMap::new();
+/// data.insert(2u8);
+/// data.insert(3u16);
+/// let (a, b, c) = anymap::query!(&mut data, (&u8, &mut u16, Option<&u32>)).unwrap();
+/// *b *= *a as u16 + c.map_or(0, |c| *c as u16);
+/// assert_eq!(data.get(), Some(&6u16));
+/// assert!(anymap::query!(&mut data, (&mut u32,)).is_none());
+/// ```
+///
+/// This evaluates to `None` if any type not in an `Option` is missing. As with
+/// [`anymap!`](crate::anymap), each type must be different, which is checked at compile time, so
+/// that no value can be borrowed mutably and also borrowed again:
+///
+/// ```rust,compile_fail
+/// let mut data = anymap::AnyMap::new();
+/// let _ = anymap::query!(&mut data, (&u32, &mut u32));  // error[E0119]: conflicting implementations of trait `EachTypeOnlyOnce`
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! query {
+    // Each borrow is munched separately, as which kind it is takes lookahead.
+    (@items $map:tt [$($done:tt)*] &mut $T:ty $(, $($rest:tt)*)?) => {
+        $crate::query!(@items $map [$($done)* [unique $T]] $($($rest)*)?)
+    };
+    (@items $map:tt [$($done:tt)*] &$T:ty $(, $($rest:tt)*)?) => {
+        $crate::query!(@items $map [$($done)* [shared $T]] $($($rest)*)?)
+    };
+    (@items $map:tt [$($done:tt)*] Option<&mut $T:ty> $(, $($rest:tt)*)?) => {
+        $crate::query!(@items $map [$($done)* [optional_unique $T]] $($($rest)*)?)
+    };
+    (@items $map:tt [$($done:tt)*] Option<&$T:ty> $(, $($rest:tt)*)?) => {
+        $crate::query!(@items $map [$($done)* [optional_shared $T]] $($($rest)*)?)
+    };
+    (@items $map:tt [$([$kind:ident $T:ty])*]) => {{
+        #[allow(dead_code)]
+        trait EachTypeOnlyOnce {}
+        $(impl EachTypeOnlyOnce for $T {})*
+
+        // The flavour parameter is named to keep out of the way of the types being borrowed.
+        fn query<__A: ?Sized + $crate::Downcast>(map: &mut $crate::Map<__A>)
+            -> ::core::option::Option<($($crate::query!(@type '_ $kind $T),)*)>
+        where
+            $($T: $crate::IntoBox<__A>),*
+        {
+            ::core::option::Option::Some(($($crate::query!(@fetch map $kind $T)?,)*))
+        }
+        query($map)
+    }};
+
+    (@type $lt:lifetime shared $T:ty) => { &$lt $T };
+    (@type $lt:lifetime unique $T:ty) => { &$lt mut $T };
+    (@type $lt:lifetime optional_shared $T:ty) => { ::core::option::Option<&$lt $T> };
+    (@type $lt:lifetime optional_unique $T:ty) => { ::core::option::Option<&$lt mut $T> };
+
+    // SAFETY: the types are distinct, so these point into different boxes.
+    (@fetch $map:ident shared $T:ty) => {
+        $map.get_mut::<$T>().map(|value| unsafe { &*(value as *mut $T) })
+    };
+    (@fetch $map:ident unique $T:ty) => {
+        $map.get_mut::<$T>().map(|value| unsafe { &mut *(value as *mut $T) })
+    };
+    (@fetch $map:ident optional_shared $T:ty) => {
+        ::core::option::Option::Some($crate::query!(@fetch $map shared $T))
+    };
+    (@fetch $map:ident optional_unique $T:ty) => {
+        ::core::option::Option::Some($crate::query!(@fetch $map unique $T))
+    };
+
+    ($map:expr, ($($items:tt)*)) => {
+        $crate::query!(@items $map [] $($items)*)
+    };
+}
+
+/// Get the entries for several types at once from a [`Map`](crate::Map), as
+/// [`Map::entry_many`](crate::Map::entry_many) does, but checking at compile time that the types
+/// are distinct rather than panicking at runtime.
+///
+/// ```rust
+/// #[derive(Debug, PartialEq)] struct Width(u32);
+/// #[derive(Debug, PartialEq)] struct Height(u32);
+///
+/// let mut data = anymap::AnyMap::new();
+/// data.insert(Width(16));
+/// {
+///     let mut entries = anymap::entry_many!(&mut data, (Width, Height));
+///     let (width, height) = entries.entries();
+///     let width = width.or_insert(Width(4));
+///     height.or_insert_with(|| Height(width.0 * 9 / 16));
+/// }
+/// assert_eq!(data.get(), Some(&Height(9)));
+/// ```
+///
+/// ```rust,compile_fail
+/// let mut data = anymap::AnyMap::new();
+/// let _ = anymap::entry_many!(&mut data, (u32, u8, u32));  // error[E0119]: conflicting implementations of trait `EachTypeOnlyOnce`
+/// ```
+///
+/// (As with [`anymap!`](crate::anymap), type parameters of the enclosing function can’t be used.)
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! entry_many {
+    ($map:expr, ($($T:ty),+ $(,)?)) => {{
+        #[allow(dead_code)]
+        trait EachTypeOnlyOnce {}
+        $(impl EachTypeOnlyOnce for $T {})+
+
+        $map.entry_many::<($($T,)+)>()
+    }};
+}
+
+/// Define an extension trait of named getters for an [`AnyMap`](crate::AnyMap), one per type,
+/// so that `data.config()` can stand for `data.get::<Config>()`.
+///
+/// ```rust
+/// pub struct Config(u32);
+/// pub struct Pool(&'static str);
+///
+/// anymap::accessors! {
+///     /// The values every request handler can get at.
+///     pub trait Context {
+///         /// The configuration, once loaded.
+///         config: Config,
+///         pool: Pool,
+///     }
+/// }
+///
+/// let mut data = anymap::AnyMap::new();
+/// data.insert(Config(1));
+/// assert_eq!(data.config().unwrap().0, 1);
+/// assert!(data.pool().is_none());
+/// ```
+///
+/// Each getter returns `None` if the map has no value of its type.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! accessors {
+    (
+        $(#[$attr:meta])*
+        $vis:vis trait $name:ident {
+            $($(#[$getter_attr:meta])* $getter:ident: $T:ty),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis trait $name {
+            $(
+                $(#[$getter_attr])*
+                fn $getter(&self) -> ::core::option::Option<&$T>;
+            )*
+        }
+
+        impl $name for $crate::AnyMap {
+            $(
+                #[inline]
+                fn $getter(&self) -> ::core::option::Option<&$T> {
+                    self.get::<$T>()
+                }
+            )*
+        }
+    };
+}
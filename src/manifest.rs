@@ -0,0 +1,136 @@
+//! `Manifest`, a list of the types a map contains, for checking contracts between processes.
+
+use core::fmt;
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::{String, ToString}, vec::Vec};
+
+use crate::TypeInfo;
+
+/// A list of the types a map contains, from `Map::manifest`.
+///
+/// `TypeId`s are meaningless outside the build that produced them, so a manifest is exchanged
+/// between processes by its type names, which need the type-names Cargo feature: its `Display`
+/// form has one name per line, and [`check_names`](Manifest::check_names) compares it with such a
+/// list, as received with some serialized payload, before trying to deserialize it.
+///
+/// Within one process, [`Map::reserve_for_manifest`](crate::Map::reserve_for_manifest) uses a
+/// manifest to size a new map.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    types: Vec<TypeInfo>,
+}
+
+impl Manifest {
+    /// Make a manifest of the given types, sorted by name.
+    pub(crate) fn new(mut types: Vec<TypeInfo>) -> Manifest {
+        types.sort_by(|a, b| a.name.cmp(&b.name).then(a.type_id.cmp(&b.type_id)));
+        Manifest { types }
+    }
+
+    /// The types, sorted by name (those with no name known first).
+    #[inline]
+    pub fn types(&self) -> &[TypeInfo] {
+        &self.types
+    }
+
+    /// The number of types.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Returns true if there are no types.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// The names of the types, sorted, skipping any with no name known.
+    #[inline]
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.types.iter().filter_map(|info| info.name)
+    }
+
+    /// Compare the manifest with a list of type names (such as another process’s manifest, in its
+    /// `Display` form), reporting which of its types are missing from the list and which names in
+    /// the list are unexpected.
+    ///
+    /// Types with no name known can’t be matched, so they are always missing.
+    pub fn check_names<'a, I>(&self, names: I) -> Result<(), ManifestError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut unexpected = Vec::new();
+        let mut found = Vec::new();
+        for name in names {
+            match self.types.iter().position(|info| info.name == Some(name)) {
+                Some(i) => found.push(i),
+                None => unexpected.push(name.to_string()),
+            }
+        }
+        let missing: Vec<TypeInfo> = self.types.iter()
+            .enumerate()
+            .filter(|&(i, _)| !found.contains(&i))
+            .map(|(_, &info)| info)
+            .collect();
+        if missing.is_empty() && unexpected.is_empty() {
+            Ok(())
+        } else {
+            Err(ManifestError { missing, unexpected })
+        }
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for info in &self.types {
+            writeln!(f, "{}", info)?;
+        }
+        Ok(())
+    }
+}
+
+/// The differences between a [`Manifest`] and a list of type names, from
+/// `Manifest::check_names`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestError {
+    missing: Vec<TypeInfo>,
+    unexpected: Vec<String>,
+}
+
+impl ManifestError {
+    /// The manifest’s types which the list doesn’t name.
+    #[inline]
+    pub fn missing(&self) -> &[TypeInfo] {
+        &self.missing
+    }
+
+    /// The names in the list which aren’t in the manifest.
+    #[inline]
+    pub fn unexpected(&self) -> &[String] {
+        &self.unexpected
+    }
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("type names do not match manifest")?;
+        if !self.missing.is_empty() {
+            f.write_str("; missing: ")?;
+            for (i, info) in self.missing.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", info)?;
+            }
+        }
+        if !self.unexpected.is_empty() {
+            write!(f, "; unexpected: {}", self.unexpected.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ManifestError {}
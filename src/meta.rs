@@ -0,0 +1,149 @@
+//! `MetaMap`, which keeps a metadata value alongside each entry.
+
+macro_rules! meta_map {
+    ($example_use:literal) => {
+        /// A value in a [`MetaMap`], with its metadata.
+        #[derive(Debug)]
+        struct MetaSlot<M, A: ?Sized> {
+            meta: M,
+            value: Box<A>,
+        }
+
+        /// A collection like [`Map`], but with a metadata value of type `M` attached to each
+        /// entry.
+        ///
+        /// This is for things like registries recording which plugin put each value into a
+        /// shared map: the metadata is set with [`insert_with_meta`](MetaMap::insert_with_meta),
+        /// got by type with [`meta`](MetaMap::meta), and seen for all entries with
+        /// [`iter_raw`](MetaMap::iter_raw).
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// let mut data = MetaMap::<&'static str>::new();
+        /// data.insert_with_meta(42i32, "plugin-a");
+        /// data.insert_with_meta(String::from("hello"), "plugin-b");
+        /// assert_eq!(data.get::<i32>(), Some(&42));
+        /// assert_eq!(data.meta::<String>(), Some(&"plugin-b"));
+        /// let mut owners: Vec<_> = data.iter_raw().map(|(_, _, owner)| *owner).collect();
+        /// owners.sort();
+        /// assert_eq!(owners, ["plugin-a", "plugin-b"]);
+        /// ```
+        #[derive(Debug)]
+        pub struct MetaMap<M, A: ?Sized + Downcast = dyn Any> {
+            raw: HashMap<TypeId, MetaSlot<M, A>, BuildHasherDefault<TypeIdHasher>>,
+        }
+
+        impl<M, A: ?Sized + Downcast> Default for MetaMap<M, A> {
+            #[inline]
+            fn default() -> MetaMap<M, A> {
+                MetaMap::new()
+            }
+        }
+
+        impl<M, A: ?Sized + Downcast> MetaMap<M, A> {
+            /// Create an empty collection.
+            #[inline]
+            pub fn new() -> MetaMap<M, A> {
+                MetaMap {
+                    raw: HashMap::with_hasher(Default::default()),
+                }
+            }
+
+            /// Returns the number of items in the collection.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.raw.len()
+            }
+
+            /// Returns true if there are no items in the collection.
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.raw.is_empty()
+            }
+
+            /// Removes all items from the collection. Keeps the allocated memory for reuse.
+            #[inline]
+            pub fn clear(&mut self) {
+                self.raw.clear()
+            }
+
+            /// Returns a reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get<T: IntoBox<A>>(&self) -> Option<&T> {
+                self.raw.get(&TypeId::of::<T>())
+                    .map(|slot| unsafe { slot.value.downcast_ref_unchecked::<T>() })
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+                self.raw.get_mut(&TypeId::of::<T>())
+                    .map(|slot| unsafe { slot.value.downcast_mut_unchecked::<T>() })
+            }
+
+            /// Returns a reference to the metadata of the value stored in the collection for the
+            /// type `T`, if it exists.
+            #[inline]
+            pub fn meta<T: IntoBox<A>>(&self) -> Option<&M> {
+                self.raw.get(&TypeId::of::<T>()).map(|slot| &slot.meta)
+            }
+
+            /// Returns a mutable reference to the metadata of the value stored in the collection
+            /// for the type `T`, if it exists.
+            #[inline]
+            pub fn meta_mut<T: IntoBox<A>>(&mut self) -> Option<&mut M> {
+                self.raw.get_mut(&TypeId::of::<T>()).map(|slot| &mut slot.meta)
+            }
+
+            /// Sets the value stored in the collection for the type `T`, with its metadata.
+            /// If the collection already had a value of type `T`, that value and its metadata are
+            /// returned. Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert_with_meta<T: IntoBox<A>>(&mut self, value: T, meta: M) -> Option<(T, M)> {
+                let slot = MetaSlot {
+                    meta,
+                    value: value.into_box(),
+                };
+                self.raw.insert(TypeId::of::<T>(), slot)
+                    .map(|slot| (unsafe { *slot.value.downcast_unchecked::<T>() }, slot.meta))
+            }
+
+            /// Sets the value stored in the collection for the type `T`, with default metadata.
+            /// If the collection already had a value of type `T`, that value is returned (its
+            /// metadata is dropped). Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> where M: Default {
+                self.insert_with_meta(value, M::default()).map(|(value, _)| value)
+            }
+
+            /// Removes the `T` value from the collection, returning it and its metadata if there
+            /// was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<(T, M)> {
+                self.raw.remove(&TypeId::of::<T>())
+                    .map(|slot| (unsafe { *slot.value.downcast_unchecked::<T>() }, slot.meta))
+            }
+
+            /// Returns true if the collection contains a value of type `T`.
+            #[inline]
+            pub fn contains<T: IntoBox<A>>(&self) -> bool {
+                self.raw.contains_key(&TypeId::of::<T>())
+            }
+
+            /// An iterator visiting all entries, with their metadata, in arbitrary order.
+            #[inline]
+            pub fn iter_raw(&self) -> impl Iterator<Item = (TypeId, &A, &M)> {
+                self.raw.iter().map(|(&type_id, slot)| (type_id, &*slot.value, &slot.meta))
+            }
+
+            /// Convert this into a plain [`Map`], discarding the metadata.
+            pub fn into_map(self) -> Map<A> {
+                let raw = self.raw.into_iter().map(|(type_id, slot)| (type_id, slot.value)).collect();
+                // SAFETY: the keys were all checked on the way in.
+                unsafe { Map::from_raw(raw) }
+            }
+        }
+    };
+}
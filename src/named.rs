@@ -0,0 +1,131 @@
+//! `NamedMap`, which can also be queried by registered string names.
+
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::collections::BTreeMap;
+#[cfg(feature = "std")]
+pub(crate) use std::collections::BTreeMap;
+
+macro_rules! named_map {
+    ($example_use:literal) => {
+        /// A [`Map`] with a secondary index by name, for scripting layers, debug consoles and the
+        /// like which need to query it dynamically while Rust code keeps using the typed API.
+        ///
+        /// Names are registered for types with [`register_name`](NamedMap::register_name), or
+        /// [`insert_named`](NamedMap::insert_named) which also inserts a value. The map itself is
+        /// reached through `Deref` and `DerefMut`, and a name whose type has no value in the map
+        /// just finds nothing.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// struct Config { verbose: bool }
+        ///
+        /// let mut data = NamedMap::<dyn std::any::Any>::new();
+        /// data.insert_named("my_crate::Config", Config { verbose: true });
+        /// assert!(data.get::<Config>().unwrap().verbose);
+        /// let config = data.get_by_name("my_crate::Config").unwrap();
+        /// assert!(config.downcast_ref::<Config>().unwrap().verbose);
+        /// assert!(data.get_by_name("my_crate::Other").is_none());
+        /// ```
+        #[derive(Debug)]
+        pub struct NamedMap<A: ?Sized + Downcast = dyn Any> {
+            map: Map<A>,
+            names: crate::named::BTreeMap<&'static str, TypeId>,
+        }
+
+        impl<A: ?Sized + Downcast> Default for NamedMap<A> {
+            #[inline]
+            fn default() -> NamedMap<A> {
+                NamedMap::new()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> From<Map<A>> for NamedMap<A> {
+            #[inline]
+            fn from(map: Map<A>) -> NamedMap<A> {
+                NamedMap {
+                    map,
+                    names: crate::named::BTreeMap::new(),
+                }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> core::ops::Deref for NamedMap<A> {
+            type Target = Map<A>;
+
+            #[inline]
+            fn deref(&self) -> &Map<A> {
+                &self.map
+            }
+        }
+
+        impl<A: ?Sized + Downcast> core::ops::DerefMut for NamedMap<A> {
+            #[inline]
+            fn deref_mut(&mut self) -> &mut Map<A> {
+                &mut self.map
+            }
+        }
+
+        impl<A: ?Sized + Downcast> NamedMap<A> {
+            /// Create an empty collection with no names registered.
+            #[inline]
+            pub fn new() -> NamedMap<A> {
+                Map::new().into()
+            }
+
+            /// Register `name` for the type `T`, replacing whatever type it was registered for,
+            /// if any. A type can have any number of names.
+            #[inline]
+            pub fn register_name<T: IntoBox<A>>(&mut self, name: &'static str) {
+                let _ = self.names.insert(name, TypeId::of::<T>());
+            }
+
+            /// Register `name` for the type `T` and set the value stored in the collection for it.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert_named<T: IntoBox<A>>(&mut self, name: &'static str, value: T) -> Option<T> {
+                self.register_name::<T>(name);
+                self.map.insert(value)
+            }
+
+            /// Forget the name `name`, returning the `TypeId` it was registered for, if any.
+            /// The value, if any, stays in the collection.
+            #[inline]
+            pub fn unregister_name(&mut self, name: &str) -> Option<TypeId> {
+                self.names.remove(name)
+            }
+
+            /// Returns the value stored in the collection for the type registered as `name`,
+            /// if there is one.
+            #[inline]
+            pub fn get_by_name(&self, name: &str) -> Option<&A> {
+                self.names.get(name).and_then(|type_id| self.map.as_raw().get(type_id))
+                    .map(|value| &**value)
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type
+            /// registered as `name`, if there is one.
+            #[inline]
+            pub fn get_mut_by_name(&mut self, name: &str) -> Option<&mut A> {
+                let type_id = *self.names.get(name)?;
+                // SAFETY: the value isn’t replaced, so the key stays correct.
+                unsafe { self.map.as_raw_mut() }.get_mut(&type_id).map(|value| &mut **value)
+            }
+
+            /// An iterator visiting, in order, the registered names whose types have values in the
+            /// collection, with those values.
+            #[inline]
+            pub fn iter_named(&self) -> impl Iterator<Item = (&'static str, &A)> {
+                let raw = self.map.as_raw();
+                self.names.iter()
+                    .filter_map(move |(&name, type_id)| raw.get(type_id).map(|value| (name, &**value)))
+            }
+
+            /// Unwrap the underlying map, forgetting the names.
+            #[inline]
+            pub fn into_inner(self) -> Map<A> {
+                self.map
+            }
+        }
+    };
+}
@@ -0,0 +1,144 @@
+//! `ObservedMap`, which reports its mutations down channels.
+
+use core::any::TypeId;
+
+/// A mutation of an `ObservedMap`, as received from `ObservedMap::subscribe`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// A value was inserted where there was none before.
+    Inserted(TypeId),
+    /// A value was inserted, replacing an existing value.
+    Replaced(TypeId),
+    /// A value was removed.
+    Removed(TypeId),
+}
+
+macro_rules! observed_map {
+    ($example_use:literal) => {
+        /// A [`Map`] which reports each insertion and removal to its subscribers.
+        ///
+        /// Reading happens through `Deref<Target = Map<A>>`; mutation through the methods here,
+        /// each of which sends an [`Event`](crate::Event) down every channel got from
+        /// [`subscribe`](ObservedMap::subscribe). The channels are `std::sync::mpsc` ones, so
+        /// events can be received on another thread, and forwarded into an async channel there if
+        /// that’s what you need.
+        ///
+        /// `get_mut` is available too, but isn’t reported: there’s no telling whether the value
+        /// is actually changed.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// let mut data = ObservedMap::<dyn std::any::Any>::new();
+        /// let events = data.subscribe();
+        /// data.insert(1u8);
+        /// data.insert(2u8);
+        /// data.remove::<u8>();
+        /// assert_eq!(data.get::<u8>(), None);
+        /// use anymap::Event::*;
+        /// let u8 = std::any::TypeId::of::<u8>();
+        /// assert_eq!(events.try_iter().collect::<Vec<_>>(), [Inserted(u8), Replaced(u8), Removed(u8)]);
+        /// ```
+        #[derive(Debug)]
+        pub struct ObservedMap<A: ?Sized + Downcast = dyn Any> {
+            map: Map<A>,
+            subscribers: Vec<std::sync::mpsc::Sender<crate::Event>>,
+        }
+
+        impl<A: ?Sized + Downcast> Default for ObservedMap<A> {
+            #[inline]
+            fn default() -> ObservedMap<A> {
+                ObservedMap::new()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> From<Map<A>> for ObservedMap<A> {
+            #[inline]
+            fn from(map: Map<A>) -> ObservedMap<A> {
+                ObservedMap {
+                    map,
+                    subscribers: Vec::new(),
+                }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> core::ops::Deref for ObservedMap<A> {
+            type Target = Map<A>;
+
+            #[inline]
+            fn deref(&self) -> &Map<A> {
+                &self.map
+            }
+        }
+
+        impl<A: ?Sized + Downcast> ObservedMap<A> {
+            /// Create an empty collection with no subscribers.
+            #[inline]
+            pub fn new() -> ObservedMap<A> {
+                Map::new().into()
+            }
+
+            /// Subscribe to the events of all future mutations.
+            ///
+            /// Subscribers that have hung up are forgotten the next time there is an event.
+            #[inline]
+            pub fn subscribe(&mut self) -> std::sync::mpsc::Receiver<crate::Event> {
+                let (sender, receiver) = std::sync::mpsc::channel();
+                self.subscribers.push(sender);
+                receiver
+            }
+
+            fn emit(&mut self, event: crate::Event) {
+                self.subscribers.retain(|sender| sender.send(event).is_ok());
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists. This is *not* reported to subscribers.
+            #[inline]
+            pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+                self.map.get_mut::<T>()
+            }
+
+            /// Sets the value stored in the collection for the type `T`, reporting
+            /// `Event::Inserted` or `Event::Replaced`.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> {
+                let old = self.map.insert(value);
+                let type_id = TypeId::of::<T>();
+                self.emit(match old {
+                    Some(_) => crate::Event::Replaced(type_id),
+                    None => crate::Event::Inserted(type_id),
+                });
+                old
+            }
+
+            /// Removes the `T` value from the collection, reporting `Event::Removed` if there
+            /// was one, and returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+                let old = self.map.remove::<T>();
+                if old.is_some() {
+                    self.emit(crate::Event::Removed(TypeId::of::<T>()));
+                }
+                old
+            }
+
+            /// Removes all items from the collection, reporting `Event::Removed` for each.
+            /// Keeps the allocated memory for reuse.
+            pub fn clear(&mut self) {
+                let type_ids: Vec<TypeId> = self.map.as_raw().keys().cloned().collect();
+                self.map.clear();
+                for type_id in type_ids {
+                    self.emit(crate::Event::Removed(type_id));
+                }
+            }
+
+            /// Unwrap the underlying map, dropping all the subscribers.
+            #[inline]
+            pub fn into_inner(self) -> Map<A> {
+                self.map
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! `Ops`, a batch of mutations applied to a map all at once or not at all.
+
+macro_rules! ops {
+    ($example_init:literal, $example_use:literal) => {
+        /// One operation in an [`Ops`] batch.
+        #[derive(Debug)]
+        enum Op<A: ?Sized> {
+            Insert(crate::TypeInfo, Box<A>),
+            Replace(crate::TypeInfo, Box<A>),
+            Remove(crate::TypeInfo),
+        }
+
+        /// A batch of insertions, replacements and removals, for [`Map::apply`].
+        ///
+        /// The whole batch is checked before any of it is applied, so mutations staged from
+        /// several sources either all happen or, if any conflicts, none do. Operations are
+        /// checked and applied in the order they were added.
+        #[derive(Debug)]
+        pub struct Ops<A: ?Sized + Downcast = dyn Any> {
+            ops: Vec<Op<A>>,
+        }
+
+        impl<A: ?Sized + Downcast> Default for Ops<A> {
+            #[inline]
+            fn default() -> Ops<A> {
+                Ops::new()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> Ops<A> {
+            /// Create an empty batch.
+            #[inline]
+            pub fn new() -> Ops<A> {
+                Ops { ops: Vec::new() }
+            }
+
+            /// Insert `value`, which requires that there be no value of type `T` at that point.
+            #[inline]
+            pub fn insert<T: IntoBox<A>>(mut self, value: T) -> Ops<A> {
+                self.ops.push(Op::Insert(crate::TypeInfo::of::<T>(), value.into_box()));
+                self
+            }
+
+            /// Replace the value of type `T` with `value`, which requires that there be one at
+            /// that point.
+            #[inline]
+            pub fn replace<T: IntoBox<A>>(mut self, value: T) -> Ops<A> {
+                self.ops.push(Op::Replace(crate::TypeInfo::of::<T>(), value.into_box()));
+                self
+            }
+
+            /// Remove the value of type `T`, which requires that there be one at that point.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(mut self) -> Ops<A> {
+                self.ops.push(Op::Remove(crate::TypeInfo::of::<T>()));
+                self
+            }
+
+            /// Returns the number of operations in the batch.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.ops.len()
+            }
+
+            /// Returns true if there are no operations in the batch.
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.ops.is_empty()
+            }
+        }
+
+        /// The outcome of a successful [`Map::apply`]: the values replaced or removed.
+        #[derive(Debug)]
+        pub struct Applied<A: ?Sized + Downcast = dyn Any> {
+            displaced: Map<A>,
+        }
+
+        impl<A: ?Sized + Downcast> Applied<A> {
+            /// The values replaced or removed by the batch. If one type was displaced more than
+            /// once, this has the last such value.
+            #[inline]
+            pub fn displaced(&self) -> &Map<A> {
+                &self.displaced
+            }
+
+            /// Take the values replaced or removed by the batch.
+            #[inline]
+            pub fn into_displaced(self) -> Map<A> {
+                self.displaced
+            }
+        }
+
+        impl<A: ?Sized + Downcast> Map<A> {
+            /// Apply a batch of operations, all of them or, if any isn’t possible, none of them.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            #[doc = $example_init]
+            /// data.insert(1u8);
+            /// let applied = data.apply(Ops::new().replace(2u8).insert("new")).unwrap();
+            /// assert_eq!(applied.displaced().get::<u8>(), Some(&1));
+            /// assert_eq!(data.get::<&str>(), Some(&"new"));
+            ///
+            /// // The insertion conflicts, so the removal doesn’t happen either.
+            /// let error = data.apply(Ops::new().remove::<u8>().insert("newer")).unwrap_err();
+            /// assert_eq!(error.index(), 1);
+            /// assert_eq!(data.get::<u8>(), Some(&2));
+            /// ```
+            pub fn apply(&mut self, ops: Ops<A>) -> Result<Applied<A>, crate::errors::OpsError> {
+                // Check the whole batch against what will be present by then.
+                let mut changes: HashMap<TypeId, bool, BuildHasherDefault<TypeIdHasher>> =
+                    HashMap::with_hasher(Default::default());
+                for (index, op) in ops.ops.iter().enumerate() {
+                    let (type_info, occupied, after) = match *op {
+                        Op::Insert(type_info, _) => (type_info, false, true),
+                        Op::Replace(type_info, _) => (type_info, true, true),
+                        Op::Remove(type_info) => (type_info, true, false),
+                    };
+                    let type_id = type_info.type_id;
+                    let present = changes.get(&type_id).cloned()
+                        .unwrap_or_else(|| self.raw.contains_key(&type_id));
+                    if present != occupied {
+                        return Err(crate::errors::OpsError { index, type_info, occupied: present });
+                    }
+                    let _ = changes.insert(type_id, after);
+                }
+
+                let mut displaced = Map::new();
+                for op in ops.ops {
+                    let old = match op {
+                        Op::Insert(type_info, value) | Op::Replace(type_info, value) => {
+                            #[cfg(feature = "type-names")]
+                            {
+                                if let Some(name) = type_info.name {
+                                    let _ = self.names.entry(type_info.type_id).or_insert(name);
+                                }
+                            }
+                            self.raw.insert(type_info.type_id, value)
+                        },
+                        Op::Remove(type_info) => self.raw.remove(&type_info.type_id),
+                    };
+                    if let Some(old) = old {
+                        let _ = displaced.raw.insert_checked(old);
+                    }
+                }
+                Ok(Applied { displaced })
+            }
+        }
+    };
+}
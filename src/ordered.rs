@@ -0,0 +1,196 @@
+//! `OrderedMap`, backed by a `BTreeMap` rather than a hash table.
+
+use core::any::{Any, TypeId};
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, collections::BTreeMap};
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
+
+use crate::any::{Downcast, IntoBox};
+
+/// Raw access to the underlying `BTreeMap` of an [`OrderedMap`].
+pub type RawOrderedMap<A> = BTreeMap<TypeId, Box<A>>;
+
+/// A collection like [`Map`](crate::Map), but backed by a `BTreeMap` instead of a `HashMap`.
+///
+/// This iterates in a deterministic order (that of `TypeId`, which is stable for a given build),
+/// has no hasher state, and is smaller for tiny maps; which suits reproducible builds and
+/// snapshot tests. It needs neither std nor hashbrown, just alloc.
+///
+/// ```rust
+/// let mut data = anymap::OrderedAnyMap::new();
+/// data.insert(1u8);
+/// data.insert("two");
+/// assert_eq!(data.get::<u8>(), Some(&1));
+/// // The keys come out sorted, whatever order they went in.
+/// let keys: Vec<_> = data.keys().collect();
+/// let mut sorted = keys.clone();
+/// sorted.sort();
+/// assert_eq!(keys, sorted);
+/// ```
+#[derive(Debug)]
+pub struct OrderedMap<A: ?Sized + Downcast = dyn Any> {
+    raw: RawOrderedMap<A>,
+}
+
+/// The most common type of `OrderedMap`: just using `Any`;
+/// <code>[OrderedMap]&lt;dyn [Any]&gt;</code>.
+pub type OrderedAnyMap = OrderedMap<dyn Any>;
+
+// #[derive(Clone)] would want A to implement Clone, but in reality only Box<A> can.
+impl<A: ?Sized + Downcast> Clone for OrderedMap<A> where Box<A>: Clone {
+    #[inline]
+    fn clone(&self) -> OrderedMap<A> {
+        OrderedMap {
+            raw: self.raw.clone(),
+        }
+    }
+}
+
+impl<A: ?Sized + Downcast> Default for OrderedMap<A> {
+    #[inline]
+    fn default() -> OrderedMap<A> {
+        OrderedMap::new()
+    }
+}
+
+impl<A: ?Sized + Downcast> OrderedMap<A> {
+    /// Create an empty collection.
+    #[inline]
+    pub fn new() -> OrderedMap<A> {
+        OrderedMap {
+            raw: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of items in the collection.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// Returns true if there are no items in the collection.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    /// Removes all items from the collection.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.raw.clear()
+    }
+
+    /// Returns a reference to the value stored in the collection for the type `T`,
+    /// if it exists.
+    #[inline]
+    pub fn get<T: IntoBox<A>>(&self) -> Option<&T> {
+        self.raw.get(&TypeId::of::<T>())
+            .map(|any| unsafe { any.downcast_ref_unchecked::<T>() })
+    }
+
+    /// Returns a mutable reference to the value stored in the collection for the type `T`,
+    /// if it exists.
+    #[inline]
+    pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+        self.raw.get_mut(&TypeId::of::<T>())
+            .map(|any| unsafe { any.downcast_mut_unchecked::<T>() })
+    }
+
+    /// Sets the value stored in the collection for the type `T`.
+    /// If the collection already had a value of type `T`, that value is returned.
+    /// Otherwise, `None` is returned.
+    #[inline]
+    pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> {
+        self.raw.insert(TypeId::of::<T>(), value.into_box())
+            .map(|any| unsafe { *any.downcast_unchecked::<T>() })
+    }
+
+    /// Removes the `T` value from the collection,
+    /// returning it if there was one or `None` if there was not.
+    #[inline]
+    pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+        self.raw.remove(&TypeId::of::<T>())
+            .map(|any| unsafe { *any.downcast_unchecked::<T>() })
+    }
+
+    /// Returns true if the collection contains a value of type `T`.
+    #[inline]
+    pub fn contains<T: IntoBox<A>>(&self) -> bool {
+        self.raw.contains_key(&TypeId::of::<T>())
+    }
+
+    /// An iterator visiting the keys of all the values in the collection, in order.
+    #[inline]
+    pub fn keys(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.raw.keys().cloned()
+    }
+
+    /// Get access to the raw map that backs this.
+    #[inline]
+    pub fn as_raw(&self) -> &RawOrderedMap<A> {
+        &self.raw
+    }
+
+    /// Get mutable access to the raw map that backs this.
+    ///
+    /// # Safety
+    ///
+    /// If you insert any values to the raw map, the key (a `TypeId`) must match the
+    /// value’s type, or *undefined behaviour* will occur when you access those values.
+    ///
+    /// (*Removing* entries is perfectly safe.)
+    #[inline]
+    pub unsafe fn as_raw_mut(&mut self) -> &mut RawOrderedMap<A> {
+        &mut self.raw
+    }
+
+    /// Convert this into the raw map that backs this.
+    #[inline]
+    pub fn into_raw(self) -> RawOrderedMap<A> {
+        self.raw
+    }
+}
+
+impl<A: ?Sized + Downcast> Extend<Box<A>> for OrderedMap<A> {
+    #[inline]
+    fn extend<T: IntoIterator<Item = Box<A>>>(&mut self, iter: T) {
+        for item in iter {
+            let _ = self.raw.insert(Downcast::type_id(&*item), item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)] struct A(i32);
+    #[derive(Clone, Debug, PartialEq)] struct B(i32);
+
+    #[test]
+    fn test_ordered_map() {
+        let mut map = OrderedAnyMap::new();
+        assert_eq!(map.insert(A(1)), None);
+        assert_eq!(map.insert(A(2)), Some(A(1)));
+        assert_eq!(map.insert(B(3)), None);
+        map.get_mut::<B>().unwrap().0 = 30;
+        assert_eq!(map.get::<B>(), Some(&B(30)));
+        let mut keys = [TypeId::of::<A>(), TypeId::of::<B>()];
+        keys.sort();
+        assert!(map.keys().eq(keys.iter().cloned()));
+        assert_eq!(map.remove::<A>(), Some(A(2)));
+        assert!(!map.contains::<A>());
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_ordered_map_clone() {
+        let mut map = OrderedMap::<dyn crate::CloneAny>::new();
+        let _ = map.insert(A(1));
+        let copy = map.clone();
+        let _ = map.insert(A(2));
+        assert_eq!(copy.get::<A>(), Some(&A(1)));
+    }
+}
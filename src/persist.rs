@@ -0,0 +1,443 @@
+//! Saving the values of a map to bytes and loading them back, through the [`Save`] and [`Load`]
+//! traits, for state that must outlive the process.
+//!
+//! Only the types listed in a [`Registry`] are saved or loaded. `Map::save_all` writes them to a
+//! container with this format (every integer little-endian):
+//!
+//! - the magic bytes `ANYMAP`, then the format version, one byte, currently 1;
+//! - the number of records, a `u32`;
+//! - for each record: the length of its key (a `u16`) and the key ([`Save::KEY`], UTF-8), its
+//!   type’s [`Save::VERSION`] (a `u32`), the length of its payload (a `u64`) and the payload.
+//!
+//! `Map::load_all` reads such a container, giving each payload to its type’s [`Load::load`]
+//! along with the version it was saved at, so that a type can migrate its old formats. Records
+//! whose key isn’t registered (perhaps for a type since removed) are skipped.
+//!
+//! A `ColdMap` uses the same registry to demote single values to payloads, encoded by a
+//! [`Codec`], to save memory while they’re idle.
+//!
+//! This requires the std feature.
+
+use core::convert::TryFrom;
+use core::fmt;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use crate::any::{Downcast, IntoBox};
+
+/// The magic bytes a container starts with.
+const MAGIC: &[u8; 6] = b"ANYMAP";
+
+/// The version of the container format.
+const FORMAT_VERSION: u8 = 1;
+
+/// A type which can be saved in a container, under a key identifying it.
+pub trait Save {
+    /// The key identifying this type in a container. It must be unique among the registered
+    /// types, and should never change once containers have been saved.
+    const KEY: &'static str;
+
+    /// The version of this type’s format, saved with it and given back to [`Load::load`].
+    const VERSION: u32;
+
+    /// Write this value’s payload.
+    fn save(&self, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// A type which can be loaded back from a container.
+pub trait Load: Save + Sized {
+    /// Read a value from its payload, which was saved at format version `version` (which may
+    /// be older than [`Save::VERSION`], or if the saving program was newer, newer).
+    fn load(input: &mut dyn Read, version: u32) -> io::Result<Self>;
+}
+
+type SaveFn<A> = fn(&A, &mut dyn Write) -> io::Result<()>;
+type LoadFn<A> = fn(&mut dyn Read, u32) -> io::Result<Box<A>>;
+
+struct Saver<A: ?Sized> {
+    key: &'static str,
+    version: u32,
+    save: SaveFn<A>,
+}
+
+// Not #[derive(Debug)]: on older compilers, higher-ranked fn pointers don’t implement Debug.
+impl<A: ?Sized> fmt::Debug for Saver<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Saver")
+            .field("key", &self.key)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+/// A transformation applied to the payloads of the values a `ColdMap` demotes, such as
+/// compression.
+///
+/// `()` is the codec storing payloads as they are.
+pub trait Codec {
+    /// Transform a payload for storage.
+    fn encode(&self, payload: &[u8]) -> Vec<u8>;
+
+    /// Get back the payload from what `encode` returned.
+    fn decode(&self, stored: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+impl Codec for () {
+    #[inline]
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        payload.to_vec()
+    }
+
+    #[inline]
+    fn decode(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(stored.to_vec())
+    }
+}
+
+/// The types a map may save and load, for `Map::save_all` and `Map::load_all`.
+pub struct Registry<A: ?Sized + Downcast = dyn core::any::Any> {
+    savers: HashMap<core::any::TypeId, Saver<A>>,
+    loaders: HashMap<&'static str, LoadFn<A>>,
+}
+
+// The loaders are under the same keys as the savers, and fn pointers can’t be shown anyway.
+impl<A: ?Sized + Downcast> fmt::Debug for Registry<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("savers", &self.savers)
+            .finish()
+    }
+}
+
+impl<A: ?Sized + Downcast> Default for Registry<A> {
+    #[inline]
+    fn default() -> Registry<A> {
+        Registry::new()
+    }
+}
+
+impl<A: ?Sized + Downcast> Registry<A> {
+    /// Create an empty registry.
+    #[inline]
+    pub fn new() -> Registry<A> {
+        Registry { savers: HashMap::new(), loaders: HashMap::new() }
+    }
+
+    /// Register the type `T`, so that its values are saved and loaded.
+    ///
+    /// # Panics
+    ///
+    /// If another type is registered with the same key.
+    #[cfg_attr(feature = "track-caller", track_caller)]
+    pub fn register<T: Load + IntoBox<A>>(mut self) -> Registry<A> {
+        fn save<T: Save + IntoBox<A>, A: ?Sized + Downcast>(value: &A, out: &mut dyn Write) -> io::Result<()> {
+            // SAFETY: savers are only looked up by the value’s own type.
+            unsafe { value.downcast_ref_unchecked::<T>() }.save(out)
+        }
+        fn load<T: Load + IntoBox<A>, A: ?Sized + Downcast>(input: &mut dyn Read, version: u32) -> io::Result<Box<A>> {
+            T::load(input, version).map(T::into_box)
+        }
+
+        let type_id = core::any::TypeId::of::<T>();
+        if self.loaders.insert(T::KEY, load::<T, A>).is_some() && !self.savers.contains_key(&type_id) {
+            panic!("persist key {:?} registered for two types, the second being {}", T::KEY, crate::TypeInfo::of::<T>());
+        }
+        let _ = self.savers.insert(type_id, Saver { key: T::KEY, version: T::VERSION, save: save::<T, A> });
+        self
+    }
+
+    /// Write the payload of `value`, of the type `type_id`, returning its type’s version, or
+    /// `None` if the type isn’t registered.
+    pub(crate) fn save_value(&self, type_id: &core::any::TypeId, value: &A, out: &mut dyn Write)
+        -> Option<io::Result<u32>>
+    {
+        let saver = self.savers.get(type_id)?;
+        Some((saver.save)(value, out).map(|()| saver.version))
+    }
+
+    /// Read a value of the type `type_id` from a payload written by `save_value`.
+    pub(crate) fn load_value(&self, type_id: &core::any::TypeId, version: u32, input: &mut dyn Read)
+        -> io::Result<Box<A>>
+    {
+        let load = self.savers.get(type_id)
+            .and_then(|saver| self.loaders.get(saver.key))
+            .ok_or_else(|| invalid("type not registered"))?;
+        load(input, version)
+    }
+
+    /// Write a container holding the values from `values` of the registered types.
+    pub(crate) fn save_all<'a, I>(&self, values: I, out: &mut dyn Write) -> io::Result<usize>
+    where
+        I: Iterator<Item = (&'a core::any::TypeId, &'a A)>,
+        A: 'a,
+    {
+        let records: Vec<_> = values
+            .filter_map(|(type_id, value)| self.savers.get(type_id).map(|saver| (saver, value)))
+            .collect();
+        // Check the lengths before writing anything, rather than leave a truncated container.
+        let count = u32::try_from(records.len())
+            .map_err(|_| too_long("too many values to save"))?;
+        let key_lens = records.iter()
+            .map(|(saver, _)| u16::try_from(saver.key.len()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| too_long("persist key too long"))?;
+        out.write_all(MAGIC)?;
+        out.write_all(&[FORMAT_VERSION])?;
+        out.write_all(&count.to_le_bytes())?;
+        let mut payload = Vec::new();
+        for (&(saver, value), key_len) in records.iter().zip(key_lens) {
+            payload.clear();
+            (saver.save)(value, &mut payload)?;
+            out.write_all(&key_len.to_le_bytes())?;
+            out.write_all(saver.key.as_bytes())?;
+            out.write_all(&saver.version.to_le_bytes())?;
+            out.write_all(&(payload.len() as u64).to_le_bytes())?;
+            out.write_all(&payload)?;
+        }
+        Ok(records.len())
+    }
+
+    /// Read a container, returning the values of the registered types in it.
+    pub(crate) fn load_all(&self, input: &mut dyn Read) -> io::Result<Vec<Box<A>>> {
+        let mut magic = [0; 6];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a saved map"));
+        }
+        let mut format_version = [0; 1];
+        input.read_exact(&mut format_version)?;
+        if format_version[0] != FORMAT_VERSION {
+            return Err(invalid("unsupported saved map format version"));
+        }
+        let count = u32::from_le_bytes(read_array(input)?);
+        let mut values = Vec::new();
+        for _ in 0..count {
+            let mut key = vec![0; u16::from_le_bytes(read_array(input)?) as usize];
+            input.read_exact(&mut key)?;
+            let version = u32::from_le_bytes(read_array(input)?);
+            let len = u64::from_le_bytes(read_array(input)?);
+            let mut payload = input.take(len);
+            if let Some(load) = core::str::from_utf8(&key).ok().and_then(|key| self.loaders.get(key)) {
+                values.push(load(&mut payload, version)?);
+            }
+            // Skip whatever the loader didn’t read, or the whole payload if there’s no loader.
+            let _ = io::copy(&mut payload, &mut io::sink())?;
+        }
+        Ok(values)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn too_long(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn read_array<A: Default + AsMut<[u8]>>(input: &mut dyn Read) -> io::Result<A> {
+    let mut bytes = A::default();
+    input.read_exact(bytes.as_mut())?;
+    Ok(bytes)
+}
+
+macro_rules! persist_map {
+    ($example_init:literal) => {
+        impl<A: ?Sized + Downcast> Map<A> {
+            /// Write the values of the types registered in `registry` to `out`, returning how many
+            /// were saved. See [`persist`](crate::persist) for the format.
+            ///
+            /// This requires the std feature.
+            ///
+            /// ```rust
+            /// use std::io::{self, Read, Write};
+            /// use anymap::persist::{Load, Registry, Save};
+            ///
+            /// #[derive(Debug, PartialEq)]
+            /// struct Counter(u32);
+            ///
+            /// impl Save for Counter {
+            ///     const KEY: &'static str = "counter";
+            ///     const VERSION: u32 = 2;
+            ///     fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+            ///         out.write_all(&self.0.to_le_bytes())
+            ///     }
+            /// }
+            ///
+            /// impl Load for Counter {
+            ///     fn load(input: &mut dyn Read, version: u32) -> io::Result<Counter> {
+            ///         let mut bytes = [0; 4];
+            ///         match version {
+            ///             // Version 1 was a single byte.
+            ///             1 => input.read_exact(&mut bytes[..1])?,
+            ///             2 => input.read_exact(&mut bytes)?,
+            ///             _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "too new")),
+            ///         }
+            ///         Ok(Counter(u32::from_le_bytes(bytes)))
+            ///     }
+            /// }
+            ///
+            /// let registry = Registry::new().register::<Counter>();
+            #[doc = $example_init]
+            /// data.insert(Counter(1000));
+            /// data.insert("not registered, so not saved");
+            /// let mut saved = Vec::new();
+            /// assert_eq!(data.save_all(&mut saved, &registry).unwrap(), 1);
+            ///
+            #[doc = $example_init]
+            /// assert_eq!(data.load_all(&mut &saved[..], &registry).unwrap(), 1);
+            /// assert_eq!(data.get::<Counter>(), Some(&Counter(1000)));
+            /// ```
+            #[inline]
+            pub fn save_all(&self, out: &mut dyn std::io::Write, registry: &crate::persist::Registry<A>)
+                -> std::io::Result<usize>
+            {
+                registry.save_all(self.raw.iter().map(|(type_id, value)| (type_id, &**value)), out)
+            }
+
+            /// Read values of the types registered in `registry` from `input`, as written by
+            /// [`save_all`](Map::save_all), inserting them and returning how many there were.
+            ///
+            /// If there is an error, nothing is inserted.
+            ///
+            /// This requires the std feature.
+            pub fn load_all(&mut self, input: &mut dyn std::io::Read, registry: &crate::persist::Registry<A>)
+                -> std::io::Result<usize>
+            {
+                let values = registry.load_all(input)?;
+                let count = values.len();
+                self.extend(values);
+                Ok(count)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AnyMap;
+
+    #[derive(Debug, PartialEq)] struct A(u8);
+    #[derive(Debug, PartialEq)] struct B(u8);
+
+    impl Save for A {
+        const KEY: &'static str = "a";
+        const VERSION: u32 = 1;
+        fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+            out.write_all(&[self.0])
+        }
+    }
+
+    impl Load for A {
+        fn load(input: &mut dyn Read, _version: u32) -> io::Result<A> {
+            Ok(A(read_array::<[u8; 1]>(input)?[0]))
+        }
+    }
+
+    impl Save for B {
+        const KEY: &'static str = "b";
+        const VERSION: u32 = 1;
+        fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+            out.write_all(&[self.0, self.0])
+        }
+    }
+
+    impl Load for B {
+        fn load(input: &mut dyn Read, _version: u32) -> io::Result<B> {
+            // Read only half the payload; the rest is skipped.
+            Ok(B(read_array::<[u8; 1]>(input)?[0]))
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let both = Registry::new().register::<A>().register::<B>();
+        let mut map = AnyMap::new();
+        let _ = map.insert(A(1));
+        let _ = map.insert(B(2));
+        let _ = map.insert(3u8);
+        let mut saved = Vec::new();
+        assert_eq!(map.save_all(&mut saved, &both).unwrap(), 2);
+
+        // An unregistered key is skipped.
+        let only_b = Registry::new().register::<B>();
+        let mut loaded = AnyMap::new();
+        assert_eq!(loaded.load_all(&mut &saved[..], &only_b).unwrap(), 1);
+        assert_eq!(loaded.get::<B>(), Some(&B(2)));
+        assert_eq!(loaded.load_all(&mut &saved[..], &both).unwrap(), 2);
+        assert_eq!(loaded.get::<A>(), Some(&A(1)));
+    }
+
+    /// Stores payloads back to front, to check that they go through the codec.
+    struct Reverse;
+
+    impl Codec for Reverse {
+        fn encode(&self, payload: &[u8]) -> Vec<u8> {
+            payload.iter().rev().cloned().collect()
+        }
+        fn decode(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+            Ok(stored.iter().rev().cloned().collect())
+        }
+    }
+
+    #[test]
+    fn test_cold_map() {
+        let mut map = crate::ColdMap::<_, dyn core::any::Any>::new(Registry::new().register::<B>(), Reverse);
+        assert_eq!(map.insert(A(1)).unwrap(), None);
+        assert_eq!(map.insert(B(2)).unwrap(), None);
+        assert!(!map.demote::<A>().unwrap());
+        assert!(map.demote::<B>().unwrap());
+        assert!(map.is_cold::<B>() && map.contains::<B>());
+        assert_eq!((map.len(), map.cold_bytes()), (2, 2));
+        map.get_mut::<B>().unwrap().unwrap().0 = 3;
+        assert!(!map.is_cold::<B>());
+
+        let _ = map.get::<A>().unwrap();
+        assert_eq!(map.demote_idle(1).unwrap(), 0);
+        let _ = map.get::<A>().unwrap();
+        assert_eq!(map.demote_idle(1).unwrap(), 1);
+        assert_eq!(map.insert(B(4)).unwrap(), Some(B(3)));
+        assert!(map.demote::<B>().unwrap());
+        let map = map.into_inner().unwrap();
+        assert_eq!((map.get::<A>(), map.get::<B>()), (Some(&A(1)), Some(&B(4))));
+    }
+
+    #[test]
+    fn test_bad_input() {
+        let registry = Registry::new().register::<A>();
+        let mut map = AnyMap::new();
+        let error = map.load_all(&mut &b"NOTMAP\x01"[..], &registry).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let _ = map.insert(A(1));
+        let mut saved = Vec::new();
+        let _ = map.save_all(&mut saved, &registry).unwrap();
+        let mut loaded = AnyMap::new();
+        let error = loaded.load_all(&mut &saved[..saved.len() - 1], &registry).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(loaded.is_empty());
+    }
+
+    #[test]
+    fn test_debug() {
+        let registry = Registry::<dyn core::any::Any>::new().register::<A>();
+        let debug = format!("{:?}", registry);
+        assert!(debug.contains(r#"Saver { key: "a", version: 1 }"#), "{}", debug);
+    }
+
+    #[test]
+    #[should_panic(expected = "registered for two types")]
+    fn test_duplicate_key() {
+        struct AlsoA;
+        impl Save for AlsoA {
+            const KEY: &'static str = "a";
+            const VERSION: u32 = 1;
+            fn save(&self, _out: &mut dyn Write) -> io::Result<()> { Ok(()) }
+        }
+        impl Load for AlsoA {
+            fn load(_input: &mut dyn Read, _version: u32) -> io::Result<AlsoA> { Ok(AlsoA) }
+        }
+        let _ = Registry::<dyn core::any::Any>::new().register::<A>().register::<AlsoA>();
+    }
+}
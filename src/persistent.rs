@@ -0,0 +1,432 @@
+//! `PersistentMap`, an immutable map whose modified versions share structure with the original.
+//!
+//! This is a hash array mapped trie, with path copying: each `insert` or `remove` copies only the
+//! nodes on the path to the affected entry (at most thirteen, each at most 32 pointers wide), and
+//! everything else is shared by reference counting.
+
+use core::any::{Any, TypeId};
+use core::fmt;
+use core::hash::{Hash, Hasher};
+
+#[cfg(not(feature = "std"))]
+use alloc::{sync::Arc, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+use crate::any::{Downcast, IntoBox};
+use crate::TypeIdHasher;
+
+/// Bits of the hash consumed at each level of the trie.
+const BITS: u32 = 5;
+
+#[inline]
+fn hash(type_id: TypeId) -> u64 {
+    let mut hasher = TypeIdHasher::default();
+    type_id.hash(&mut hasher);
+    hasher.finish()
+}
+
+enum Node<A: ?Sized> {
+    /// Up to 32 children, one for each set bit in `bitmap`.
+    Branch { bitmap: u32, children: Vec<Child<A>> },
+    /// Distinct `TypeId`s whose hashes are identical, once all the bits of the hash have been used.
+    Collision { hash: u64, entries: Vec<(TypeId, Arc<A>)> },
+}
+
+enum Child<A: ?Sized> {
+    Leaf { key: TypeId, hash: u64, value: Arc<A> },
+    Node(Arc<Node<A>>),
+}
+
+// #[derive(Clone)] would want A: Clone.
+impl<A: ?Sized> Clone for Node<A> {
+    fn clone(&self) -> Node<A> {
+        match *self {
+            Node::Branch { bitmap, ref children } =>
+                Node::Branch { bitmap, children: children.clone() },
+            Node::Collision { hash, ref entries } =>
+                Node::Collision { hash, entries: entries.clone() },
+        }
+    }
+}
+
+impl<A: ?Sized> Clone for Child<A> {
+    fn clone(&self) -> Child<A> {
+        match *self {
+            Child::Leaf { key, hash, ref value } =>
+                Child::Leaf { key, hash, value: value.clone() },
+            Child::Node(ref node) => Child::Node(node.clone()),
+        }
+    }
+}
+
+impl<A: ?Sized> Node<A> {
+    fn empty() -> Node<A> {
+        Node::Branch { bitmap: 0, children: Vec::new() }
+    }
+
+    /// The bit in a `Branch`’s bitmap for `hash` at this `shift`, and the position in `children`
+    /// that that bit corresponds to.
+    #[inline]
+    fn locate(bitmap: u32, hash: u64, shift: u32) -> (u32, usize) {
+        let bit = 1 << ((hash >> shift) & 31);
+        (bit, (bitmap & (bit - 1)).count_ones() as usize)
+    }
+
+    fn get(&self, key: TypeId, hash: u64, shift: u32) -> Option<&Arc<A>> {
+        match *self {
+            Node::Branch { bitmap, ref children } => {
+                let (bit, pos) = Node::<A>::locate(bitmap, hash, shift);
+                if bitmap & bit == 0 {
+                    return None;
+                }
+                match children[pos] {
+                    Child::Leaf { key: k, ref value, .. } if k == key => Some(value),
+                    Child::Leaf { .. } => None,
+                    Child::Node(ref node) => node.get(key, hash, shift + BITS),
+                }
+            },
+            Node::Collision { ref entries, .. } => {
+                entries.iter().find(|entry| entry.0 == key).map(|entry| &entry.1)
+            },
+        }
+    }
+
+    /// A node at `shift` holding just these two distinct entries.
+    fn pair(shift: u32, a: Child<A>, b: Child<A>) -> Node<A> {
+        let (a_hash, b_hash) = match (&a, &b) {
+            (&Child::Leaf { hash: a, .. }, &Child::Leaf { hash: b, .. }) => (a, b),
+            _ => unreachable!(),
+        };
+        if shift >= 64 {
+            let mut entries = Vec::with_capacity(2);
+            for child in [a, b].iter() {
+                if let Child::Leaf { key, ref value, .. } = *child {
+                    entries.push((key, value.clone()));
+                }
+            }
+            return Node::Collision { hash: a_hash, entries };
+        }
+        let a_bit = 1u32 << ((a_hash >> shift) & 31);
+        let b_bit = 1u32 << ((b_hash >> shift) & 31);
+        if a_bit == b_bit {
+            let child = Child::Node(Arc::new(Node::pair(shift + BITS, a, b)));
+            Node::Branch { bitmap: a_bit, children: vec![child] }
+        } else if a_bit < b_bit {
+            Node::Branch { bitmap: a_bit | b_bit, children: vec![a, b] }
+        } else {
+            Node::Branch { bitmap: a_bit | b_bit, children: vec![b, a] }
+        }
+    }
+
+    /// A copy of this node with the entry inserted; and whether it replaced an existing entry.
+    fn insert(&self, key: TypeId, hash: u64, value: Arc<A>, shift: u32) -> (Node<A>, bool) {
+        match *self {
+            Node::Branch { bitmap, ref children } => {
+                let (bit, pos) = Node::<A>::locate(bitmap, hash, shift);
+                let mut children = children.clone();
+                if bitmap & bit == 0 {
+                    children.insert(pos, Child::Leaf { key, hash, value });
+                    return (Node::Branch { bitmap: bitmap | bit, children }, false);
+                }
+                let (new_child, replaced) = match children[pos] {
+                    Child::Leaf { key: k, .. } if k == key =>
+                        (Child::Leaf { key, hash, value }, true),
+                    ref leaf @ Child::Leaf { .. } => {
+                        let node = Node::pair(shift + BITS, leaf.clone(),
+                                              Child::Leaf { key, hash, value });
+                        (Child::Node(Arc::new(node)), false)
+                    },
+                    Child::Node(ref node) => {
+                        let (node, replaced) = node.insert(key, hash, value, shift + BITS);
+                        (Child::Node(Arc::new(node)), replaced)
+                    },
+                };
+                children[pos] = new_child;
+                (Node::Branch { bitmap, children }, replaced)
+            },
+            Node::Collision { hash: collision_hash, ref entries } => {
+                let mut entries = entries.clone();
+                let replaced = match entries.iter_mut().find(|entry| entry.0 == key) {
+                    Some(entry) => {
+                        entry.1 = value;
+                        true
+                    },
+                    None => {
+                        entries.push((key, value));
+                        false
+                    },
+                };
+                (Node::Collision { hash: collision_hash, entries }, replaced)
+            },
+        }
+    }
+
+    /// If this node holds only one entry, that entry as a leaf, so that it can be hoisted.
+    fn sole_leaf(&self) -> Option<Child<A>> {
+        match *self {
+            Node::Branch { ref children, .. } if children.len() == 1 => match children[0] {
+                ref leaf @ Child::Leaf { .. } => Some(leaf.clone()),
+                Child::Node(_) => None,
+            },
+            Node::Collision { hash, ref entries } if entries.len() == 1 =>
+                Some(Child::Leaf { key: entries[0].0, hash, value: entries[0].1.clone() }),
+            _ => None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match *self {
+            Node::Branch { ref children, .. } => children.is_empty(),
+            Node::Collision { ref entries, .. } => entries.is_empty(),
+        }
+    }
+
+    /// A copy of this node with the entry removed, or `None` if there was no such entry.
+    fn remove(&self, key: TypeId, hash: u64, shift: u32) -> Option<Node<A>> {
+        match *self {
+            Node::Branch { bitmap, ref children } => {
+                let (bit, pos) = Node::<A>::locate(bitmap, hash, shift);
+                if bitmap & bit == 0 {
+                    return None;
+                }
+                let new_child = match children[pos] {
+                    Child::Leaf { key: k, .. } if k == key => None,
+                    Child::Leaf { .. } => return None,
+                    Child::Node(ref node) => {
+                        let node = node.remove(key, hash, shift + BITS)?;
+                        if node.is_empty() {
+                            None
+                        } else {
+                            Some(node.sole_leaf().unwrap_or_else(|| Child::Node(Arc::new(node))))
+                        }
+                    },
+                };
+                let mut children = children.clone();
+                match new_child {
+                    Some(child) => {
+                        children[pos] = child;
+                        Some(Node::Branch { bitmap, children })
+                    },
+                    None => {
+                        let _ = children.remove(pos);
+                        Some(Node::Branch { bitmap: bitmap & !bit, children })
+                    },
+                }
+            },
+            Node::Collision { hash, ref entries } => {
+                let pos = entries.iter().position(|entry| entry.0 == key)?;
+                let mut entries = entries.clone();
+                let _ = entries.remove(pos);
+                Some(Node::Collision { hash, entries })
+            },
+        }
+    }
+
+    fn for_each_key<F: FnMut(TypeId)>(&self, f: &mut F) {
+        match *self {
+            Node::Branch { ref children, .. } => for child in children {
+                match *child {
+                    Child::Leaf { key, .. } => f(key),
+                    Child::Node(ref node) => node.for_each_key(f),
+                }
+            },
+            Node::Collision { ref entries, .. } => for entry in entries {
+                f(entry.0);
+            },
+        }
+    }
+}
+
+/// An immutable collection containing zero or one values for any given type, where modification
+/// produces a new collection that shares most of its structure with the old one.
+///
+/// This is for when you want to keep old versions around (e.g. for undo and redo): cloning is
+/// cheap (one reference count increment), and `insert` and `remove` copy only a handful of small
+/// nodes, rather than the whole collection. The values themselves are never copied, and so don’t
+/// need to implement `Clone`.
+///
+/// The type parameter `A` is as for [`Map`](crate::Map), and values are stored as `Arc<A>`, so
+/// with `dyn Any + Send + Sync` the collection can be shared between threads.
+///
+/// ```rust
+/// use anymap::PersistentAnyMap;
+///
+/// let v1 = PersistentAnyMap::new().insert(1u8).insert("one");
+/// let v2 = v1.insert(2u8);
+/// let v3 = v2.remove::<&str>();
+/// assert_eq!((v1.get(), v1.get()), (Some(&1u8), Some(&"one")));
+/// assert_eq!((v2.get(), v2.get()), (Some(&2u8), Some(&"one")));
+/// assert_eq!((v3.get(), v3.get::<&str>()), (Some(&2u8), None));
+/// ```
+pub struct PersistentMap<A: ?Sized + Downcast = dyn Any> {
+    root: Arc<Node<A>>,
+    len: usize,
+}
+
+/// The most common type of `PersistentMap`: just using `Any`;
+/// <code>[PersistentMap]&lt;dyn [Any]&gt;</code>.
+pub type PersistentAnyMap = PersistentMap<dyn Any>;
+
+// #[derive(Clone)] would want A: Clone.
+impl<A: ?Sized + Downcast> Clone for PersistentMap<A> {
+    #[inline]
+    fn clone(&self) -> PersistentMap<A> {
+        PersistentMap {
+            root: self.root.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<A: ?Sized + Downcast> Default for PersistentMap<A> {
+    #[inline]
+    fn default() -> PersistentMap<A> {
+        PersistentMap::new()
+    }
+}
+
+impl<A: ?Sized + Downcast> fmt::Debug for PersistentMap<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut set = f.debug_set();
+        self.root.for_each_key(&mut |key| { let _ = set.entry(&key); });
+        set.finish()
+    }
+}
+
+impl<A: ?Sized + Downcast> PersistentMap<A> {
+    /// Create an empty collection.
+    #[inline]
+    pub fn new() -> PersistentMap<A> {
+        PersistentMap {
+            root: Arc::new(Node::empty()),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of items in the collection.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there are no items in the collection.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the value stored in the collection for the type `T`,
+    /// if it exists.
+    #[inline]
+    pub fn get<T: IntoBox<A>>(&self) -> Option<&T> {
+        let type_id = TypeId::of::<T>();
+        self.root.get(type_id, hash(type_id), 0)
+            .map(|any| unsafe { any.downcast_ref_unchecked::<T>() })
+    }
+
+    /// Returns true if the collection contains a value of type `T`.
+    #[inline]
+    pub fn contains<T: IntoBox<A>>(&self) -> bool {
+        let type_id = TypeId::of::<T>();
+        self.root.get(type_id, hash(type_id), 0).is_some()
+    }
+
+    /// Returns a new collection with the value for the type `T` set, replacing any existing
+    /// value of that type. `self` is left unchanged.
+    pub fn insert<T: IntoBox<A>>(&self, value: T) -> PersistentMap<A> {
+        let type_id = TypeId::of::<T>();
+        let value: Arc<A> = value.into_box().into();
+        let (root, replaced) = self.root.insert(type_id, hash(type_id), value, 0);
+        PersistentMap {
+            root: Arc::new(root),
+            len: if replaced { self.len } else { self.len + 1 },
+        }
+    }
+
+    /// Returns a new collection without any value for the type `T`. `self` is left unchanged.
+    pub fn remove<T: IntoBox<A>>(&self) -> PersistentMap<A> {
+        let type_id = TypeId::of::<T>();
+        match self.root.remove(type_id, hash(type_id), 0) {
+            Some(root) => PersistentMap {
+                root: Arc::new(root),
+                len: self.len - 1,
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// Returns true if the two collections are the very same version, sharing all their structure.
+    ///
+    /// This is cheap, and false negatives are possible: two versions with the same contents
+    /// reached by different paths are not pointer-equal.
+    #[inline]
+    pub fn ptr_eq(&self, other: &PersistentMap<A>) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)] struct A(i32);
+    #[derive(Debug, PartialEq)] struct B(i32);
+
+    #[test]
+    fn test_versions() {
+        let empty = PersistentAnyMap::new();
+        let v1 = empty.insert(A(1));
+        let v2 = v1.insert(B(2));
+        let v3 = v2.insert(A(3));
+        let v4 = v3.remove::<B>();
+        assert_eq!((empty.len(), v1.len(), v2.len(), v3.len(), v4.len()), (0, 1, 2, 2, 1));
+        assert_eq!(empty.get::<A>(), None);
+        assert_eq!((v1.get(), v1.get::<B>()), (Some(&A(1)), None));
+        assert_eq!((v2.get(), v2.get()), (Some(&A(1)), Some(&B(2))));
+        assert_eq!((v3.get(), v3.get()), (Some(&A(3)), Some(&B(2))));
+        assert_eq!((v4.get(), v4.get::<B>()), (Some(&A(3)), None));
+        assert!(v4.remove::<B>().ptr_eq(&v4));
+        assert!(v4.remove::<A>().is_empty());
+    }
+
+    #[test]
+    fn test_many_types() {
+        macro_rules! types {
+            ($($T:ident)*) => {{
+                $(#[derive(Debug, PartialEq)] struct $T;)*
+                let mut map = PersistentAnyMap::new();
+                $(map = map.insert($T);)*
+                let full = map.clone();
+                $(assert_eq!(map.get(), Some(&$T));)*
+                $(map = map.remove::<$T>();)*
+                assert!(map.is_empty());
+                $(assert_eq!(full.get(), Some(&$T));)*
+                full
+            }}
+        }
+        let full = types!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13 T14 T15 T16 T17 T18 T19
+                          T20 T21 T22 T23 T24 T25 T26 T27 T28 T29 T30 T31 T32 T33 T34 T35 T36 T37
+                          T38 T39 T40 T41 T42 T43 T44 T45 T46 T47 T48 T49);
+        assert_eq!(full.len(), 50);
+    }
+
+    #[test]
+    fn test_collision() {
+        // Fake distinct keys with identical hashes, which isn’t possible with real TypeIds.
+        let node = Node::<dyn Any>::empty();
+        let key_a = TypeId::of::<A>();
+        let key_b = TypeId::of::<B>();
+        let (node, _) = node.insert(key_a, 7, Arc::new(A(1)), 0);
+        let (node, replaced) = node.insert(key_b, 7, Arc::new(B(2)), 0);
+        assert!(!replaced);
+        assert!(node.get(key_a, 7, 0).is_some());
+        assert!(node.get(key_b, 7, 0).is_some());
+        let node = node.remove(key_a, 7, 0).unwrap();
+        assert!(node.get(key_a, 7, 0).is_none());
+        assert!(node.get(key_b, 7, 0).is_some());
+        let mut keys = vec![];
+        node.for_each_key(&mut |key| keys.push(key));
+        assert_eq!(keys, [key_b]);
+    }
+}
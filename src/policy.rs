@@ -0,0 +1,205 @@
+//! `Policy`, consulted by a `PolicyMap` on each insertion of a new type.
+
+use core::any::TypeId;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use crate::TypeInfo;
+
+/// A [`Policy`]’s decision on a type being inserted into a `PolicyMap`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Admission {
+    /// Insert the value.
+    Admit,
+    /// Don’t insert the value; `PolicyMap::insert` gives it back.
+    Deny,
+    /// Remove the value of the given type (if there is one), then insert the value.
+    Evict(TypeId),
+}
+
+/// Rules for which types a `PolicyMap` may contain.
+///
+/// `admit` is consulted only when a type is inserted that the map doesn’t already contain;
+/// replacing a value is always allowed. Closures of the same signature implement this.
+pub trait Policy {
+    /// Decide whether to admit a value of the type `incoming` into a map holding `len` values.
+    fn admit(&mut self, incoming: TypeInfo, len: usize) -> Admission;
+
+    /// Note that the value of the type `type_id` has been removed from the map, whether by
+    /// eviction or otherwise. By default, this does nothing.
+    #[inline]
+    fn removed(&mut self, type_id: TypeId) {
+        let _ = type_id;
+    }
+}
+
+impl<F: FnMut(TypeInfo, usize) -> Admission> Policy for F {
+    #[inline]
+    fn admit(&mut self, incoming: TypeInfo, len: usize) -> Admission {
+        self(incoming, len)
+    }
+}
+
+/// A [`Policy`] denying the insertion of new types once the map holds that many.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaxTypes(pub usize);
+
+impl Policy for MaxTypes {
+    #[inline]
+    fn admit(&mut self, _incoming: TypeInfo, len: usize) -> Admission {
+        if len < self.0 {
+            Admission::Admit
+        } else {
+            Admission::Deny
+        }
+    }
+}
+
+/// A [`Policy`] denying the insertion of any type it doesn’t list.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Allowlist {
+    types: Vec<TypeId>,
+}
+
+impl Allowlist {
+    /// Create an empty allowlist, which denies everything.
+    #[inline]
+    pub fn new() -> Allowlist {
+        Allowlist::default()
+    }
+
+    /// Allow the type `T`.
+    #[inline]
+    pub fn allow<T: 'static>(mut self) -> Allowlist {
+        self.types.push(TypeId::of::<T>());
+        self
+    }
+}
+
+impl Policy for Allowlist {
+    #[inline]
+    fn admit(&mut self, incoming: TypeInfo, _len: usize) -> Admission {
+        if self.types.contains(&incoming.type_id) {
+            Admission::Admit
+        } else {
+            Admission::Deny
+        }
+    }
+}
+
+macro_rules! policy_map {
+    ($example_use:literal) => {
+        /// A [`Map`] which consults a [`Policy`](crate::Policy) on each insertion of a new type,
+        /// which may deny it or evict another type to make room.
+        ///
+        /// Reading happens through `Deref<Target = Map<A>>`; mutation through the methods here,
+        /// so that nothing gets past the policy. A map it’s made from isn’t checked, though.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// use anymap::{Allowlist, MaxTypes};
+        ///
+        /// let mut data = PolicyMap::<_, dyn std::any::Any>::new(MaxTypes(1));
+        /// assert_eq!(data.insert(1u8), Ok(None));
+        /// assert_eq!(data.insert(2u8), Ok(Some(1)));
+        /// assert_eq!(data.insert(3u16), Err(3));
+        ///
+        /// let mut data = PolicyMap::<_, dyn std::any::Any>::new(Allowlist::new().allow::<u8>());
+        /// assert_eq!(data.insert("nope"), Err("nope"));
+        /// ```
+        #[derive(Debug)]
+        pub struct PolicyMap<P, A: ?Sized + Downcast = dyn Any> {
+            map: Map<A>,
+            policy: P,
+        }
+
+        impl<P: crate::Policy, A: ?Sized + Downcast> core::ops::Deref for PolicyMap<P, A> {
+            type Target = Map<A>;
+
+            #[inline]
+            fn deref(&self) -> &Map<A> {
+                &self.map
+            }
+        }
+
+        impl<P: crate::Policy, A: ?Sized + Downcast> PolicyMap<P, A> {
+            /// Create an empty collection governed by `policy`.
+            #[inline]
+            pub fn new(policy: P) -> PolicyMap<P, A> {
+                PolicyMap::with_map(Map::new(), policy)
+            }
+
+            /// Wrap an existing map, whose contents the policy isn’t asked about.
+            #[inline]
+            pub fn with_map(map: Map<A>, policy: P) -> PolicyMap<P, A> {
+                PolicyMap { map, policy }
+            }
+
+            /// Get a reference to the policy.
+            #[inline]
+            pub fn policy(&self) -> &P {
+                &self.policy
+            }
+
+            /// Get a mutable reference to the policy.
+            #[inline]
+            pub fn policy_mut(&mut self) -> &mut P {
+                &mut self.policy
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+                self.map.get_mut::<T>()
+            }
+
+            /// Sets the value stored in the collection for the type `T`, if the policy admits it
+            /// (which it always does if there’s already a `T`, evicting as it says if not).
+            ///
+            /// If the collection already had a value of type `T`, `Ok(Some)` of that value is
+            /// returned; if the policy denied it, `Err` of the value.
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Result<Option<T>, T> {
+                if !self.map.contains::<T>() {
+                    match self.policy.admit(crate::TypeInfo::of::<T>(), self.map.len()) {
+                        crate::Admission::Admit => (),
+                        crate::Admission::Deny => return Err(value),
+                        crate::Admission::Evict(type_id) => {
+                            if self.map.raw.remove(&type_id).is_some() {
+                                self.policy.removed(type_id);
+                            }
+                        },
+                    }
+                }
+                Ok(self.map.insert(value))
+            }
+
+            /// Removes the `T` value from the collection, telling the policy,
+            /// returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+                let old = self.map.remove::<T>();
+                if old.is_some() {
+                    self.policy.removed(TypeId::of::<T>());
+                }
+                old
+            }
+
+            /// Removes all items from the collection, telling the policy of each.
+            /// Keeps the allocated memory for reuse.
+            pub fn clear(&mut self) {
+                for type_id in self.map.raw.keys() {
+                    self.policy.removed(*type_id);
+                }
+                self.map.clear();
+            }
+
+            /// Unwrap the underlying map and the policy.
+            #[inline]
+            pub fn into_inner(self) -> (Map<A>, P) {
+                (self.map, self.policy)
+            }
+        }
+    };
+}
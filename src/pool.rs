@@ -0,0 +1,119 @@
+//! `MapPool`, which recycles maps to save reallocating their tables.
+
+macro_rules! map_pool {
+    ($example_use:literal) => {
+        /// A pool of empty [`Map`]s, kept for reuse along with their tables’ capacity.
+        ///
+        /// [`take`](MapPool::take) hands out a map from the pool (or a new one if the pool is
+        /// empty), wrapped in a [`Pooled`] which derefs to it, and puts it back, cleared, when
+        /// dropped. This is for servers and the like which make a map per request.
+        ///
+        /// With `A` being `dyn Any + Send` or `dyn Any + Send + Sync`, the pool can be shared
+        /// between threads.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// let pool = MapPool::<dyn std::any::Any + Send>::new();
+        /// {
+        ///     let mut data = pool.take();
+        ///     data.insert(42i32);
+        /// }
+        /// assert_eq!(pool.idle(), 1);
+        /// let data = pool.take();
+        /// assert!(data.is_empty());
+        /// ```
+        #[derive(Debug)]
+        pub struct MapPool<A: ?Sized + Downcast = dyn Any> {
+            maps: std::sync::Mutex<Vec<Map<A>>>,
+        }
+
+        /// The most common type of `MapPool`: just using `Any`;
+        /// <code>[MapPool]&lt;dyn [Any]&gt;</code>.
+        pub type AnyMapPool = MapPool<dyn Any>;
+
+        impl<A: ?Sized + Downcast> Default for MapPool<A> {
+            #[inline]
+            fn default() -> MapPool<A> {
+                MapPool::new()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> MapPool<A> {
+            /// Create an empty pool.
+            #[inline]
+            pub fn new() -> MapPool<A> {
+                MapPool {
+                    maps: std::sync::Mutex::new(Vec::new()),
+                }
+            }
+
+            /// The idle maps, whether or not a panic poisoned the lock. (Nothing panics while
+            /// holding it, so the maps are always in a fit state.)
+            fn maps(&self) -> std::sync::MutexGuard<'_, Vec<Map<A>>> {
+                self.maps.lock().unwrap_or_else(|error| error.into_inner())
+            }
+
+            /// Take an empty map from the pool, or make a new one if there are none.
+            #[inline]
+            pub fn take(&self) -> Pooled<'_, A> {
+                let map = self.maps().pop().unwrap_or_default();
+                Pooled { pool: self, map: Some(map) }
+            }
+
+            /// Returns the number of maps in the pool, waiting to be taken.
+            #[inline]
+            pub fn idle(&self) -> usize {
+                self.maps().len()
+            }
+
+            /// Drop all the maps in the pool, freeing their memory.
+            #[inline]
+            pub fn clear(&self) {
+                let maps = core::mem::replace(&mut *self.maps(), Vec::new());
+                drop(maps);
+            }
+        }
+
+        /// A map taken from a [`MapPool`], which is cleared and returned to the pool when dropped.
+        #[derive(Debug)]
+        pub struct Pooled<'a, A: ?Sized + Downcast = dyn Any> {
+            pool: &'a MapPool<A>,
+            /// Only `None` in `into_inner` and `drop`.
+            map: Option<Map<A>>,
+        }
+
+        impl<'a, A: ?Sized + Downcast> Pooled<'a, A> {
+            /// Keep the map out of the pool, returning it.
+            #[inline]
+            pub fn into_inner(mut self) -> Map<A> {
+                self.map.take().expect("Pooled has no map")
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast> core::ops::Deref for Pooled<'a, A> {
+            type Target = Map<A>;
+
+            #[inline]
+            fn deref(&self) -> &Map<A> {
+                self.map.as_ref().expect("Pooled has no map")
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast> core::ops::DerefMut for Pooled<'a, A> {
+            #[inline]
+            fn deref_mut(&mut self) -> &mut Map<A> {
+                self.map.as_mut().expect("Pooled has no map")
+            }
+        }
+
+        impl<'a, A: ?Sized + Downcast> Drop for Pooled<'a, A> {
+            fn drop(&mut self) {
+                if let Some(mut map) = self.map.take() {
+                    // Outside the lock, since the values’ destructors could do anything.
+                    map.clear();
+                    self.pool.maps().push(map);
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,178 @@
+//! `PresenceMap`, which keeps a bitset of which registered types it contains.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+/// A registered type’s bit in a `PresenceMap`, from `PresenceMap::register`.
+///
+/// Checking it is a single AND, with no hashing. Using it with a map other than the one that
+/// issued it gives a meaningless answer, but is otherwise harmless.
+pub struct TypeBit<T> {
+    index: usize,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> TypeBit<T> {
+    #[inline]
+    pub(crate) fn new(index: usize) -> TypeBit<T> {
+        TypeBit { index, _type: PhantomData }
+    }
+
+    /// The index of the bit.
+    #[inline]
+    pub fn index(self) -> usize {
+        self.index
+    }
+}
+
+// #[derive] would want T to implement these traits, which it needn’t.
+impl<T> Clone for TypeBit<T> {
+    #[inline]
+    fn clone(&self) -> TypeBit<T> {
+        *self
+    }
+}
+
+impl<T> Copy for TypeBit<T> {}
+
+impl<T> fmt::Debug for TypeBit<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TypeBit").field(&self.index).finish()
+    }
+}
+
+macro_rules! presence_map {
+    ($example_use:literal) => {
+        /// A [`Map`] which mirrors the presence of registered types in a bitset, so that checking
+        /// for them with [`has`](PresenceMap::has) needs no hashing.
+        ///
+        /// [`register`](PresenceMap::register) assigns each type the next bit. Reading happens
+        /// through `Deref<Target = Map<A>>`; mutation through the methods here, so that the bits
+        /// stay in step.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// let mut data = PresenceMap::<dyn std::any::Any>::new();
+        /// let position = data.register::<(f32, f32)>();
+        /// assert!(!data.has(position));
+        /// data.insert((1.0f32, 2.0f32));
+        /// assert!(data.has(position));
+        /// ```
+        #[derive(Debug)]
+        pub struct PresenceMap<A: ?Sized + Downcast = dyn Any> {
+            map: Map<A>,
+            indices: HashMap<TypeId, usize, BuildHasherDefault<TypeIdHasher>>,
+            bits: Vec<u64>,
+        }
+
+        impl<A: ?Sized + Downcast> Default for PresenceMap<A> {
+            #[inline]
+            fn default() -> PresenceMap<A> {
+                PresenceMap::new()
+            }
+        }
+
+        /// Wrap an existing map, with no types registered yet.
+        impl<A: ?Sized + Downcast> From<Map<A>> for PresenceMap<A> {
+            #[inline]
+            fn from(map: Map<A>) -> PresenceMap<A> {
+                PresenceMap {
+                    map,
+                    indices: HashMap::with_hasher(Default::default()),
+                    bits: Vec::new(),
+                }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> core::ops::Deref for PresenceMap<A> {
+            type Target = Map<A>;
+
+            #[inline]
+            fn deref(&self) -> &Map<A> {
+                &self.map
+            }
+        }
+
+        impl<A: ?Sized + Downcast> PresenceMap<A> {
+            /// Create an empty collection, with no types registered.
+            #[inline]
+            pub fn new() -> PresenceMap<A> {
+                Map::new().into()
+            }
+
+            /// Assign the type `T` a bit, if it doesn’t have one already, and return it.
+            pub fn register<T: IntoBox<A>>(&mut self) -> crate::TypeBit<T> {
+                let next = self.indices.len();
+                let index = *self.indices.entry(TypeId::of::<T>()).or_insert(next);
+                if index == next {
+                    self.bits.resize(index / 64 + 1, 0);
+                    self.set(index, self.map.contains::<T>());
+                }
+                crate::TypeBit::new(index)
+            }
+
+            /// Returns true if the collection contains a value of the registered type `T`.
+            #[inline]
+            pub fn has<T>(&self, bit: crate::TypeBit<T>) -> bool {
+                let index = bit.index();
+                self.bits.get(index / 64).map_or(false, |word| word & (1 << (index % 64)) != 0)
+            }
+
+            fn set(&mut self, index: usize, present: bool) {
+                let word = &mut self.bits[index / 64];
+                if present {
+                    *word |= 1 << (index % 64);
+                } else {
+                    *word &= !(1 << (index % 64));
+                }
+            }
+
+            /// Update the bit for the type `type_id`, if it is registered.
+            fn update(&mut self, type_id: TypeId, present: bool) {
+                if let Some(&index) = self.indices.get(&type_id) {
+                    self.set(index, present);
+                }
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+                self.map.get_mut::<T>()
+            }
+
+            /// Sets the value stored in the collection for the type `T`.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> {
+                self.update(TypeId::of::<T>(), true);
+                self.map.insert(value)
+            }
+
+            /// Removes the `T` value from the collection,
+            /// returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+                self.update(TypeId::of::<T>(), false);
+                self.map.remove::<T>()
+            }
+
+            /// Removes all items from the collection, keeping the registrations.
+            /// Keeps the allocated memory for reuse.
+            #[inline]
+            pub fn clear(&mut self) {
+                self.map.clear();
+                for word in &mut self.bits {
+                    *word = 0;
+                }
+            }
+
+            /// Unwrap the underlying map, discarding the registrations.
+            #[inline]
+            pub fn into_inner(self) -> Map<A> {
+                self.map
+            }
+        }
+    };
+}
@@ -0,0 +1,132 @@
+//! Distributed registration of values, collected at startup, for `AnyMap::with_registered`.
+//!
+//! Each use of [`register!`](crate::register) puts a constructor function in the platform’s list
+//! of functions to run before `main` (`.init_array` on ELF platforms, `__mod_init_func` on Apple
+//! platforms, `.CRT$XCU` on Windows), which pushes the registration onto a lock-free list. So
+//! registrations cost nothing until the program starts, and need no central list naming them.
+
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::AnyMap;
+
+/// A value registered with `register!`. Not for use directly.
+#[doc(hidden)]
+pub struct Registration {
+    pub init: fn(&mut AnyMap),
+    pub next: AtomicPtr<Registration>,
+}
+
+/// The most recent registration; the rest follow by `next`.
+static HEAD: AtomicPtr<Registration> = AtomicPtr::new(ptr::null_mut());
+
+/// Add a registration to the list. Not for use directly.
+#[doc(hidden)]
+pub fn submit(registration: &'static Registration) {
+    let new = registration as *const Registration as *mut Registration;
+    let mut head = HEAD.load(Ordering::Acquire);
+    loop {
+        registration.next.store(head, Ordering::Relaxed);
+        match HEAD.compare_exchange_weak(head, new, Ordering::Release, Ordering::Acquire) {
+            Ok(_) => return,
+            Err(current) => head = current,
+        }
+    }
+}
+
+/// Register a value to be put in every map made by [`AnyMap::with_registered`].
+///
+/// The expression is evaluated afresh for each map, so it is in effect a factory. This can be
+/// used anywhere an item can, in any crate linked into the program, so that plugin crates can
+/// contribute their extensions without anything else having to name them:
+///
+/// ```rust
+/// struct GreeterFactory(&'static str);
+///
+/// anymap::register!(GreeterFactory("hello"));
+///
+/// let data = anymap::AnyMap::with_registered();
+/// assert_eq!(data.get::<GreeterFactory>().unwrap().0, "hello");
+/// ```
+///
+/// If several registrations are of the same type, one wins arbitrarily.
+///
+/// This requires the registration Cargo feature, and that the platform be one where functions
+/// can be run before `main`: Linux and the other ELF platforms, Apple platforms, and Windows. On
+/// other platforms, registrations are silently ignored.
+#[macro_export]
+macro_rules! register {
+    ($value:expr) => {
+        const _: () = {
+            fn init(map: &mut $crate::AnyMap) {
+                let _ = map.insert($value);
+            }
+
+            static REGISTRATION: $crate::registry::Registration = $crate::registry::Registration {
+                init,
+                next: ::core::sync::atomic::AtomicPtr::new(::core::ptr::null_mut()),
+            };
+
+            extern "C" fn submit() {
+                $crate::registry::submit(&REGISTRATION);
+            }
+
+            #[used]
+            #[cfg_attr(
+                any(
+                    target_os = "linux",
+                    target_os = "android",
+                    target_os = "freebsd",
+                    target_os = "dragonfly",
+                    target_os = "netbsd",
+                    target_os = "openbsd",
+                    target_os = "illumos",
+                ),
+                link_section = ".init_array"
+            )]
+            #[cfg_attr(any(target_os = "macos", target_os = "ios"), link_section = "__DATA,__mod_init_func")]
+            #[cfg_attr(windows, link_section = ".CRT$XCU")]
+            static SUBMIT: extern "C" fn() = submit;
+        };
+    };
+}
+
+impl AnyMap {
+    /// Create a collection containing a fresh value from each use of [`register!`](crate::register)
+    /// in the program.
+    ///
+    /// This requires the registration Cargo feature.
+    pub fn with_registered() -> AnyMap {
+        let mut map = AnyMap::new();
+        let mut next = HEAD.load(Ordering::Acquire);
+        // SAFETY: everything in the list is a `&'static Registration`.
+        while let Some(registration) = unsafe { next.as_ref() } {
+            (registration.init)(&mut map);
+            next = registration.next.load(Ordering::Acquire);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::AnyMap;
+
+    #[derive(Debug, PartialEq)]
+    struct First(i32);
+    #[derive(Debug, PartialEq)]
+    struct Second(&'static str);
+
+    crate::register!(First(1));
+    crate::register!(Second("two"));
+
+    #[test]
+    fn test_with_registered() {
+        let mut map = AnyMap::with_registered();
+        assert_eq!(map.get::<First>(), Some(&First(1)));
+        assert_eq!(map.get::<Second>(), Some(&Second("two")));
+        // Each map gets its own values.
+        map.get_mut::<First>().unwrap().0 = 10;
+        assert_eq!(AnyMap::with_registered().get::<First>(), Some(&First(1)));
+    }
+}
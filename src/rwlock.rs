@@ -0,0 +1,140 @@
+//! `SharedMap`, a map behind a `RwLock`, locked and looked up in one call.
+
+macro_rules! rwlock_map {
+    ($example_use:literal) => {
+        /// A [`Map`] behind a `std::sync::RwLock`, whose [`read`](SharedMap::read) and
+        /// [`write`](SharedMap::write) lock the map and look up a value in one call, returning a
+        /// guard for just that value.
+        ///
+        /// A panic while the lock is held for writing doesn’t poison it here: the map itself is
+        /// still sound, though the value being written to may have been left half changed.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// let data = SharedAnyMap::new();
+        /// data.write_map().insert(1u32);
+        /// *data.write::<u32>().unwrap() += 1;
+        /// assert_eq!(*data.read::<u32>().unwrap(), 2);
+        /// assert!(data.read::<String>().is_none());
+        /// ```
+        #[derive(Debug)]
+        pub struct SharedMap<A: ?Sized + Downcast = dyn Any + Send + Sync> {
+            lock: std::sync::RwLock<Map<A>>,
+        }
+
+        /// The most common type of `SharedMap`, which can be shared between threads;
+        /// <code>[SharedMap]&lt;dyn [Any] + Send + Sync&gt;</code>.
+        pub type SharedAnyMap = SharedMap<dyn Any + Send + Sync>;
+
+        impl<A: ?Sized + Downcast> Default for SharedMap<A> {
+            #[inline]
+            fn default() -> SharedMap<A> {
+                SharedMap::new()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> From<Map<A>> for SharedMap<A> {
+            #[inline]
+            fn from(map: Map<A>) -> SharedMap<A> {
+                SharedMap { lock: std::sync::RwLock::new(map) }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> SharedMap<A> {
+            /// Create an empty collection.
+            #[inline]
+            pub fn new() -> SharedMap<A> {
+                Map::new().into()
+            }
+
+            /// Lock the whole map for reading, blocking until it can be.
+            #[inline]
+            pub fn read_map(&self) -> std::sync::RwLockReadGuard<'_, Map<A>> {
+                self.lock.read().unwrap_or_else(|error| error.into_inner())
+            }
+
+            /// Lock the whole map for writing, blocking until it can be.
+            #[inline]
+            pub fn write_map(&self) -> std::sync::RwLockWriteGuard<'_, Map<A>> {
+                self.lock.write().unwrap_or_else(|error| error.into_inner())
+            }
+
+            /// Lock the map for reading and get the value of type `T`, if there is one.
+            ///
+            /// The map stays locked until the guard is dropped.
+            pub fn read<T: IntoBox<A>>(&self) -> Option<MappedReadGuard<'_, T, A>> {
+                let guard = self.read_map();
+                let value: *const T = guard.get::<T>()?;
+                Some(MappedReadGuard { _guard: guard, value })
+            }
+
+            /// Lock the map for writing and get the value of type `T`, if there is one.
+            ///
+            /// The map stays locked until the guard is dropped.
+            pub fn write<T: IntoBox<A>>(&self) -> Option<MappedWriteGuard<'_, T, A>> {
+                let mut guard = self.write_map();
+                let value: *mut T = guard.get_mut::<T>()?;
+                Some(MappedWriteGuard { _guard: guard, value })
+            }
+
+            /// Convert this into the map.
+            #[inline]
+            pub fn into_inner(self) -> Map<A> {
+                self.lock.into_inner().unwrap_or_else(|error| error.into_inner())
+            }
+        }
+
+        /// A read lock on a [`SharedMap`], dereferencing to one value in it, from
+        /// [`SharedMap::read`].
+        pub struct MappedReadGuard<'a, T, A: ?Sized + Downcast = dyn Any + Send + Sync> {
+            _guard: std::sync::RwLockReadGuard<'a, Map<A>>,
+            // Points into the box in the locked map, which can’t change while the guard lives.
+            value: *const T,
+        }
+
+        impl<'a, T, A: ?Sized + Downcast> core::ops::Deref for MappedReadGuard<'a, T, A> {
+            type Target = T;
+
+            #[inline]
+            fn deref(&self) -> &T {
+                unsafe { &*self.value }
+            }
+        }
+
+        impl<'a, T: core::fmt::Debug, A: ?Sized + Downcast> core::fmt::Debug for MappedReadGuard<'a, T, A> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                (**self).fmt(f)
+            }
+        }
+
+        /// A write lock on a [`SharedMap`], dereferencing to one value in it, from
+        /// [`SharedMap::write`].
+        pub struct MappedWriteGuard<'a, T, A: ?Sized + Downcast = dyn Any + Send + Sync> {
+            _guard: std::sync::RwLockWriteGuard<'a, Map<A>>,
+            // Points into the box in the locked map, which only this guard can reach.
+            value: *mut T,
+        }
+
+        impl<'a, T, A: ?Sized + Downcast> core::ops::Deref for MappedWriteGuard<'a, T, A> {
+            type Target = T;
+
+            #[inline]
+            fn deref(&self) -> &T {
+                unsafe { &*self.value }
+            }
+        }
+
+        impl<'a, T, A: ?Sized + Downcast> core::ops::DerefMut for MappedWriteGuard<'a, T, A> {
+            #[inline]
+            fn deref_mut(&mut self) -> &mut T {
+                unsafe { &mut *self.value }
+            }
+        }
+
+        impl<'a, T: core::fmt::Debug, A: ?Sized + Downcast> core::fmt::Debug for MappedWriteGuard<'a, T, A> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                (**self).fmt(f)
+            }
+        }
+    };
+}
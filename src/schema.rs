@@ -0,0 +1,149 @@
+//! `Schema`, a list of the types a map must or may contain, for `Map::conforms`.
+
+use core::any::TypeId;
+use core::fmt;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// A type, as reported in a [`SchemaError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TypeInfo {
+    /// The type’s `TypeId`.
+    pub type_id: TypeId,
+    /// The type’s name, if known (which needs the type-names Cargo feature).
+    pub name: Option<&'static str>,
+}
+
+impl TypeInfo {
+    /// Describe the type `T`.
+    #[inline]
+    pub fn of<T: 'static>() -> TypeInfo {
+        TypeInfo {
+            type_id: TypeId::of::<T>(),
+            #[cfg(feature = "type-names")]
+            #[allow(clippy::incompatible_msrv)]  // The type-names feature requires 1.38.
+            name: Some(core::any::type_name::<T>()),
+            #[cfg(not(feature = "type-names"))]
+            name: None,
+        }
+    }
+}
+
+impl fmt::Display for TypeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{:?}", self.type_id),
+        }
+    }
+}
+
+/// A list of the types a map must contain and the types it may contain, for `Map::conforms`.
+///
+/// Anything not listed is unexpected.
+#[derive(Clone, Debug, Default)]
+pub struct Schema {
+    required: Vec<TypeInfo>,
+    optional: Vec<TypeInfo>,
+}
+
+impl Schema {
+    /// Create an empty schema, which only an empty map conforms to.
+    #[inline]
+    pub fn new() -> Schema {
+        Schema::default()
+    }
+
+    /// Require a value of type `T`.
+    #[inline]
+    pub fn require<T: 'static>(mut self) -> Schema {
+        self.required.push(TypeInfo::of::<T>());
+        self
+    }
+
+    /// Allow, but don’t require, a value of type `T`.
+    #[inline]
+    pub fn allow<T: 'static>(mut self) -> Schema {
+        self.optional.push(TypeInfo::of::<T>());
+        self
+    }
+
+    /// The required types.
+    #[inline]
+    pub fn required(&self) -> &[TypeInfo] {
+        &self.required
+    }
+
+    /// The allowed but not required types.
+    #[inline]
+    pub fn optional(&self) -> &[TypeInfo] {
+        &self.optional
+    }
+
+    /// Check a map’s keys against this schema.
+    pub(crate) fn check<'a, I, N>(&self, keys: I, name_of: N) -> Result<(), SchemaError>
+    where
+        I: Iterator<Item = &'a TypeId> + Clone,
+        N: Fn(TypeId) -> Option<&'static str>,
+    {
+        let missing: Vec<TypeInfo> = self.required.iter()
+            .filter(|info| !keys.clone().any(|&key| key == info.type_id))
+            .cloned()
+            .collect();
+        let unexpected: Vec<TypeInfo> = keys
+            .filter(|&&key| !self.required.iter().chain(&self.optional).any(|info| info.type_id == key))
+            .map(|&type_id| TypeInfo { type_id, name: name_of(type_id) })
+            .collect();
+        if missing.is_empty() && unexpected.is_empty() {
+            Ok(())
+        } else {
+            Err(SchemaError { missing, unexpected })
+        }
+    }
+}
+
+/// The ways in which a map doesn’t conform to a [`Schema`], from `Map::conforms`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaError {
+    missing: Vec<TypeInfo>,
+    unexpected: Vec<TypeInfo>,
+}
+
+impl SchemaError {
+    /// The required types which the map doesn’t contain.
+    #[inline]
+    pub fn missing(&self) -> &[TypeInfo] {
+        &self.missing
+    }
+
+    /// The types the map contains which the schema doesn’t list.
+    ///
+    /// Their names are known only if they were put in the map by type, with the type-names Cargo
+    /// feature enabled.
+    #[inline]
+    pub fn unexpected(&self) -> &[TypeInfo] {
+        &self.unexpected
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("map does not conform to schema")?;
+        for (label, infos) in &[("missing", &self.missing), ("unexpected", &self.unexpected)] {
+            if !infos.is_empty() {
+                write!(f, "; {}: ", label)?;
+                for (i, info) in infos.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", info)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SchemaError {}
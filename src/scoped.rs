@@ -0,0 +1,146 @@
+//! `ScopedAnyMap`, which holds borrowed values for a lifetime.
+
+macro_rules! scoped_map {
+    ($example_use:literal) => {
+        /// A borrowed value in a [`ScopedAnyMap`].
+        #[derive(Debug)]
+        enum Borrowed<'a> {
+            Shared(&'a dyn Any),
+            Mut(&'a mut dyn Any),
+        }
+
+        impl<'a> Borrowed<'a> {
+            #[inline]
+            fn into_ref<T: Any>(self) -> Option<&'a T> {
+                match self {
+                    Borrowed::Shared(value) => value.downcast_ref(),
+                    Borrowed::Mut(value) => value.downcast_ref(),
+                }
+            }
+        }
+
+        /// A collection like [`AnyMap`], but of *borrowed* values, which may live only as long as
+        /// the lifetime `'a`.
+        ///
+        /// This is for request-scoped state and the like: things that live on the stack of some
+        /// outer function can be lent to the map, and everything under it can get them by type,
+        /// without anything needing to be `'static` or wrapped in `Arc`. (The types themselves
+        /// must still be `'static`, as `TypeId` requires.)
+        ///
+        /// Values can be lent shared, with [`insert`](ScopedAnyMap::insert), or exclusively, with
+        /// [`insert_mut`](ScopedAnyMap::insert_mut), which allows `get_mut` too.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// struct Request { path: String }
+        /// struct Counter(u32);
+        ///
+        /// fn handle(data: &mut ScopedAnyMap) {
+        ///     assert_eq!(data.get::<Request>().unwrap().path, "/");
+        ///     data.get_mut::<Counter>().unwrap().0 += 1;
+        /// }
+        ///
+        /// let request = Request { path: String::from("/") };
+        /// let mut counter = Counter(0);
+        /// {
+        ///     let mut data = ScopedAnyMap::new();
+        ///     data.insert(&request);
+        ///     data.insert_mut(&mut counter);
+        ///     handle(&mut data);
+        /// }
+        /// assert_eq!(counter.0, 1);
+        /// ```
+        #[derive(Debug)]
+        pub struct ScopedAnyMap<'a> {
+            raw: HashMap<TypeId, Borrowed<'a>, BuildHasherDefault<TypeIdHasher>>,
+        }
+
+        impl<'a> Default for ScopedAnyMap<'a> {
+            #[inline]
+            fn default() -> ScopedAnyMap<'a> {
+                ScopedAnyMap::new()
+            }
+        }
+
+        impl<'a> ScopedAnyMap<'a> {
+            /// Create an empty collection.
+            #[inline]
+            pub fn new() -> ScopedAnyMap<'a> {
+                ScopedAnyMap {
+                    raw: HashMap::with_hasher(Default::default()),
+                }
+            }
+
+            /// Returns the number of items in the collection.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.raw.len()
+            }
+
+            /// Returns true if there are no items in the collection.
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.raw.is_empty()
+            }
+
+            /// Removes all items from the collection, ending the borrows. Keeps the allocated
+            /// memory for reuse.
+            #[inline]
+            pub fn clear(&mut self) {
+                self.raw.clear()
+            }
+
+            /// Returns a reference to the value lent to the collection for the type `T`,
+            /// if there is one.
+            #[inline]
+            pub fn get<T: Any>(&self) -> Option<&T> {
+                match self.raw.get(&TypeId::of::<T>())? {
+                    Borrowed::Shared(value) => value.downcast_ref(),
+                    Borrowed::Mut(value) => value.downcast_ref(),
+                }
+            }
+
+            /// Returns a mutable reference to the value lent to the collection for the type `T`,
+            /// if there is one and it was lent exclusively, with `insert_mut`.
+            #[inline]
+            pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+                match self.raw.get_mut(&TypeId::of::<T>())? {
+                    Borrowed::Shared(_) => None,
+                    Borrowed::Mut(value) => value.downcast_mut(),
+                }
+            }
+
+            /// Lend a value to the collection for the type `T`.
+            /// If the collection already had a value of type `T`, that borrow is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: Any>(&mut self, value: &'a T) -> Option<&'a T> {
+                self.raw.insert(TypeId::of::<T>(), Borrowed::Shared(value))
+                    .and_then(Borrowed::into_ref)
+            }
+
+            /// Lend a value exclusively to the collection for the type `T`, so that it can be
+            /// got mutably too.
+            /// If the collection already had a value of type `T`, that borrow is returned (as a
+            /// shared borrow, even if it was lent exclusively). Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert_mut<T: Any>(&mut self, value: &'a mut T) -> Option<&'a T> {
+                self.raw.insert(TypeId::of::<T>(), Borrowed::Mut(value))
+                    .and_then(Borrowed::into_ref)
+            }
+
+            /// Removes the `T` value from the collection,
+            /// returning its borrow if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: Any>(&mut self) -> Option<&'a T> {
+                self.raw.remove(&TypeId::of::<T>()).and_then(Borrowed::into_ref)
+            }
+
+            /// Returns true if the collection contains a value of type `T`.
+            #[inline]
+            pub fn contains<T: Any>(&self) -> bool {
+                self.raw.contains_key(&TypeId::of::<T>())
+            }
+        }
+    };
+}
@@ -0,0 +1,147 @@
+//! `RcMap` and `ArcMap`, which store their values behind reference-counted pointers.
+
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::{rc::Rc, sync::Arc};
+#[cfg(feature = "std")]
+pub(crate) use std::{rc::Rc, sync::Arc};
+
+/// Downcast from `Rc<A>` to `Rc<T>`, without checking the type matches.
+///
+/// # Safety
+///
+/// `T` must be the type of the value behind `rc`.
+#[inline]
+pub(crate) unsafe fn downcast_rc_unchecked<A: ?Sized, T>(rc: Rc<A>) -> Rc<T> {
+    Rc::from_raw(Rc::into_raw(rc) as *const T)
+}
+
+/// Downcast from `Arc<A>` to `Arc<T>`, without checking the type matches.
+///
+/// # Safety
+///
+/// `T` must be the type of the value behind `arc`.
+#[inline]
+pub(crate) unsafe fn downcast_arc_unchecked<A: ?Sized, T>(arc: Arc<A>) -> Arc<T> {
+    Arc::from_raw(Arc::into_raw(arc) as *const T)
+}
+
+macro_rules! shared_map {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident<A = $default:ty>(
+            $Ptr:ident,
+            $get_ptr:ident,
+            $downcast:ident
+        );
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug)]
+        pub struct $name<A: ?Sized + Downcast = $default> {
+            raw: HashMap<TypeId, crate::shared::$Ptr<A>, BuildHasherDefault<TypeIdHasher>>,
+        }
+
+        // #[derive(Clone)] would want A: Clone.
+        impl<A: ?Sized + Downcast> Clone for $name<A> {
+            #[inline]
+            fn clone(&self) -> $name<A> {
+                $name {
+                    raw: self.raw.clone(),
+                }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> Default for $name<A> {
+            #[inline]
+            fn default() -> $name<A> {
+                $name::new()
+            }
+        }
+
+        /// Move the values of a `Map` into shared pointers.
+        impl<A: ?Sized + Downcast> From<Map<A>> for $name<A> {
+            #[inline]
+            fn from(map: Map<A>) -> $name<A> {
+                $name {
+                    raw: map.into_raw().into_iter()
+                        .map(|(type_id, value)| (type_id, value.into()))
+                        .collect(),
+                }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> $name<A> {
+            /// Create an empty collection.
+            #[inline]
+            pub fn new() -> $name<A> {
+                $name {
+                    raw: HashMap::with_hasher(Default::default()),
+                }
+            }
+
+            /// Creates an empty collection with the given initial capacity.
+            #[inline]
+            pub fn with_capacity(capacity: usize) -> $name<A> {
+                $name {
+                    raw: HashMap::with_capacity_and_hasher(capacity, Default::default()),
+                }
+            }
+
+            /// Returns the number of items in the collection.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.raw.len()
+            }
+
+            /// Returns true if there are no items in the collection.
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.raw.is_empty()
+            }
+
+            /// Removes all items from the collection. Keeps the allocated memory for reuse.
+            #[inline]
+            pub fn clear(&mut self) {
+                self.raw.clear()
+            }
+
+            /// Returns a reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get<T: IntoBox<A>>(&self) -> Option<&T> {
+                self.raw.get(&TypeId::of::<T>())
+                    .map(|any| unsafe { any.downcast_ref_unchecked::<T>() })
+            }
+
+            /// Returns a new shared pointer to the value stored in the collection for the type
+            /// `T`, if it exists.
+            #[inline]
+            pub fn $get_ptr<T: IntoBox<A>>(&self) -> Option<crate::shared::$Ptr<T>> {
+                self.raw.get(&TypeId::of::<T>())
+                    .map(|any| unsafe { crate::shared::$downcast::<A, T>(any.clone()) })
+            }
+
+            /// Sets the value stored in the collection for the type `T`.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<crate::shared::$Ptr<T>> {
+                self.raw.insert(TypeId::of::<T>(), value.into_box().into())
+                    .map(|any| unsafe { crate::shared::$downcast::<A, T>(any) })
+            }
+
+            /// Removes the `T` value from the collection,
+            /// returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<crate::shared::$Ptr<T>> {
+                self.raw.remove(&TypeId::of::<T>())
+                    .map(|any| unsafe { crate::shared::$downcast::<A, T>(any) })
+            }
+
+            /// Returns true if the collection contains a value of type `T`.
+            #[inline]
+            pub fn contains<T: IntoBox<A>>(&self) -> bool {
+                self.raw.contains_key(&TypeId::of::<T>())
+            }
+        }
+    };
+}
@@ -0,0 +1,293 @@
+//! `StableMap`, keyed by something that agrees between separately compiled libraries.
+//!
+//! `TypeId` is only meaningful within one compilation: a host program and a plugin loaded from a
+//! dynamic library can get different `TypeId`s for the same type, so a `Map` passed between them
+//! can’t find anything. A `StableMap` is instead keyed by a [`StableTypeId`], got from a UUID
+//! given to each type by [`TypeUuid`], or (with the type-names feature) from a hash of the type’s
+//! name.
+
+use core::hash::{Hash, Hasher};
+
+/// A key identifying a type, which agrees between separately compiled libraries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StableTypeId(u128);
+
+impl StableTypeId {
+    /// Use a 128-bit number, normally a UUID, as a key.
+    #[inline]
+    pub const fn from_u128(id: u128) -> StableTypeId {
+        StableTypeId(id)
+    }
+
+    /// Make a key by hashing a name (with 128-bit FNV-1a, so it’s the same everywhere).
+    pub fn of_name(name: &str) -> StableTypeId {
+        let mut hash: u128 = 0x6c62272e07bb014262b821756295c58d;
+        for &byte in name.as_bytes() {
+            hash ^= u128::from(byte);
+            hash = hash.wrapping_mul(0x0000000001000000000000000000013b);
+        }
+        StableTypeId(hash)
+    }
+
+    /// The key as a 128-bit number.
+    #[inline]
+    pub fn as_u128(self) -> u128 {
+        self.0
+    }
+}
+
+impl Hash for StableTypeId {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // One 64-bit write, for TypeIdHasher.
+        state.write_u64((self.0 ^ (self.0 >> 64)) as u64);
+    }
+}
+
+/// A type with a UUID, to key it in a [`StableMap`](crate::StableMap) of mode [`ByUuid`].
+///
+/// # Safety
+///
+/// No two types stored in the same `StableMap` may have the same UUID, or the map will mistake
+/// one for the other. Generating a fresh UUID for each type, and changing it whenever the type’s
+/// layout changes, takes care of that.
+pub unsafe trait TypeUuid: 'static {
+    /// The type’s UUID, as a 128-bit number.
+    const UUID: u128;
+}
+
+/// The `StableMap` key mode using [`TypeUuid`].
+#[derive(Debug)]
+pub enum ByUuid {}
+
+/// The `StableMap` key mode using a hash of `core::any::type_name`.
+///
+/// This requires the type-names Cargo feature.
+#[cfg(feature = "type-names")]
+#[derive(Debug)]
+pub enum ByTypeName {}
+
+/// Types that can be keyed in a `StableMap` of mode `M`.
+///
+/// This is sealed: the map trusts the key to pick the type to downcast to, so the only
+/// implementations are those for [`TypeUuid`] types (mode [`ByUuid`]) and, with the type-names
+/// Cargo feature, for every type (mode `ByTypeName`). Implement `TypeUuid` instead:
+///
+/// ```rust,compile_fail
+/// struct Local;
+/// impl anymap::StableKey<anymap::ByUuid> for Local {  // error[E0277]: the trait bound `Local: Sealed<ByUuid>` is not satisfied
+///     fn stable_type_id() -> anymap::StableTypeId { anymap::StableTypeId::from_u128(1) }
+///     fn stable_name() -> Option<&'static str> { None }
+/// }
+/// ```
+pub trait StableKey<M>: sealed::Sealed<M> + 'static {
+    /// The key for this type.
+    fn stable_type_id() -> StableTypeId;
+
+    /// The name of this type, if the key was derived from it, for detecting collisions.
+    fn stable_name() -> Option<&'static str>;
+}
+
+mod sealed {
+    pub trait Sealed<M> { }
+}
+
+impl<T: TypeUuid> sealed::Sealed<ByUuid> for T { }
+
+impl<T: TypeUuid> StableKey<ByUuid> for T {
+    #[inline]
+    fn stable_type_id() -> StableTypeId {
+        StableTypeId::from_u128(T::UUID)
+    }
+
+    #[inline]
+    fn stable_name() -> Option<&'static str> {
+        None
+    }
+}
+
+#[cfg(feature = "type-names")]
+impl<T: 'static> sealed::Sealed<ByTypeName> for T { }
+
+#[cfg(feature = "type-names")]
+#[allow(clippy::incompatible_msrv)]  // The type-names feature requires 1.38.
+impl<T: 'static> StableKey<ByTypeName> for T {
+    #[inline]
+    fn stable_type_id() -> StableTypeId {
+        StableTypeId::of_name(core::any::type_name::<T>())
+    }
+
+    #[inline]
+    fn stable_name() -> Option<&'static str> {
+        Some(core::any::type_name::<T>())
+    }
+}
+
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+macro_rules! stable_map {
+    ($example_use:literal) => {
+        /// A value in a [`StableMap`], with the name of its type if it’s keyed by name.
+        #[derive(Debug)]
+        struct StableSlot<A: ?Sized> {
+            name: Option<&'static str>,
+            value: Box<A>,
+        }
+
+        impl<A: ?Sized> StableSlot<A> {
+            /// Whether this slot holds the type named `name`, as far as can be told.
+            #[inline]
+            fn is(&self, name: Option<&'static str>) -> bool {
+                match (self.name, name) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => true,
+                }
+            }
+        }
+
+        /// A collection like [`Map`], but keyed by [`StableTypeId`](crate::StableTypeId) rather
+        /// than `TypeId`, so that a host program and plugins in dynamic libraries agree on the
+        /// keys. (Whatever is passed between them must still be compiled by the same compiler
+        /// with the same dependencies, of course.)
+        ///
+        /// The key mode `M` is [`ByUuid`](crate::ByUuid), for types implementing
+        /// [`TypeUuid`](crate::TypeUuid), or (with the type-names Cargo feature)
+        /// `ByTypeName`. With `ByTypeName`, a hash collision between two names is detected:
+        /// `insert` panics, and `get` and the like find nothing.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// struct Config(u32);
+        /// unsafe impl anymap::TypeUuid for Config {
+        ///     const UUID: u128 = 0x6d5e_9a1c_2f3b_4e8d_a7c6_0b1f_9e2d_3c4a;
+        /// }
+        ///
+        /// let mut data = StableMap::<anymap::ByUuid, dyn std::any::Any>::new();
+        /// data.insert(Config(7));
+        /// assert_eq!(data.get::<Config>().map(|config| config.0), Some(7));
+        /// ```
+        #[derive(Debug)]
+        pub struct StableMap<M, A: ?Sized + Downcast = dyn Any> {
+            raw: HashMap<crate::StableTypeId, StableSlot<A>, BuildHasherDefault<TypeIdHasher>>,
+            mode: PhantomData<M>,
+        }
+
+        impl<A: ?Sized + Downcast> Default for StableMap<crate::ByUuid, A> {
+            #[inline]
+            fn default() -> StableMap<crate::ByUuid, A> {
+                StableMap::new()
+            }
+        }
+
+        impl<A: ?Sized + Downcast> StableMap<crate::ByUuid, A> {
+            /// Create an empty collection, keyed by [`TypeUuid`](crate::TypeUuid).
+            #[inline]
+            pub fn new() -> StableMap<crate::ByUuid, A> {
+                StableMap {
+                    raw: HashMap::with_hasher(Default::default()),
+                    mode: PhantomData,
+                }
+            }
+        }
+
+        #[cfg(feature = "type-names")]
+        impl<A: ?Sized + Downcast> StableMap<crate::ByTypeName, A> {
+            /// Create an empty collection, keyed by type name.
+            ///
+            /// This requires the type-names Cargo feature.
+            ///
+            /// # Safety
+            ///
+            /// `core::any::type_name` isn’t guaranteed to be unique: for example, two versions of
+            /// one crate in a build will give the same names. No two types stored in the
+            /// collection may have the same name, or it will mistake one for the other.
+            #[inline]
+            pub unsafe fn new_by_type_name() -> StableMap<crate::ByTypeName, A> {
+                StableMap {
+                    raw: HashMap::with_hasher(Default::default()),
+                    mode: PhantomData,
+                }
+            }
+        }
+
+        impl<M, A: ?Sized + Downcast> StableMap<M, A> {
+            /// Returns the number of items in the collection.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.raw.len()
+            }
+
+            /// Returns true if there are no items in the collection.
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.raw.is_empty()
+            }
+
+            /// Removes all items from the collection. Keeps the allocated memory for reuse.
+            #[inline]
+            pub fn clear(&mut self) {
+                self.raw.clear()
+            }
+
+            /// Returns a reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get<T: IntoBox<A> + crate::StableKey<M>>(&self) -> Option<&T> {
+                self.raw.get(&T::stable_type_id())
+                    .filter(|slot| slot.is(T::stable_name()))
+                    .map(|slot| unsafe { slot.value.downcast_ref_unchecked::<T>() })
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists.
+            #[inline]
+            pub fn get_mut<T: IntoBox<A> + crate::StableKey<M>>(&mut self) -> Option<&mut T> {
+                self.raw.get_mut(&T::stable_type_id())
+                    .filter(|slot| slot.is(T::stable_name()))
+                    .map(|slot| unsafe { slot.value.downcast_mut_unchecked::<T>() })
+            }
+
+            /// Sets the value stored in the collection for the type `T`.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            ///
+            /// # Panics
+            ///
+            /// If the collection holds a value of another type with the same key.
+            pub fn insert<T: IntoBox<A> + crate::StableKey<M>>(&mut self, value: T) -> Option<T> {
+                let name = T::stable_name();
+                match self.raw.entry(T::stable_type_id()) {
+                    hash_map::Entry::Occupied(mut e) => {
+                        if let (Some(a), Some(b)) = (e.get().name, name) {
+                            if a != b {
+                                panic!("StableMap key collision between {} and {}", a, b);
+                            }
+                        }
+                        let old = e.insert(StableSlot { name, value: value.into_box() });
+                        Some(unsafe { *old.value.downcast_unchecked::<T>() })
+                    },
+                    hash_map::Entry::Vacant(e) => {
+                        let _ = e.insert(StableSlot { name, value: value.into_box() });
+                        None
+                    },
+                }
+            }
+
+            /// Removes the `T` value from the collection,
+            /// returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A> + crate::StableKey<M>>(&mut self) -> Option<T> {
+                let key = T::stable_type_id();
+                if !self.raw.get(&key).map_or(false, |slot| slot.is(T::stable_name())) {
+                    return None;
+                }
+                self.raw.remove(&key).map(|slot| unsafe { *slot.value.downcast_unchecked::<T>() })
+            }
+
+            /// Returns true if the collection contains a value of type `T`.
+            #[inline]
+            pub fn contains<T: IntoBox<A> + crate::StableKey<M>>(&self) -> bool {
+                self.raw.get(&T::stable_type_id()).map_or(false, |slot| slot.is(T::stable_name()))
+            }
+        }
+    };
+}
@@ -0,0 +1,131 @@
+//! `Stats`, describing how full a map’s hash table is, and `SimulatedStats`, estimating how well
+//! its keys would be spread across it.
+
+use core::any::TypeId;
+use core::hash::{Hash, Hasher};
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use crate::TypeIdHasher;
+
+/// Statistics about the hash table backing a map, from `Map::stats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stats {
+    len: usize,
+    capacity: usize,
+}
+
+impl Stats {
+    /// The figures for a table of the given length and capacity.
+    #[inline]
+    pub(crate) fn new(len: usize, capacity: usize) -> Stats {
+        Stats { len, capacity }
+    }
+
+    /// The number of items in the collection.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there were no items in the collection.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of items the collection could hold without reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of items over the capacity, from 0 to 1 (0 if the capacity is 0).
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        if self.capacity == 0 {
+            0.0
+        } else {
+            self.len as f64 / self.capacity as f64
+        }
+    }
+}
+
+/// How a map’s keys would be spread across a hash table, from `Map::simulate_stats`.
+///
+/// Neither `std` nor `hashbrown` exposes the actual layout of its table, so these figures are
+/// for a simulation: a table with the same number of buckets as the real one (as far as can be
+/// told from its capacity), and linear probing from each key’s home bucket. The real table
+/// probes differently, so the numbers won’t match it, but they are enough to show whether the
+/// `TypeId` hasher is spreading the keys out well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulatedStats {
+    buckets: usize,
+    occupied_home_buckets: usize,
+    probe_lengths: Vec<usize>,
+}
+
+impl SimulatedStats {
+    /// Simulate a table of the given capacity holding the given keys.
+    pub(crate) fn new<'a, I: Iterator<Item = &'a TypeId>>(keys: I, capacity: usize)
+        -> SimulatedStats
+    {
+        // The inverse of the SwissTable capacity rules: up to 8 buckets, one is kept free;
+        // beyond that, an eighth are.
+        let buckets = match capacity {
+            0 => 0,
+            1..=7 => capacity + 1,
+            _ => capacity / 7 * 8,
+        };
+        let mut occupied = vec![false; buckets];
+        let mut home = vec![false; buckets];
+        let mut probe_lengths = Vec::new();
+        for type_id in keys {
+            if buckets == 0 {
+                continue;
+            }
+            let mut hasher = TypeIdHasher::default();
+            type_id.hash(&mut hasher);
+            let start = hasher.finish() as usize % buckets;
+            home[start] = true;
+            let probes = (0..buckets).find(|i| !occupied[(start + i) % buckets]).unwrap_or(0);
+            occupied[(start + probes) % buckets] = true;
+            if probe_lengths.len() <= probes {
+                probe_lengths.resize(probes + 1, 0);
+            }
+            probe_lengths[probes] += 1;
+        }
+        SimulatedStats {
+            buckets,
+            occupied_home_buckets: home.iter().filter(|&&home| home).count(),
+            probe_lengths,
+        }
+    }
+
+    /// The number of buckets in the simulated table.
+    #[inline]
+    pub fn buckets(&self) -> usize {
+        self.buckets
+    }
+
+    /// The number of buckets which are the home bucket of at least one key. With a perfect
+    /// hasher, this is the map’s `len`.
+    #[inline]
+    pub fn occupied_home_buckets(&self) -> usize {
+        self.occupied_home_buckets
+    }
+
+    /// A histogram of probe lengths: element `i` is the number of keys found `i` buckets away
+    /// from their home bucket. With a perfect hasher and a sparse table, this is just `[len]`.
+    #[inline]
+    pub fn probe_lengths(&self) -> &[usize] {
+        &self.probe_lengths
+    }
+
+    /// The longest probe length of any key.
+    #[inline]
+    pub fn max_probe_length(&self) -> usize {
+        self.probe_lengths.len().saturating_sub(1)
+    }
+}
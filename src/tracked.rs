@@ -0,0 +1,244 @@
+//! `TrackedMap`, which keeps a logical clock and records when each entry was last used and changed.
+
+macro_rules! tracked_map {
+    ($example_use:literal) => {
+        /// A value in a [`TrackedMap`], with its bookkeeping.
+        #[derive(Debug)]
+        struct TrackedSlot<A: ?Sized> {
+            accessed: core::cell::Cell<u64>,
+            changed: u64,
+            value: Box<A>,
+        }
+
+        /// A collection like [`Map`], but recording when each entry was last accessed and last
+        /// changed, so that stale entries can be pruned and changed entries found.
+        ///
+        /// Time here is a logical clock, [`tick`](TrackedMap::tick), which advances by one on each
+        /// access (`get`, `get_mut` or `insert`) and stamps the entry accessed. To drop everything
+        /// not touched in some period, take the tick at the start of it and later pass that to
+        /// [`prune_unused`](TrackedMap::prune_unused).
+        ///
+        /// `get_mut` and `insert` also count as changes (whether or not `get_mut` is actually used
+        /// to change anything), so a system can remember the tick when it last ran and next time
+        /// process only what [`changed_since`](TrackedMap::changed_since) then.
+        ///
+        /// Because `get` takes `&self` and still records the access, this type is not `Sync`.
+        ///
+        /// ```rust
+        #[doc = $example_use]
+        /// let mut data = TrackedMap::<dyn std::any::Any>::new();
+        /// data.insert(1u8);
+        /// data.insert(2u16);
+        /// let mark = data.tick();
+        /// assert_eq!(data.get::<u8>(), Some(&1));
+        /// assert_eq!(data.prune_unused(mark), 1);
+        /// assert!(data.contains::<u8>());
+        /// assert!(!data.contains::<u16>());
+        /// ```
+        #[derive(Debug)]
+        pub struct TrackedMap<A: ?Sized + Downcast = dyn Any> {
+            raw: HashMap<TypeId, TrackedSlot<A>, BuildHasherDefault<TypeIdHasher>>,
+            clock: core::cell::Cell<u64>,
+        }
+
+        impl<A: ?Sized + Downcast> Default for TrackedMap<A> {
+            #[inline]
+            fn default() -> TrackedMap<A> {
+                TrackedMap::new()
+            }
+        }
+
+        /// Track an existing map, treating all its entries as accessed and changed at tick zero.
+        impl<A: ?Sized + Downcast> From<Map<A>> for TrackedMap<A> {
+            fn from(map: Map<A>) -> TrackedMap<A> {
+                TrackedMap {
+                    raw: map.into_raw().into_iter()
+                        .map(|(type_id, value)| (type_id, TrackedSlot {
+                            accessed: core::cell::Cell::new(0),
+                            changed: 0,
+                            value,
+                        }))
+                        .collect(),
+                    clock: core::cell::Cell::new(0),
+                }
+            }
+        }
+
+        impl<A: ?Sized + Downcast> TrackedMap<A> {
+            /// Create an empty collection, with the clock at zero.
+            #[inline]
+            pub fn new() -> TrackedMap<A> {
+                TrackedMap {
+                    raw: HashMap::with_hasher(Default::default()),
+                    clock: core::cell::Cell::new(0),
+                }
+            }
+
+            /// Returns the number of items in the collection.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.raw.len()
+            }
+
+            /// Returns true if there are no items in the collection.
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.raw.is_empty()
+            }
+
+            /// Removes all items from the collection. Keeps the allocated memory for reuse.
+            ///
+            /// The clock is not reset.
+            #[inline]
+            pub fn clear(&mut self) {
+                self.raw.clear()
+            }
+
+            /// Returns the current tick of the clock: the tick of the most recent access.
+            #[inline]
+            pub fn tick(&self) -> u64 {
+                self.clock.get()
+            }
+
+            /// Advance the clock, returning the new tick.
+            #[inline]
+            fn advance(&self) -> u64 {
+                let tick = self.clock.get() + 1;
+                self.clock.set(tick);
+                tick
+            }
+
+            /// Returns a reference to the value stored in the collection for the type `T`,
+            /// if it exists, recording the access.
+            #[inline]
+            pub fn get<T: IntoBox<A>>(&self) -> Option<&T> {
+                self.raw.get(&TypeId::of::<T>()).map(|slot| {
+                    slot.accessed.set(self.advance());
+                    unsafe { slot.value.downcast_ref_unchecked::<T>() }
+                })
+            }
+
+            /// Returns a mutable reference to the value stored in the collection for the type `T`,
+            /// if it exists, recording the access and change.
+            #[inline]
+            pub fn get_mut<T: IntoBox<A>>(&mut self) -> Option<&mut T> {
+                let clock = &self.clock;
+                self.raw.get_mut(&TypeId::of::<T>()).map(|slot| {
+                    clock.set(clock.get() + 1);
+                    slot.accessed.set(clock.get());
+                    slot.changed = clock.get();
+                    unsafe { slot.value.downcast_mut_unchecked::<T>() }
+                })
+            }
+
+            /// Sets the value stored in the collection for the type `T`, recording the access and
+            /// change.
+            /// If the collection already had a value of type `T`, that value is returned.
+            /// Otherwise, `None` is returned.
+            #[inline]
+            pub fn insert<T: IntoBox<A>>(&mut self, value: T) -> Option<T> {
+                let tick = self.advance();
+                let slot = TrackedSlot {
+                    accessed: core::cell::Cell::new(tick),
+                    changed: tick,
+                    value: value.into_box(),
+                };
+                self.raw.insert(TypeId::of::<T>(), slot)
+                    .map(|slot| unsafe { *slot.value.downcast_unchecked::<T>() })
+            }
+
+            /// Removes the `T` value from the collection,
+            /// returning it if there was one or `None` if there was not.
+            #[inline]
+            pub fn remove<T: IntoBox<A>>(&mut self) -> Option<T> {
+                self.raw.remove(&TypeId::of::<T>())
+                    .map(|slot| unsafe { *slot.value.downcast_unchecked::<T>() })
+            }
+
+            /// Returns true if the collection contains a value of type `T`.
+            ///
+            /// This does not count as an access.
+            #[inline]
+            pub fn contains<T: IntoBox<A>>(&self) -> bool {
+                self.raw.contains_key(&TypeId::of::<T>())
+            }
+
+            /// Returns the tick at which the value for the type `T` was last accessed,
+            /// if it exists. This does not itself count as an access.
+            #[inline]
+            pub fn last_access<T: IntoBox<A>>(&self) -> Option<u64> {
+                self.raw.get(&TypeId::of::<T>()).map(|slot| slot.accessed.get())
+            }
+
+            /// Returns the tick at which the value for the type `T` was last changed,
+            /// if it exists. This does not count as an access.
+            #[inline]
+            pub fn last_change<T: IntoBox<A>>(&self) -> Option<u64> {
+                self.raw.get(&TypeId::of::<T>()).map(|slot| slot.changed)
+            }
+
+            /// An iterator visiting all entries changed since the tick `since`, in arbitrary
+            /// order. This does not count as an access.
+            ///
+            /// (Removals aren’t reported, as there’s nothing left to visit.)
+            #[inline]
+            pub fn changed_since(&self, since: u64) -> impl Iterator<Item = (TypeId, &A)> {
+                self.raw.iter()
+                    .filter(move |&(_, slot)| slot.changed > since)
+                    .map(|(&type_id, slot)| (type_id, &*slot.value))
+            }
+
+            /// Returns a reference to the memoized value of type `T`, first computing it with `f`
+            /// (which is given the collection, to read the dependencies from) if it is missing or
+            /// any of the types `deps` changed after it did.
+            ///
+            /// Inserting a dependency that was missing counts as a change; removing one doesn’t.
+            ///
+            /// ```rust
+            #[doc = $example_use]
+            /// use std::any::TypeId;
+            /// struct Celsius(f64);
+            /// struct Fahrenheit(f64);
+            ///
+            /// let mut data = TrackedMap::<dyn std::any::Any>::new();
+            /// data.insert(Celsius(100.0));
+            /// let to_f = |data: &TrackedMap| Fahrenheit(data.get::<Celsius>().unwrap().0 * 1.8 + 32.0);
+            /// let deps = [TypeId::of::<Celsius>()];
+            /// assert_eq!(data.get_or_compute(&deps, to_f).0, 212.0);
+            /// data.get_mut::<Celsius>().unwrap().0 = 0.0;
+            /// assert_eq!(data.get_or_compute(&deps, to_f).0, 32.0);
+            /// ```
+            pub fn get_or_compute<T, F>(&mut self, deps: &[TypeId], f: F) -> &T
+            where
+                T: IntoBox<A>,
+                F: FnOnce(&TrackedMap<A>) -> T,
+            {
+                let fresh = self.raw.get(&TypeId::of::<T>()).map_or(false, |slot| {
+                    deps.iter().all(|dep| self.raw.get(dep).map_or(true, |dep| dep.changed <= slot.changed))
+                });
+                if !fresh {
+                    let value = f(self);
+                    let _ = self.insert(value);
+                }
+                let slot = &self.raw[&TypeId::of::<T>()];
+                slot.accessed.set(self.advance());
+                unsafe { slot.value.downcast_ref_unchecked::<T>() }
+            }
+
+            /// Removes all the values not accessed since the tick `older_than`,
+            /// returning how many were removed.
+            pub fn prune_unused(&mut self, older_than: u64) -> usize {
+                let len = self.raw.len();
+                self.raw.retain(|_, slot| slot.accessed.get() > older_than);
+                len - self.raw.len()
+            }
+
+            /// Convert this into a plain [`Map`], discarding the access records.
+            pub fn into_map(self) -> Map<A> {
+                let raw = self.raw.into_iter().map(|(type_id, slot)| (type_id, slot.value)).collect();
+                // SAFETY: the keys were all checked on the way in.
+                unsafe { Map::from_raw(raw) }
+            }
+        }
+    };
+}
@@ -0,0 +1,175 @@
+//! Machinery for the methods that operate on a tuple of types at once, like `Map::entry_many`
+//! and `Map::insert_all`.
+//!
+//! The traits themselves are defined in `everything!`, since they depend on the flavour of
+//! `RawMap`; the macros here just stamp out the implementations for each size of tuple.
+
+use crate::TypeInfo;
+
+/// Invokes `$m!` with the type parameters for each size of tuple from one to twelve.
+macro_rules! for_each_tuple {
+    ($m:ident) => {
+        $m!(T0);
+        $m!(T0 T1);
+        $m!(T0 T1 T2);
+        $m!(T0 T1 T2 T3);
+        $m!(T0 T1 T2 T3 T4);
+        $m!(T0 T1 T2 T3 T4 T5);
+        $m!(T0 T1 T2 T3 T4 T5 T6);
+        $m!(T0 T1 T2 T3 T4 T5 T6 T7);
+        $m!(T0 T1 T2 T3 T4 T5 T6 T7 T8);
+        $m!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9);
+        $m!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9 T10);
+        $m!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11);
+    }
+}
+
+/// Panics if any two of the types are the same, naming the one repeated.
+///
+/// Tuples are small, so quadratic is fine, and it saves allocating.
+#[inline]
+#[cfg_attr(feature = "track-caller", track_caller)]
+pub(crate) fn assert_distinct(types: &[TypeInfo], method: &str) {
+    for (i, a) in types.iter().enumerate() {
+        if types[i + 1..].contains(a) {
+            panic!("{} requires each type in the tuple to be distinct, but {} is repeated", method, a);
+        }
+    }
+}
+
+// The type parameters double as variable names when destructuring, hence the allow(non_snake_case).
+
+macro_rules! impl_entry_many {
+    ($($T:ident)+) => {
+        impl<A: ?Sized + Downcast, $($T: IntoBox<A>),+> EntryMany<A> for ($($T,)+) {
+            type Slots = ($(tuple_slot!($T, RawMap<A>),)+);
+
+            #[allow(non_snake_case)]
+            #[cfg_attr(feature = "track-caller", track_caller)]
+            fn take(map: &mut Map<A>) -> Self::Slots {
+                crate::tuple::assert_distinct(&[$(crate::TypeInfo::of::<$T>()),+], "Map::entry_many");
+                // Each type gets a map of its own, since an `Entry` borrows a whole map and we
+                // want them all alive at once. An empty map doesn’t allocate, so this only costs a
+                // small table for each slot that is or becomes filled, freed again by `restore`.
+                $(
+                    map.record_name::<$T>();
+                    let mut $T = RawMap::default();
+                    if let Some(value) = map.raw.remove(&TypeId::of::<$T>()) {
+                        let _ = $T.insert(TypeId::of::<$T>(), value);
+                    }
+                )+
+                ($($T,)+)
+            }
+
+            #[allow(non_snake_case)]
+            fn restore(raw: &mut RawMap<A>, slots: &mut Self::Slots) {
+                let ($(ref mut $T,)+) = *slots;
+                $(raw.extend($T.drain());)+
+            }
+        }
+
+        impl<'b, A: ?Sized + Downcast + 'b, $($T: IntoBox<A>),+> EntryManyRef<'b, A> for ($($T,)+) {
+            type Entries = ($(Entry<'b, A, $T>,)+);
+
+            #[allow(non_snake_case)]
+            fn entries(slots: &'b mut Self::Slots) -> Self::Entries {
+                let ($(ref mut $T,)+) = *slots;
+                ($(Entry::from_raw($T.entry(TypeId::of::<$T>())),)+)
+            }
+        }
+    }
+}
+
+macro_rules! impl_type_tuple {
+    ($($T:ident)+) => {
+        impl<A: ?Sized + Downcast, $($T: IntoBox<A>),+> TypeTuple<A> for ($($T,)+) {
+            type Options = ($(Option<$T>,)+);
+
+            const LEN: usize = 0 $(+ tuple_count!($T))+;
+
+            #[allow(non_snake_case)]
+            fn insert_all(self, map: &mut Map<A>) -> Self::Options {
+                let ($($T,)+) = self;
+                map.reserve(Self::LEN);
+                ($(map.insert($T),)+)
+            }
+
+            #[inline]
+            fn remove_all(map: &mut Map<A>) -> Self::Options {
+                ($(map.remove::<$T>(),)+)
+            }
+        }
+
+        impl<'b, A: ?Sized + Downcast + 'b, $($T: IntoBox<A>),+> TypeTupleRef<'b, A> for ($($T,)+) {
+            type Refs = ($(Option<&'b $T>,)+);
+            type Required = ($(&'b $T,)+);
+
+            #[inline]
+            fn get_all(map: &'b Map<A>) -> Self::Refs {
+                ($(map.get::<$T>(),)+)
+            }
+
+            #[inline]
+            fn get_all_strict(map: &'b Map<A>) -> Option<Self::Required> {
+                Some(($(map.get::<$T>()?,)+))
+            }
+        }
+    }
+}
+
+macro_rules! impl_from_any_map {
+    ($($T:ident)+) => {
+        impl<'a, A: ?Sized + Downcast, $($T: FromAnyMap<'a, A>),+> FromAnyMap<'a, A> for ($($T,)+) {
+            #[inline]
+            fn from_map(map: &'a Map<A>) -> Result<Self, crate::MissingTypeError> {
+                Ok(($($T::from_map(map)?,)+))
+            }
+        }
+    }
+}
+
+/// Expands to `$ty`, ignoring `$T`; for repeating a type once per tuple member.
+macro_rules! tuple_slot {
+    ($T:ident, $ty:ty) => { $ty }
+}
+
+/// Expands to `1`, ignoring `$T`; for counting tuple members.
+macro_rules! tuple_count {
+    ($T:ident) => { 1 }
+}
+
+/// Implemented for tuples containing the type `T`, saying where it is with the index marker `I`.
+///
+/// This is what [`SubsetView`](crate::SubsetView) uses to restrict access to the types it lists.
+/// `I` is always inferred; it exists only so that the implementations for each position in the
+/// tuple don’t overlap. Code generic over this trait will normally take `I` as a type parameter
+/// too, e.g. `fn f<S: HasType<Config, I>, I>(…)`.
+///
+/// This is implemented for tuples of up to twelve types.
+pub trait HasType<T, I> { }
+
+macro_rules! index_markers {
+    ($($I:ident)+) => {
+        $(
+            #[doc(hidden)]
+            #[allow(missing_debug_implementations)]
+            pub enum $I { }
+        )+
+    }
+}
+
+index_markers!(Index0 Index1 Index2 Index3 Index4 Index5 Index6 Index7 Index8 Index9 Index10 Index11);
+
+macro_rules! impl_has_type {
+    ($($T:ident)+) => {
+        impl_has_type!(@step [] [$($T)+]
+            [Index0 Index1 Index2 Index3 Index4 Index5 Index6 Index7 Index8 Index9 Index10 Index11]);
+    };
+    (@step [$($before:ident)*] [$T:ident $($after:ident)*] [$I:ident $($Is:ident)*]) => {
+        impl<$($before,)* $T, $($after),*> HasType<$T, $I> for ($($before,)* $T, $($after,)*) { }
+        impl_has_type!(@step [$($before)* $T] [$($after)*] [$($Is)*]);
+    };
+    (@step [$($before:ident)*] [] [$($Is:ident)*]) => { };
+}
+
+for_each_tuple!(impl_has_type);